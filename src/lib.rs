@@ -1,23 +1,39 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
-use ringtone::{PlayedTone, Ringtone};
 use wasm_bindgen::prelude::*;
 
 mod ringtone;
 mod sound;
 mod synth;
 
-fn err_to_string(e: Box<dyn std::error::Error>) -> String {
+pub use ringtone::{
+    AbcError, AbcWarning, AbcWarningKind, Adjustment, BuildError, CHeaderOptions, CollectionEntry,
+    ComposerError, DeviceProfile, Duration, ImelodyError, Lint, LintCode, LintTarget, MidiError,
+    MidiImportOptions, MidiLoss, MmlError, Note, Octave, OtaError, ParseOptions, ParseWarning,
+    Pitch, PlayedTone, Ringtone, RingtoneBuilder, RingtoneCollection, RtttlError, Section,
+    Settings, Style, Tempo, Voice, WarningKind, LOOP_FOREVER, NOKIA_TEMPOS, TICKS_PER_QUARTER,
+};
+pub use synth::{
+    ADSROptions, Biquad, BiquadType, Coefficients, FilterChain, Patch, PatchError, Waveform,
+};
+
+fn err_to_string(e: RtttlError) -> String {
     e.to_string()
 }
 
 #[wasm_bindgen]
 pub fn play(input: String) -> Result<(), String> {
-    // Parse, and panic if the parse fails.
+    // Parse, and report the location of the problem if the parse fails.
     let ringtone = Ringtone::parse(input.trim()).map_err(err_to_string)?;
 
+    play_ringtone(&ringtone, &Patch::default());
+
+    Ok(())
+}
+
+fn play_ringtone(ringtone: &Ringtone, patch: &Patch) {
     // Set up the output device.
-    let output = sound::output();
+    let output = sound::output(patch.clone());
 
     // WebAudio has an initial "squeak" to the sound when it starts playing instantly. 10ms of
     // silence seems to be enough to fix it.
@@ -33,8 +49,6 @@ pub fn play(input: String) -> Result<(), String> {
 
     // Wait for playback to complete before exiting.
     output.finish();
-
-    Ok(())
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -44,12 +58,181 @@ pub fn run() {
 
 #[cfg(not(target_arch = "wasm32"))]
 pub fn run() {
-    // Consume all of stdin to a string.
+    // Consume all of stdin. It's usually RTTTL text, but MIDI files are binary.
 
-    let mut input = String::new();
+    let mut input = Vec::new();
     std::io::stdin()
-        .read_to_string(&mut input)
+        .read_to_end(&mut input)
         .expect("stdin read error");
 
-    play(input).expect("play error");
+    let args: Vec<String> = std::env::args().collect();
+
+    // With `--from <format>`, import a ringtone from another format and write it out as RTTTL,
+    // reporting anything that was lost.
+    if let Some(i) = args.iter().position(|arg| arg == "--from") {
+        let ringtone = match args.get(i + 1).map(String::as_str) {
+            Some("abc") => {
+                let input = std::str::from_utf8(&input).unwrap_or_else(|e| exit_with(e));
+                let (ringtone, warnings) =
+                    Ringtone::from_abc(input).unwrap_or_else(|e| exit_with(e));
+
+                for warning in warnings {
+                    eprintln!("{warning}");
+                }
+
+                ringtone
+            }
+            Some("composer") => {
+                let input = std::str::from_utf8(&input).unwrap_or_else(|e| exit_with(e));
+                Ringtone::from_composer(input).unwrap_or_else(|e| exit_with(e))
+            }
+            Some("midi") => {
+                let (ringtone, losses) = Ringtone::from_midi(&input, &MidiImportOptions::default())
+                    .unwrap_or_else(|e| exit_with(e));
+
+                for loss in losses {
+                    eprintln!("{loss}");
+                }
+
+                ringtone
+            }
+            Some("mml") => {
+                let input = std::str::from_utf8(&input).unwrap_or_else(|e| exit_with(e));
+                Ringtone::from_mml(input).unwrap_or_else(|e| exit_with(e))
+            }
+            Some("ota") => Ringtone::from_ota(&input).unwrap_or_else(|e| exit_with(e)),
+            Some("imelody") => {
+                let input = std::str::from_utf8(&input).unwrap_or_else(|e| exit_with(e));
+                Ringtone::from_imelody(input).unwrap_or_else(|e| exit_with(e))
+            }
+            format => exit_with(format!(
+                "unknown format {format:?}, expected one of: abc, composer, imelody, midi, mml, ota"
+            )),
+        };
+
+        println!("{ringtone}");
+
+        return;
+    }
+
+    let input = String::from_utf8(input).unwrap_or_else(|e| exit_with(e));
+
+    // With `--convert <format>`, write the ringtone to stdout in another format instead of
+    // playing it.
+    if let Some(i) = args.iter().position(|arg| arg == "--convert") {
+        let ringtone = Ringtone::parse(input.trim()).unwrap_or_else(|e| exit_with(e));
+
+        let output = match args.get(i + 1).map(String::as_str) {
+            Some("abc") => ringtone.to_abc().into_bytes(),
+            Some("c") => {
+                let options = CHeaderOptions {
+                    progmem: args.iter().any(|arg| arg == "--progmem"),
+                    ..CHeaderOptions::default()
+                };
+                ringtone.to_c_header(&options).into_bytes()
+            }
+            Some("composer") => ringtone
+                .to_composer()
+                .unwrap_or_else(|e| exit_with(e))
+                .into_bytes(),
+            Some("lilypond") => ringtone.to_lilypond().into_bytes(),
+            Some("midi") => ringtone.to_midi().unwrap_or_else(|e| exit_with(e)),
+            Some("ota") => ringtone.to_ota().unwrap_or_else(|e| exit_with(e)),
+            Some("imelody") => ringtone
+                .to_imelody()
+                .unwrap_or_else(|e| exit_with(e))
+                .into_bytes(),
+            Some("mml") => ringtone
+                .to_mml()
+                .unwrap_or_else(|e| exit_with(e))
+                .into_bytes(),
+            Some("musicxml") => ringtone.to_musicxml().into_bytes(),
+            Some("rtttl") => ringtone.to_rtttl().into_bytes(),
+            format => exit_with(format!(
+                "unknown format {format:?}, expected one of: abc, c, composer, imelody, lilypond, midi, \
+                 mml, musicxml, ota, rtttl"
+            )),
+        };
+
+        std::io::stdout()
+            .write_all(&output)
+            .expect("stdout write error");
+
+        return;
+    }
+
+    // With `--patch <file>`, play the notes with an instrument from a TOML file instead of the
+    // phone sound.
+    let mut patch = match args.iter().position(|arg| arg == "--patch") {
+        Some(i) => {
+            let path = args
+                .get(i + 1)
+                .unwrap_or_else(|| exit_with("--patch needs a file"));
+            Patch::load(path).unwrap_or_else(|e| exit_with(e))
+        }
+        None => Patch::default(),
+    };
+
+    // With `--waveform <name>`, play the notes with another waveform instead of the patch's.
+    if let Some(i) = args.iter().position(|arg| arg == "--waveform") {
+        patch.waveform = args
+            .get(i + 1)
+            .map_or("", String::as_str)
+            .parse()
+            .unwrap_or_else(|e| exit_with(e));
+    }
+
+    // With `--lint`, check the ringtone against the Nokia profile instead of playing it. Any lints
+    // make the exit status non-zero, so this can gate ringtone submissions.
+    if args.iter().any(|arg| arg == "--lint") {
+        let lints = Ringtone::lint_source(input.trim(), &DeviceProfile::nokia())
+            .unwrap_or_else(|e| exit_with(e));
+
+        for lint in &lints {
+            println!("{lint}");
+        }
+
+        if !lints.is_empty() {
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    // With `--collection`, the input has one ringtone per line. Play each in turn, skipping the
+    // lines that don't parse.
+    if args.iter().any(|arg| arg == "--collection") {
+        for entry in Ringtone::parse_many(&input) {
+            match entry.result() {
+                Ok(ringtone) => play_ringtone(ringtone, &patch),
+                Err(e) => eprintln!("skipping line {}: {e}", entry.line()),
+            }
+        }
+
+        return;
+    }
+
+    // With `--fit`, preview the ringtone as a Nokia phone would play it, reporting what had to
+    // change.
+    if args.iter().any(|arg| arg == "--fit") {
+        let mut ringtone = Ringtone::parse(input.trim()).unwrap_or_else(|e| exit_with(e));
+
+        for adjustment in ringtone.fit(&DeviceProfile::nokia()) {
+            eprintln!("{adjustment}");
+        }
+
+        play_ringtone(&ringtone, &patch);
+
+        return;
+    }
+
+    let ringtone = Ringtone::parse(input.trim()).unwrap_or_else(|e| exit_with(e));
+    play_ringtone(&ringtone, &patch);
+}
+
+/// Reports an error from the command line and exits.
+#[cfg(not(target_arch = "wasm32"))]
+fn exit_with(e: impl std::fmt::Display) -> ! {
+    eprintln!("{e}");
+    std::process::exit(2)
 }
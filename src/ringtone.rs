@@ -1,35 +1,356 @@
+mod abc;
+mod builder;
+mod c_header;
+mod collection;
+mod composer;
+mod device;
+mod error;
 mod frequency;
+mod imelody;
 mod iterator;
+mod lenient;
+mod lilypond;
+mod lint;
+mod midi;
+mod mml;
+mod musicxml;
+mod ota;
 mod parser;
+mod serializer;
 
+pub use abc::{AbcWarning, AbcWarningKind};
+pub use builder::RingtoneBuilder;
+pub use c_header::CHeaderOptions;
+pub use collection::{CollectionEntry, RingtoneCollection};
+pub use device::{Adjustment, DeviceProfile, NOKIA_TEMPOS};
+pub use error::{
+    AbcError, BuildError, ComposerError, ImelodyError, MidiError, MmlError, OtaError, ParseWarning,
+    RtttlError, Section, WarningKind,
+};
 pub use iterator::PlayedTone;
+pub use lint::{Lint, LintCode, LintTarget};
+pub use midi::{MidiImportOptions, MidiLoss, Voice};
+pub use parser::ParseOptions;
+
+/// The resolution of note timing, in ticks per quarter note. This is fine enough that every
+/// duration, dotted or triplet, is a whole number of ticks, so timing is exact until it's finally
+/// converted to seconds.
+pub const TICKS_PER_QUARTER: u32 = 96;
+
+/// The largest loop count, which in RTX and Nokia Smart Messaging means the melody repeats
+/// forever. When the melody is played or written out in full, it plays once.
+pub const LOOP_FOREVER: u8 = 15;
+
+/// The name given to ringtones which don't have one.
+const UNTITLED: &str = "Untitled";
 
 /// A ringtone is a sequence of notes and silences.
 ///
 /// This is the top level structure containing the parsed ringtone.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Ringtone {
-    #[allow(dead_code)]
     name: String,
     settings: Settings,
     notes: Vec<Note>,
 }
 
 impl Ringtone {
-    pub fn parse(input: &str) -> Result<Ringtone, Box<dyn std::error::Error>> {
-        parser::parse_input(input)
+    pub fn parse(input: &str) -> Result<Ringtone, RtttlError> {
+        parser::parse_input(input, &ParseOptions::default())
+    }
+
+    /// Parses with stricter or looser rules than the default. See `ParseOptions`.
+    pub fn parse_with(input: &str, options: &ParseOptions) -> Result<Ringtone, RtttlError> {
+        Ringtone::parse_with_warnings(input, options).map(|(ringtone, _warnings)| ringtone)
+    }
+
+    /// Parses with the given options, also returning the problems that were worked around in
+    /// lenient mode. In strict mode there are never any warnings.
+    pub fn parse_with_warnings(
+        input: &str,
+        options: &ParseOptions,
+    ) -> Result<(Ringtone, Vec<ParseWarning>), RtttlError> {
+        if options.lenient {
+            lenient::parse_input(input, options)
+        } else {
+            parser::parse_input(input, options).map(|ringtone| (ringtone, Vec::new()))
+        }
+    }
+
+    /// Parses a file with one ringtone per line. See `RingtoneCollection`.
+    pub fn parse_many(input: &str) -> RingtoneCollection {
+        RingtoneCollection::parse(input)
+    }
+
+    /// Parses the input and checks it for suspicious constructs and anything the device can't
+    /// play. Unlike `lint`, this can also find problems in how the text is written, and every lint
+    /// has a line and column.
+    pub fn lint_source(input: &str, profile: &DeviceProfile) -> Result<Vec<Lint>, RtttlError> {
+        lint::lint_source(input, profile)
+    }
+
+    /// Imports a melody from a Standard MIDI File, also returning what couldn't be carried over.
+    /// See `MidiImportOptions` for choosing what to import.
+    pub fn from_midi(
+        data: &[u8],
+        options: &MidiImportOptions,
+    ) -> Result<(Ringtone, Vec<MidiLoss>), MidiError> {
+        midi::read(data, options)
+    }
+
+    /// Reads a Music Macro Language string, as played by the BASIC `PLAY` statement and many
+    /// chiptune tools. The string can be wrapped in `PLAY "..."`.
+    pub fn from_mml(input: &str) -> Result<Ringtone, MmlError> {
+        mml::read(input)
+    }
+
+    /// Decodes a Nokia Smart Messaging ringing tone, as sent to phones by SMS (`.ott` files).
+    pub fn from_ota(data: &[u8]) -> Result<Ringtone, OtaError> {
+        ota::read(data)
+    }
+
+    /// Imports the first tune in an ABC notation file, also returning warnings for anything that
+    /// couldn't be carried over, such as chords and grace notes.
+    pub fn from_abc(input: &str) -> Result<(Ringtone, Vec<AbcWarning>), AbcError> {
+        abc::read(input)
+    }
+
+    /// Reads the keys pressed to enter a melody in a Nokia phone's Composer, e.g. `1 2#8 5*`. See
+    /// `Ringtone::to_composer` for how the keys work.
+    pub fn from_composer(input: &str) -> Result<Ringtone, ComposerError> {
+        composer::read(input)
+    }
+
+    /// Reads an iMelody (`.imy`) file, as used by Sony Ericsson and other phones.
+    pub fn from_imelody(input: &str) -> Result<Ringtone, ImelodyError> {
+        imelody::read(input)
+    }
+
+    /// Starts building a ringtone in code. See `RingtoneBuilder`.
+    pub fn builder(name: impl Into<String>) -> RingtoneBuilder {
+        RingtoneBuilder::new(name)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+
+    /// Mutable access to the settings, for editing the defaults in place.
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.settings
+    }
+
+    /// Mutable access to the notes, for editing them in place. Adding or removing notes goes
+    /// through `RingtoneBuilder`, which makes sure the ringtone is never left empty.
+    pub fn notes_mut(&mut self) -> &mut [Note] {
+        &mut self.notes
+    }
+
+    /// Checks the ringtone for redundant overrides, non-standard durations and anything the device
+    /// can't play. The lints are ordered by where they apply: the name, the settings, then each
+    /// note in turn.
+    pub fn lint(&self, profile: &DeviceProfile) -> Vec<Lint> {
+        lint::lint(self, profile)
+    }
+
+    /// Changes the ringtone so that the device can play it as written: shortening the name,
+    /// snapping the tempo, dropping extra notes and transposing into the device's octaves. Returns
+    /// every change that was made, which is empty if the ringtone already fits.
+    pub fn fit(&mut self, profile: &DeviceProfile) -> Vec<Adjustment> {
+        device::fit(self, profile)
     }
 
+    /// Changes the default duration and octave to the most common ones in the melody, which gives
+    /// the shortest RTTTL. The notes sound the same, but only mention a duration or octave where
+    /// it differs from the new defaults. Ties go to the longer duration and the higher octave.
+    pub fn compact(&mut self) {
+        let settings = self.settings;
+        let duration = |note: &Note| note.duration.unwrap_or(settings.duration);
+        let octave = |note: &Note| note.pitch.map(|_| note.octave.unwrap_or(settings.octave));
+
+        let default_duration = most_common(self.notes.iter().map(duration), Duration::ticks)
+            .unwrap_or(settings.duration);
+        let default_octave =
+            most_common(self.notes.iter().filter_map(octave), |o| o.number() as u32)
+                .unwrap_or(settings.octave);
+
+        for note in &mut self.notes {
+            let (note_duration, note_octave) = (duration(note), octave(note));
+            note.duration = Some(note_duration).filter(|&d| d != default_duration);
+            note.octave = note_octave.filter(|&o| o != default_octave);
+        }
+
+        self.settings.duration = default_duration;
+        self.settings.octave = default_octave;
+    }
+
+    /// Writes the ringtone back out as canonical RTTTL text. See the `Display` implementation.
+    pub fn to_rtttl(&self) -> String {
+        self.to_string()
+    }
+
+    /// Writes the ringtone as a Standard MIDI File (format 0), for use in other music software.
+    /// MIDI keys go up to G9, so the top few notes of octave 9 can't be written.
+    pub fn to_midi(&self) -> Result<Vec<u8>, MidiError> {
+        midi::write(self)
+    }
+
+    /// Writes the ringtone as a tune in ABC notation.
+    pub fn to_abc(&self) -> String {
+        abc::write(self)
+    }
+
+    /// Writes the ringtone as a C header for Arduino-compatible boards, with arrays of frequencies
+    /// and durations and a function that plays them with `tone()`. See `CHeaderOptions`.
+    pub fn to_c_header(&self, options: &CHeaderOptions) -> String {
+        c_header::write(self, options)
+    }
+
+    /// Writes the ringtone as the keys to press to enter it in a Nokia phone's Composer, as on the
+    /// 3310. Keys 1 to 7 enter the notes C to B and 0 a rest, and each new note has the duration
+    /// and octave of the one before. The keys after a note change it: `#` makes it sharp, `*`
+    /// moves it up an octave (round from octave 3 to 1), and 8 and 9 make it shorter and longer.
+    /// The Composer has three octaves, RTTTL's 4 to 6, and no dotted notes or triplets.
+    pub fn to_composer(&self) -> Result<String, ComposerError> {
+        composer::write(self)
+    }
+
+    /// Writes the ringtone as a LilyPond (`.ly`) score, in the major key that suits it best.
+    pub fn to_lilypond(&self) -> String {
+        lilypond::write(self)
+    }
+
+    /// Writes the ringtone as a Music Macro Language string for the BASIC `PLAY` statement.
+    /// MML's tempos go from 32 to 255, and its lengths down to a 64th note.
+    pub fn to_mml(&self) -> Result<String, MmlError> {
+        mml::write(self)
+    }
+
+    /// Writes the ringtone as a MusicXML score in 4/4 time, for notation software such as
+    /// MuseScore.
+    pub fn to_musicxml(&self) -> String {
+        musicxml::write(self)
+    }
+
+    /// Encodes the ringtone as a Nokia Smart Messaging ringing tone (`.ott`). Only ringtones that a
+    /// Nokia phone could play can be encoded, see `Ringtone::fit`.
+    pub fn to_ota(&self) -> Result<Vec<u8>, OtaError> {
+        ota::write(self)
+    }
+
+    /// Writes the ringtone as an iMelody (`.imy`) file. iMelody has no 64th notes or dotted
+    /// triplets, and no octave below RTTTL's 1.
+    pub fn to_imelody(&self) -> Result<String, ImelodyError> {
+        imelody::write(self)
+    }
+
+    /// The tones to play, with the defaults applied, the melody repeated by the loop count, and
+    /// gaps between notes for the style.
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = PlayedTone> + 'a {
         iterator::iter(self)
     }
 }
 
+/// The value that appears most often, with ties going to the one with the highest rank.
+fn most_common<T: Copy + PartialEq>(
+    values: impl Iterator<Item = T>,
+    rank: fn(T) -> u32,
+) -> Option<T> {
+    let mut counts: Vec<(T, usize)> = Vec::new();
+    for value in values {
+        match counts.iter_mut().find(|(v, _)| *v == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|&(value, count)| (count, rank(value)))
+        .map(|(value, _)| value)
+}
+
 /// The default settings for a ringtone, inherited by any notes that don't override them.
-#[derive(Debug, PartialEq, Eq)]
-struct Settings {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Settings {
     duration: Duration,
     octave: Octave,
     tempo: Tempo,
+    loops: u8,
+    style: Style,
+}
+
+impl Settings {
+    pub fn new(duration: Duration, octave: Octave, tempo: Tempo) -> Settings {
+        Settings {
+            duration,
+            octave,
+            tempo,
+            ..Settings::default()
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn octave(&self) -> Octave {
+        self.octave
+    }
+
+    pub fn tempo(&self) -> Tempo {
+        self.tempo
+    }
+
+    /// The number of times the melody repeats after it first plays, from the RTX `l=` setting, up
+    /// to `LOOP_FOREVER`.
+    pub fn loops(&self) -> u8 {
+        self.loops
+    }
+
+    /// How many times the melody plays when it's played or written out in full. A melody that
+    /// repeats forever plays once.
+    fn plays(&self) -> usize {
+        if self.loops == LOOP_FOREVER {
+            1
+        } else {
+            self.loops as usize + 1
+        }
+    }
+
+    /// The articulation between notes, from the RTX `s=` setting.
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+
+    pub fn set_octave(&mut self, octave: Octave) {
+        self.octave = octave;
+    }
+
+    pub fn set_tempo(&mut self, tempo: Tempo) {
+        self.tempo = tempo;
+    }
+
+    /// Sets the loop count, which is capped at `LOOP_FOREVER`.
+    pub fn set_loops(&mut self, loops: u8) {
+        self.loops = loops.min(LOOP_FOREVER);
+    }
+
+    pub fn set_style(&mut self, style: Style) {
+        self.style = style;
+    }
 }
 
 impl Default for Settings {
@@ -38,53 +359,250 @@ impl Default for Settings {
             duration: Duration::Quarter,
             octave: Octave::O5,
             tempo: 120,
+            loops: 0,
+            style: Style::Continuous,
+        }
+    }
+}
+
+/// How notes are articulated, as in RTX ringtones and the Nokia Smart Messaging format.
+///
+/// Plain RTTTL has no style, and its notes run into each other, so `Continuous` is the default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Style {
+    /// A short gap at the end of each note.
+    Natural,
+    /// Each note sounds for its whole duration.
+    Continuous,
+    /// Each note sounds for half of its duration.
+    Staccato,
+}
+
+impl Style {
+    /// The fraction of each note's duration that sounds, as a numerator and denominator. The rest
+    /// of the duration is silent.
+    pub fn articulation(self) -> (u32, u32) {
+        match self {
+            Style::Natural => (15, 16),
+            Style::Continuous => (1, 1),
+            Style::Staccato => (1, 2),
         }
     }
 }
 
 /// Note duration, relative to the tempo.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+///
+/// The value of each variant is the RTTTL notation for it, i.e. how many fit in a whole note. The
+/// triplet durations are an extension to RTTTL which follows the same rule, so three `12` notes
+/// take the time of one quarter note.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
-enum Duration {
+pub enum Duration {
     Whole = 1,
     Half = 2,
+    HalfTriplet = 3,
     Quarter = 4,
+    QuarterTriplet = 6,
     Eighth = 8,
+    EighthTriplet = 12,
     Sixteenth = 16,
+    SixteenthTriplet = 24,
     ThirtySecond = 32,
+    ThirtySecondTriplet = 48,
+    SixtyFourth = 64,
+    SixtyFourthTriplet = 96,
+}
+
+impl Duration {
+    /// The duration for the RTTTL number, e.g. `8` for an eighth note.
+    pub fn from_denominator(denominator: u8) -> Option<Duration> {
+        let duration = match denominator {
+            1 => Duration::Whole,
+            2 => Duration::Half,
+            3 => Duration::HalfTriplet,
+            4 => Duration::Quarter,
+            6 => Duration::QuarterTriplet,
+            8 => Duration::Eighth,
+            12 => Duration::EighthTriplet,
+            16 => Duration::Sixteenth,
+            24 => Duration::SixteenthTriplet,
+            32 => Duration::ThirtySecond,
+            48 => Duration::ThirtySecondTriplet,
+            64 => Duration::SixtyFourth,
+            96 => Duration::SixtyFourthTriplet,
+            _ => return None,
+        };
+
+        Some(duration)
+    }
+
+    /// The RTTTL number of the duration, i.e. how many fit in a whole note.
+    pub fn denominator(self) -> u8 {
+        self as u8
+    }
+
+    /// Whether this is one of the triplet durations, which take 2/3 of the time of the next
+    /// longest plain duration.
+    pub fn is_triplet(self) -> bool {
+        self.denominator().is_multiple_of(3)
+    }
+
+    /// The length of the duration in `TICKS_PER_QUARTER` units.
+    pub fn ticks(self) -> u32 {
+        TICKS_PER_QUARTER * 4 / self.denominator() as u32
+    }
 }
 
 /// Octave of the note.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum Octave {
+///
+/// The original Nokia phones only play `O4` to `O7`, but other players accept the whole range.
+/// `ParseOptions` controls which octaves the parser allows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Octave {
+    O0,
+    O1,
+    O2,
+    O3,
     O4,
     O5,
     O6,
     O7,
+    O8,
+    O9,
+}
+
+impl Octave {
+    /// The octave for the RTTTL number, if it's in the range 0-9.
+    pub fn from_number(number: u8) -> Option<Octave> {
+        let octave = match number {
+            0 => Octave::O0,
+            1 => Octave::O1,
+            2 => Octave::O2,
+            3 => Octave::O3,
+            4 => Octave::O4,
+            5 => Octave::O5,
+            6 => Octave::O6,
+            7 => Octave::O7,
+            8 => Octave::O8,
+            9 => Octave::O9,
+            _ => return None,
+        };
+
+        Some(octave)
+    }
+
+    /// The RTTTL number of the octave.
+    pub fn number(self) -> u8 {
+        self as u8
+    }
 }
 
 /// Tempo of a ringtone.
 ///
 /// In the original implementation this is more restrictive (i.e. the Nokia phones have a list of
-/// discrete tempos that they can handle), but we'll take any u16 and do our best. YMMV at the
-/// extremes.
-type Tempo = u16;
+/// discrete tempos that they can handle, see `NOKIA_TEMPOS`), but we'll take any u16 and do our
+/// best. YMMV at the extremes. Use `Ringtone::fit` to snap to the tempos of a real device.
+pub type Tempo = u16;
 
 /// A single note or silence.
 ///
 /// When the duration or octave is `None`, the default value from the ringtone settings is used. If
 /// the pitch is `None`, the note is a silence.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-struct Note {
+pub struct Note {
     duration: Option<Duration>,
     pitch: Option<Pitch>,
     octave: Option<Octave>,
     dotted: bool,
 }
 
+impl Note {
+    /// A note of the given pitch, inheriting the duration and octave from the ringtone settings.
+    pub fn new(pitch: Pitch) -> Note {
+        Note {
+            duration: None,
+            pitch: Some(pitch),
+            octave: None,
+            dotted: false,
+        }
+    }
+
+    /// A silence, inheriting the duration from the ringtone settings.
+    pub fn rest() -> Note {
+        Note {
+            duration: None,
+            pitch: None,
+            octave: None,
+            dotted: false,
+        }
+    }
+
+    pub fn with_duration(self, duration: impl Into<Option<Duration>>) -> Note {
+        Note {
+            duration: duration.into(),
+            ..self
+        }
+    }
+
+    pub fn with_pitch(self, pitch: impl Into<Option<Pitch>>) -> Note {
+        Note {
+            pitch: pitch.into(),
+            ..self
+        }
+    }
+
+    pub fn with_octave(self, octave: impl Into<Option<Octave>>) -> Note {
+        Note {
+            octave: octave.into(),
+            ..self
+        }
+    }
+
+    pub fn with_dotted(self, dotted: bool) -> Note {
+        Note { dotted, ..self }
+    }
+
+    /// The duration override for this note, or `None` to use the ringtone default.
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// The pitch of the note, or `None` if it's a silence.
+    pub fn pitch(&self) -> Option<Pitch> {
+        self.pitch
+    }
+
+    /// The octave override for this note, or `None` to use the ringtone default.
+    pub fn octave(&self) -> Option<Octave> {
+        self.octave
+    }
+
+    /// Whether the note is dotted, i.e. 1.5 times as long.
+    pub fn dotted(&self) -> bool {
+        self.dotted
+    }
+
+    /// The length of the note in `TICKS_PER_QUARTER` units, with the defaults from the settings
+    /// applied.
+    pub fn ticks(&self, settings: &Settings) -> u32 {
+        let ticks = self.duration.unwrap_or(settings.duration).ticks();
+
+        // Dotted notes are 1.5 times as long.
+        if self.dotted {
+            ticks * 3 / 2
+        } else {
+            ticks
+        }
+    }
+
+    pub fn is_rest(&self) -> bool {
+        self.pitch.is_none()
+    }
+}
+
 /// The pitch of a note within an octave.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum Pitch {
+pub enum Pitch {
     A,
     // Easier than coming up with a notation for A#, etc.
     Bb,
@@ -0,0 +1,1256 @@
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+use super::*;
+
+/// The letters of the natural notes, in ABC's order from C.
+const LETTERS: [char; 7] = ['C', 'D', 'E', 'F', 'G', 'A', 'B'];
+
+/// The semitones above C of each natural note, in the same order as `LETTERS`.
+const SEMITONES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+/// Melody lines are wrapped at this many characters.
+const LINE_LENGTH: usize = 72;
+
+/// The longest note or rest, in whole notes. Anything longer, such as a rest of thousands of
+/// bars, would be written out as an enormous ringtone.
+const LONGEST: u64 = 64;
+
+/// Something in an ABC tune that RTTTL can't express, which was dropped or approximated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AbcWarning {
+    line: usize,
+    column: usize,
+    kind: AbcWarningKind,
+}
+
+/// The kinds of ABC feature that can't be imported as they are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AbcWarningKind {
+    /// A chord, of which only the highest note was kept.
+    Chord,
+    /// A chord symbol such as `"Am"`, which was dropped.
+    ChordSymbol,
+    /// Grace notes, which were dropped.
+    GraceNotes,
+    /// A decoration such as a trill or fermata, which was dropped.
+    Decoration,
+    /// Music for another voice, which was dropped.
+    ExtraVoice,
+    /// A tempo change after the first tempo, which was ignored.
+    TempoChange,
+    /// A note length that no RTTTL duration matches, which was split into several notes.
+    SplitNote,
+    /// A note length that isn't a whole number of ticks, which was rounded.
+    Rounded,
+}
+
+impl AbcWarning {
+    /// Line number of the problem, starting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Column number of the problem in characters, starting from 1.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn kind(&self) -> AbcWarningKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for AbcWarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            AbcWarningKind::Chord => "kept only the highest note of a chord",
+            AbcWarningKind::ChordSymbol => "dropped a chord symbol",
+            AbcWarningKind::GraceNotes => "dropped grace notes",
+            AbcWarningKind::Decoration => "dropped a decoration",
+            AbcWarningKind::ExtraVoice => "dropped music for another voice",
+            AbcWarningKind::TempoChange => "ignored a tempo change",
+            AbcWarningKind::SplitNote => "split a note into several RTTTL notes",
+            AbcWarningKind::Rounded => "rounded a note length to fit RTTTL",
+        };
+
+        f.write_str(description)
+    }
+}
+
+impl fmt::Display for AbcWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+/// Writes the ringtone as a single ABC tune.
+///
+/// The unit length is the default duration, or an eighth note if that's a triplet, so triplets
+/// are written as lengths such as `2/3`. There are no bar lines, so naturals are written wherever
+/// a flat of the same letter came before. The loop count becomes repeat signs, and staccato
+/// becomes a staccato dot on each note.
+pub(super) fn write(ringtone: &Ringtone) -> String {
+    let settings = &ringtone.settings;
+    let unit = if settings.duration.is_triplet() {
+        Duration::Eighth
+    } else {
+        settings.duration
+    };
+
+    let mut flattened = [false; 7];
+    let mut melody = Vec::new();
+
+    for note in &ringtone.notes {
+        let mut token = String::new();
+
+        match note.pitch {
+            Some(pitch) => {
+                if settings.style == Style::Staccato {
+                    token.push('.');
+                }
+
+                let (letter, flat) = letter(pitch);
+                if flat {
+                    token.push('_');
+                    flattened[letter] = true;
+                } else if flattened[letter] {
+                    token.push('=');
+                }
+
+                let octave = note.octave.unwrap_or(settings.octave).number() as usize;
+                if octave >= 5 {
+                    token.push(LETTERS[letter].to_ascii_lowercase());
+                    token.push_str(&"'".repeat(octave - 5));
+                } else {
+                    token.push(LETTERS[letter]);
+                    token.push_str(&",".repeat(4 - octave));
+                }
+            }
+            None => token.push('z'),
+        }
+
+        // The length relative to the unit length.
+        let duration = note.duration.unwrap_or(settings.duration);
+        let (num, den) = if note.dotted { (3, 2) } else { (1, 1) };
+        let length = Fraction::new(
+            unit.denominator() as u64 * num,
+            duration.denominator() as u64 * den,
+        );
+        match (length.num, length.den) {
+            (1, 1) => {}
+            (num, 1) => write!(token, "{num}").expect("write to string"),
+            (1, 2) => token.push('/'),
+            (1, den) => write!(token, "/{den}").expect("write to string"),
+            (num, den) => write!(token, "{num}/{den}").expect("write to string"),
+        }
+
+        melody.push(token);
+    }
+
+    // Each pair of plays is a repeat, and an odd one out is written out again.
+    let plays = settings.plays();
+    let mut tokens = Vec::new();
+    for _ in 0..plays / 2 {
+        tokens.push("|:".to_owned());
+        tokens.extend(melody.iter().cloned());
+        tokens.push(":|".to_owned());
+    }
+    if plays % 2 == 1 {
+        tokens.extend(melody);
+    }
+
+    let mut out = String::new();
+    writeln!(out, "X:1").expect("write to string");
+    // A line break in the name would start another field.
+    let title = ringtone.name.replace(['\r', '\n'], " ");
+    writeln!(out, "T:{title}").expect("write to string");
+    writeln!(out, "M:none").expect("write to string");
+    writeln!(out, "L:1/{}", unit.denominator()).expect("write to string");
+    writeln!(out, "Q:1/4={}", settings.tempo).expect("write to string");
+    writeln!(out, "K:C").expect("write to string");
+
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+            writeln!(out, "{line}").expect("write to string");
+            line.clear();
+        }
+
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    writeln!(out, "{line}").expect("write to string");
+
+    out
+}
+
+/// Reads the first tune in ABC notation, also returning what couldn't be carried over.
+///
+/// Only the first voice is read. Chords are reduced to their highest note, repeats are written
+/// out in full, and lengths that RTTTL can't express are split into several notes. A single
+/// repeat of the whole tune becomes a loop count of 1. The tune is staccato if every note has a
+/// staccato dot, and the defaults are chosen by `Ringtone::compact`.
+pub(super) fn read(input: &str) -> Result<(Ringtone, Vec<AbcWarning>), AbcError> {
+    let mut reader = Reader::default();
+    let mut body = false;
+
+    for (i, text) in input.lines().enumerate() {
+        let line = i + 1;
+        let text = text.trim_end();
+
+        if text.starts_with('%') {
+            continue;
+        }
+
+        let field = text
+            .split_once(':')
+            .filter(|(name, _)| {
+                name.len() == 1 && name.starts_with(|c: char| c.is_ascii_alphabetic())
+            })
+            .map(|(name, value)| (name.chars().next().expect("one character"), value.trim()));
+
+        if !body {
+            // Anything before the header fields, such as free text, is skipped.
+            if let Some((name, value)) = field {
+                reader.field(name, value, line, 1)?;
+                body = name == 'K';
+            }
+
+            continue;
+        }
+
+        // A blank line or the start of another tune ends the tune.
+        match field {
+            _ if text.is_empty() => break,
+            Some(('X', _)) => break,
+            Some((name, value)) => reader.field(name, value, line, 1)?,
+            None => reader.music(text, line)?,
+        }
+    }
+
+    if !body {
+        return Err(AbcError::MissingKey);
+    }
+
+    reader.finish()
+}
+
+/// A note or rest, before its length is split into RTTTL durations.
+#[derive(Clone, Copy)]
+struct Tone {
+    key: Option<u8>,
+    length: Fraction,
+    staccato: bool,
+    line: usize,
+    column: usize,
+}
+
+/// A note length as a fraction of a whole note.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Fraction {
+    num: u64,
+    den: u64,
+}
+
+impl Fraction {
+    fn new(num: u64, den: u64) -> Fraction {
+        let gcd = gcd(num, den).max(1);
+
+        Fraction {
+            num: num / gcd,
+            den: den / gcd,
+        }
+    }
+
+    /// The product, or `None` if it overflows.
+    fn mul(self, other: Fraction) -> Option<Fraction> {
+        // Cancelling first keeps the numbers as small as possible.
+        let a = gcd(self.num, other.den).max(1);
+        let b = gcd(other.num, self.den).max(1);
+
+        Some(Fraction::new(
+            (self.num / a).checked_mul(other.num / b)?,
+            (self.den / b).checked_mul(other.den / a)?,
+        ))
+    }
+
+    /// The sum, or `None` if it overflows.
+    fn add(self, other: Fraction) -> Option<Fraction> {
+        let num = self.num.checked_mul(other.den)?;
+        let other_num = other.num.checked_mul(self.den)?;
+
+        Some(Fraction::new(
+            num.checked_add(other_num)?,
+            self.den.checked_mul(other.den)?,
+        ))
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[derive(Default)]
+struct Reader {
+    title: Option<String>,
+    /// The `L:` unit length, if it's been set.
+    unit: Option<Fraction>,
+    /// The `M:` meter, or `None` for free meter.
+    meter: Option<Fraction>,
+    tempo: Option<Tempo>,
+    /// The accidental of each letter from the key signature.
+    key: [i8; 7],
+    /// Accidentals in the current bar, by letter and octave.
+    accidentals: HashMap<(usize, i32), i8>,
+    tones: Vec<Tone>,
+    /// Whether the last note is tied to the next.
+    tie: bool,
+    /// The length multiplier for the next note, from a broken rhythm such as `A>B`.
+    broken: Option<Fraction>,
+    /// The number of notes left in a tuplet, and their length multiplier.
+    tuplet: Option<(u32, Fraction)>,
+    /// Whether the next note has a staccato dot.
+    staccato: bool,
+    /// Where the current repeat starts, and where its first ending starts, as indexes into
+    /// `tones`.
+    repeat_start: usize,
+    ending_start: Option<usize>,
+    /// The repeats so far, as the range of tones repeated and whether there was a first ending.
+    repeats: Vec<(usize, usize, bool)>,
+    voice: Option<String>,
+    other_voice: bool,
+    warnings: Vec<AbcWarning>,
+}
+
+impl Reader {
+    fn warn(&mut self, line: usize, column: usize, kind: AbcWarningKind) {
+        self.warnings.push(AbcWarning { line, column, kind });
+    }
+
+    /// The unit length, which defaults to an eighth, or a sixteenth in meters below 3/4.
+    fn unit(&self) -> Fraction {
+        self.unit.unwrap_or(match self.meter {
+            Some(meter) if meter.num as u128 * 4 < meter.den as u128 * 3 => Fraction::new(1, 16),
+            _ => Fraction::new(1, 8),
+        })
+    }
+
+    /// Handles an information field, from the header, its own line in the body or inline.
+    fn field(
+        &mut self,
+        name: char,
+        value: &str,
+        line: usize,
+        column: usize,
+    ) -> Result<(), AbcError> {
+        let invalid = AbcError::InvalidField { field: name, line };
+
+        match name {
+            'T' if self.title.is_none() => self.title = Some(value.to_owned()),
+            'L' => {
+                let unit = fraction(value).ok_or(invalid)?;
+                self.unit = Some(unit);
+            }
+            'M' => {
+                self.meter = match value {
+                    "" | "none" => None,
+                    "C" => Some(Fraction::new(4, 4)),
+                    "C|" => Some(Fraction::new(2, 2)),
+                    _ => {
+                        // Complex meters such as `2+3/8` add up the beats.
+                        let (beats, unit) = value.split_once('/').ok_or(invalid)?;
+                        let beats = beats
+                            .split('+')
+                            .try_fold(0u64, |total, beats| {
+                                total.checked_add(beats.trim().parse().ok()?)
+                            })
+                            .ok_or(invalid)?;
+                        let unit = unit.trim().parse::<u64>().ok().filter(|&unit| unit > 0);
+                        Some(Fraction::new(beats, unit.ok_or(invalid)?))
+                    }
+                };
+            }
+            'Q' => {
+                let tempo = self.parse_tempo(value).ok_or(invalid)?;
+
+                match (tempo, self.tempo) {
+                    (Some(tempo), None) => self.tempo = Some(tempo),
+                    (Some(tempo), Some(current)) if tempo != current => {
+                        if self.tones.is_empty() {
+                            self.tempo = Some(tempo);
+                        } else {
+                            self.warn(line, column, AbcWarningKind::TempoChange);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            'K' => {
+                self.key = key_signature(value).ok_or(invalid)?;
+                self.accidentals.clear();
+            }
+            'V' => {
+                let id = value.split_whitespace().next().unwrap_or_default();
+
+                // The first voice is the one that's read. Music before any voice is the first voice.
+                match &self.voice {
+                    None if self.tones.is_empty() => self.voice = Some(id.to_owned()),
+                    None => {
+                        self.voice = Some(String::new());
+                        self.other_voice = true;
+                        self.warn(line, column, AbcWarningKind::ExtraVoice);
+                    }
+                    Some(voice) => {
+                        self.other_voice = voice != id;
+                        if self.other_voice {
+                            self.warn(line, column, AbcWarningKind::ExtraVoice);
+                        }
+                    }
+                }
+            }
+            // Other fields, such as the composer or lyrics, don't affect the melody.
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Parses a `Q:` tempo in quarter notes per minute, or `None` if it's only text such as
+    /// `"Allegro"`.
+    fn parse_tempo(&self, value: &str) -> Option<Option<Tempo>> {
+        // Strip any quoted text.
+        let mut text = String::new();
+        for (i, part) in value.split('"').enumerate() {
+            if i % 2 == 0 {
+                text.push_str(part);
+            }
+        }
+
+        let text = text.trim();
+        if text.is_empty() {
+            return Some(None);
+        }
+
+        // The beat is one or more lengths, e.g. `1/4=120` or `1/4 1/8=60`. An old-style tempo
+        // without a beat counts unit lengths.
+        let (beat, per_minute) = match text.split_once('=') {
+            Some((beats, per_minute)) => {
+                let beat = beats
+                    .split_whitespace()
+                    .map(fraction)
+                    .try_fold(Fraction::new(0, 1), |total, beat| total.add(beat?))?;
+                (beat, per_minute)
+            }
+            None => (self.unit(), text),
+        };
+
+        let per_minute: u64 = per_minute.trim().parse().ok()?;
+        let quarters =
+            (per_minute as u128 * beat.num as u128 * 4 + beat.den as u128 / 2) / beat.den as u128;
+
+        Tempo::try_from(quarters)
+            .ok()
+            .filter(|&tempo| tempo > 0)
+            .map(Some)
+    }
+
+    /// Reads a line of music.
+    fn music(&mut self, text: &str, line: usize) -> Result<(), AbcError> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let column = i + 1;
+            let invalid = AbcError::InvalidMusic { line, column };
+            let closing = |from: usize, c: char| {
+                chars[from..]
+                    .iter()
+                    .position(|&other| other == c)
+                    .map(|end| from + end)
+                    .ok_or(invalid)
+            };
+
+            // Skip other voices, up to an inline field that might switch back.
+            if self.other_voice && !chars[i..].starts_with(&['[', 'V', ':']) {
+                i += 1;
+                continue;
+            }
+
+            match chars[i] {
+                '%' => break,
+                // Spacing, beam breaks, line continuations, line breaks and slurs.
+                ' ' | '\t' | '`' | '\\' | 'y' | '$' | ')' => i += 1,
+                '"' => {
+                    let end = closing(i + 1, '"')?;
+
+                    // Annotations start with their placement, anything else is a chord symbol.
+                    if !matches!(chars.get(i + 1), Some('^' | '_' | '<' | '>' | '@')) {
+                        self.warn(line, column, AbcWarningKind::ChordSymbol);
+                    }
+
+                    i = end + 1;
+                }
+                delimiter @ ('!' | '+') => {
+                    let end = closing(i + 1, delimiter)?;
+                    let name: String = chars[i + 1..end].iter().collect();
+
+                    if name == "staccato" {
+                        self.staccato = true;
+                    } else {
+                        self.warn(line, column, AbcWarningKind::Decoration);
+                    }
+
+                    i = end + 1;
+                }
+                '.' => {
+                    self.staccato = true;
+                    i += 1;
+                }
+                '~' | 'H' | 'L' | 'M' | 'O' | 'P' | 'S' | 'T' | 'u' | 'v' => {
+                    self.warn(line, column, AbcWarningKind::Decoration);
+                    i += 1;
+                }
+                '{' => {
+                    i = closing(i + 1, '}')? + 1;
+                    self.warn(line, column, AbcWarningKind::GraceNotes);
+                }
+                '(' => {
+                    i += 1;
+
+                    // A tuplet `(p:q:r` puts r notes in the time of q. Otherwise it's a slur.
+                    if let Some(p) = number(&chars, &mut i) {
+                        let mut q = None;
+                        let mut r = None;
+                        if chars.get(i) == Some(&':') {
+                            i += 1;
+                            q = number(&chars, &mut i);
+                            if chars.get(i) == Some(&':') {
+                                i += 1;
+                                r = number(&chars, &mut i);
+                            }
+                        }
+
+                        let q = q.unwrap_or(match p {
+                            2 | 4 | 8 => 3,
+                            _ => 2,
+                        });
+                        let r = r.unwrap_or(p);
+                        if p == 0 || q == 0 || r == 0 {
+                            return Err(invalid);
+                        }
+
+                        self.tuplet = Some((r, Fraction::new(q as u64, p as u64)));
+                    }
+                }
+                '-' => {
+                    self.tie = true;
+                    i += 1;
+                }
+                direction @ ('>' | '<') => {
+                    let count = chars[i..].iter().take_while(|&&c| c == direction).count();
+                    if count > 3 {
+                        return Err(invalid);
+                    }
+                    i += count;
+
+                    // `A>B` makes A dotted and B half as long, `A>>B` double dotted and a
+                    // quarter, and so on.
+                    let longer = Fraction::new((2 << count) - 1, 1 << count);
+                    let shorter = Fraction::new(1, 1 << count);
+                    let (first, second) = if direction == '>' {
+                        (longer, shorter)
+                    } else {
+                        (shorter, longer)
+                    };
+
+                    let last = self.tones.last_mut().ok_or(invalid)?;
+                    last.length = last.length.mul(first).ok_or(invalid)?;
+                    self.broken = Some(second);
+                }
+                '|' | ':' => i = self.bar(&chars, i, line)?,
+                '[' => match chars.get(i + 1) {
+                    Some('|') => i = self.bar(&chars, i + 1, line)?,
+                    Some(c) if c.is_ascii_digit() => {
+                        i += 1;
+                        self.ending(&chars, &mut i);
+                    }
+                    Some(&name) if name.is_ascii_alphabetic() && chars.get(i + 2) == Some(&':') => {
+                        let end = closing(i + 3, ']')?;
+                        let value: String = chars[i + 3..end].iter().collect();
+                        self.field(name, value.trim(), line, column)?;
+                        i = end + 1;
+                    }
+                    _ => i = self.chord(&chars, i, line)?,
+                },
+                '&' => {
+                    // A voice overlay, up to the end of the bar.
+                    self.warn(line, column, AbcWarningKind::ExtraVoice);
+                    i = chars[i..]
+                        .iter()
+                        .position(|&c| c == '|')
+                        .map_or(chars.len(), |end| i + end);
+                }
+                _ => {
+                    let (key, length) = self.note(&chars, &mut i, line)?;
+                    self.push(key, length, line, column)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a bar line, which may also start or end a repeat, or start an ending.
+    fn bar(&mut self, chars: &[char], start: usize, line: usize) -> Result<usize, AbcError> {
+        let mut i = start;
+        while matches!(chars.get(i), Some('|' | ':')) {
+            i += 1;
+        }
+
+        let token: String = chars[start..i].iter().collect();
+        if !token.contains('|') && token != "::" {
+            return Err(AbcError::InvalidMusic {
+                line,
+                column: start + 1,
+            });
+        }
+
+        if chars.get(i) == Some(&']') {
+            i += 1;
+        }
+
+        self.accidentals.clear();
+
+        if token.starts_with(':') {
+            let end = self.ending_start.take().unwrap_or(self.tones.len());
+            self.repeats
+                .push((self.repeat_start, end, end != self.tones.len()));
+            self.tones.extend_from_within(self.repeat_start..end);
+            self.repeat_start = self.tones.len();
+        }
+
+        if token.ends_with(':') {
+            self.repeat_start = self.tones.len();
+            self.ending_start = None;
+        }
+
+        self.ending(chars, &mut i);
+
+        Ok(i)
+    }
+
+    /// Reads an ending number after a bar line, such as `|1` or `[2`, if there is one. Only
+    /// the start of the first ending matters, since that's skipped on the repeat.
+    fn ending(&mut self, chars: &[char], i: &mut usize) {
+        let start = *i;
+        while matches!(chars.get(*i), Some('0'..='9' | ',' | '-')) {
+            *i += 1;
+        }
+
+        if chars.get(start) == Some(&'1') {
+            self.ending_start = Some(self.tones.len());
+        }
+    }
+
+    /// Reads a chord, keeping its highest note.
+    fn chord(&mut self, chars: &[char], start: usize, line: usize) -> Result<usize, AbcError> {
+        let mut i = start + 1;
+        let mut highest: Option<(Option<u8>, Fraction)> = None;
+        let mut count = 0;
+
+        loop {
+            match chars.get(i) {
+                Some(']') => break,
+                Some(' ' | '-') => i += 1,
+                Some(_) => {
+                    let note = self.note(chars, &mut i, line)?;
+                    if highest.is_none_or(|(key, _)| note.0 > key) {
+                        highest = Some(note);
+                    }
+                    count += 1;
+                }
+                None => {
+                    return Err(AbcError::InvalidMusic {
+                        line,
+                        column: start + 1,
+                    })
+                }
+            }
+        }
+        i += 1;
+
+        let (key, length) = highest.ok_or(AbcError::InvalidMusic {
+            line,
+            column: start + 1,
+        })?;
+        let multiplier = length_multiplier(chars, &mut i).ok_or(AbcError::InvalidMusic {
+            line,
+            column: i + 1,
+        })?;
+
+        if count > 1 {
+            self.warn(line, start + 1, AbcWarningKind::Chord);
+        }
+
+        let length = length.mul(multiplier).ok_or(AbcError::InvalidMusic {
+            line,
+            column: start + 1,
+        })?;
+        self.push(key, length, line, start + 1)?;
+
+        Ok(i)
+    }
+
+    /// Reads a note or rest, returning its MIDI key, or `None` for a rest, and its length.
+    fn note(
+        &mut self,
+        chars: &[char],
+        i: &mut usize,
+        line: usize,
+    ) -> Result<(Option<u8>, Fraction), AbcError> {
+        let column = *i + 1;
+        let invalid = AbcError::InvalidMusic { line, column };
+
+        let (accidental, len) = match chars[*i..] {
+            ['^', '^', ..] => (Some(2), 2),
+            ['_', '_', ..] => (Some(-2), 2),
+            ['^', ..] => (Some(1), 1),
+            ['_', ..] => (Some(-1), 1),
+            ['=', ..] => (Some(0), 1),
+            _ => (None, 0),
+        };
+        *i += len;
+
+        let c = chars.get(*i).copied().ok_or(invalid)?;
+        *i += 1;
+
+        // Rests, and multi-measure rests of a number of bars.
+        match c {
+            'z' | 'x' if accidental.is_none() => {
+                let length = length_multiplier(chars, i).ok_or(invalid)?;
+                return Ok((None, self.unit().mul(length).ok_or(invalid)?));
+            }
+            'Z' | 'X' if accidental.is_none() => {
+                let bars = number(chars, i).unwrap_or(1);
+                let bar = self.meter.unwrap_or(Fraction::new(1, 1));
+                let length = bar.mul(Fraction::new(bars as u64, 1)).ok_or(invalid)?;
+                return Ok((None, length));
+            }
+            _ => {}
+        }
+
+        let letter = LETTERS
+            .iter()
+            .position(|&letter| letter == c.to_ascii_uppercase())
+            .ok_or(invalid)?;
+        let mut octave = if c.is_ascii_uppercase() { 4 } else { 5 };
+
+        while let Some(mark @ ('\'' | ',')) = chars.get(*i) {
+            octave += if *mark == '\'' { 1 } else { -1 };
+            *i += 1;
+        }
+
+        let length = length_multiplier(chars, i).ok_or(invalid)?;
+
+        // Accidentals last until the end of the bar.
+        let accidental = match accidental {
+            Some(accidental) => {
+                self.accidentals.insert((letter, octave), accidental);
+                accidental
+            }
+            None => self
+                .accidentals
+                .get(&(letter, octave))
+                .copied()
+                .unwrap_or(self.key[letter]),
+        };
+
+        let key = (octave + 1) * 12 + SEMITONES[letter] + accidental as i32;
+        let key = u8::try_from(key)
+            .ok()
+            .filter(|key| (12..132).contains(key))
+            .ok_or(AbcError::OctaveOutOfRange { line, column })?;
+
+        Ok((Some(key), self.unit().mul(length).ok_or(invalid)?))
+    }
+
+    /// Adds a note or rest, applying any broken rhythm or tuplet, and joining tied notes. Notes
+    /// and rests longer than `LONGEST` are invalid.
+    fn push(
+        &mut self,
+        key: Option<u8>,
+        length: Fraction,
+        line: usize,
+        column: usize,
+    ) -> Result<(), AbcError> {
+        let invalid = AbcError::InvalidMusic { line, column };
+        let too_long = |length: Fraction| length.num as u128 > LONGEST as u128 * length.den as u128;
+        let mut length = length;
+
+        if let Some(multiplier) = self.broken.take() {
+            length = length.mul(multiplier).ok_or(invalid)?;
+        }
+
+        if let Some((count, multiplier)) = self.tuplet {
+            length = length.mul(multiplier).ok_or(invalid)?;
+            self.tuplet = (count > 1).then_some((count - 1, multiplier));
+        }
+
+        if too_long(length) {
+            return Err(invalid);
+        }
+
+        let staccato = std::mem::take(&mut self.staccato);
+
+        if std::mem::take(&mut self.tie) && key.is_some() {
+            if let Some(last) = self.tones.last_mut().filter(|last| last.key == key) {
+                last.length = last
+                    .length
+                    .add(length)
+                    .filter(|&length| !too_long(length))
+                    .ok_or(invalid)?;
+                return Ok(());
+            }
+        }
+
+        self.tones.push(Tone {
+            key,
+            length,
+            staccato,
+            line,
+            column,
+        });
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(Ringtone, Vec<AbcWarning>), AbcError> {
+        // A single repeat of the whole tune is a loop.
+        let mut loops = 0;
+        if let [(0, end, false)] = self.repeats[..] {
+            if self.tones.len() == end * 2 {
+                self.tones.truncate(end);
+                loops = 1;
+            }
+        }
+
+        let values = musicxml::note_values();
+        let mut notes = Vec::new();
+
+        for tone in &self.tones {
+            let whole = TICKS_PER_QUARTER as u128 * 4;
+            let (num, den) = (tone.length.num as u128, tone.length.den as u128);
+            if !(num * whole).is_multiple_of(den) {
+                self.warnings.push(AbcWarning {
+                    line: tone.line,
+                    column: tone.column,
+                    kind: AbcWarningKind::Rounded,
+                });
+            }
+
+            let ticks = ((num * whole + den / 2) / den).min(u32::MAX as u128) as u32;
+            let pieces = musicxml::split(ticks, &values);
+            if pieces.len() > 1 {
+                self.warnings.push(AbcWarning {
+                    line: tone.line,
+                    column: tone.column,
+                    kind: AbcWarningKind::SplitNote,
+                });
+            }
+
+            // Anything too short for the shortest duration is dropped.
+            for (_, value) in pieces {
+                if let Some((duration, dotted)) = value {
+                    let (pitch, octave) = tone.key.map(midi::pitch_octave).unzip();
+                    notes.push(Note {
+                        duration: Some(duration),
+                        pitch,
+                        octave,
+                        dotted,
+                    });
+                }
+            }
+        }
+
+        if notes.is_empty() {
+            return Err(AbcError::NoNotes);
+        }
+
+        let mut played = self
+            .tones
+            .iter()
+            .filter(|tone| tone.key.is_some())
+            .peekable();
+        let style = if played.peek().is_some() && played.all(|tone| tone.staccato) {
+            Style::Staccato
+        } else {
+            Style::Continuous
+        };
+
+        let name = self
+            .title
+            .map(|title| title.replace(':', "").trim().to_owned())
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| UNTITLED.to_owned());
+
+        let settings = Settings {
+            tempo: self.tempo.unwrap_or(Settings::default().tempo),
+            loops,
+            style,
+            ..Settings::default()
+        };
+
+        let mut ringtone = Ringtone {
+            name,
+            settings,
+            notes,
+        };
+        ringtone.compact();
+
+        Ok((ringtone, self.warnings))
+    }
+}
+
+/// Parses a length such as `1/8`.
+fn fraction(text: &str) -> Option<Fraction> {
+    let (num, den) = text.trim().split_once('/')?;
+    let num: u64 = num.parse().ok()?;
+    let den: u64 = den.parse().ok()?;
+
+    (num > 0 && den > 0).then(|| Fraction::new(num, den))
+}
+
+/// Reads a number, if there is one.
+fn number(chars: &[char], i: &mut usize) -> Option<u32> {
+    let len = chars[*i..]
+        .iter()
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+    let number = chars[*i..*i + len]
+        .iter()
+        .collect::<String>()
+        .parse()
+        .ok()?;
+    *i += len;
+
+    Some(number)
+}
+
+/// Reads the length after a note, as a multiple of the unit length: `2` is twice as long, `/` or
+/// `/2` half as long, `//` a quarter, and `3/2` one and a half times as long.
+fn length_multiplier(chars: &[char], i: &mut usize) -> Option<Fraction> {
+    let num = match chars.get(*i) {
+        Some(c) if c.is_ascii_digit() => number(chars, i)?,
+        _ => 1,
+    };
+
+    let mut den = 1u64;
+    while chars.get(*i) == Some(&'/') {
+        *i += 1;
+        den = den.checked_mul(match chars.get(*i) {
+            Some(c) if c.is_ascii_digit() => number(chars, i)? as u64,
+            _ => 2,
+        })?;
+    }
+
+    (num > 0 && den > 0).then(|| Fraction::new(num as u64, den))
+}
+
+/// Parses a key signature such as `G`, `Bbm`, `D dorian` or `F exp ^f`, as the accidental of
+/// each letter.
+fn key_signature(value: &str) -> Option<[i8; 7]> {
+    let mut key = [0; 7];
+    let mut words = value.split_whitespace().peekable();
+
+    // The tonic and mode, e.g. `F#m`, `A minor` or `Gmix`. There's no tonic for `none`, or when
+    // there are only accidentals or a clef.
+    let tonic = words
+        .next_if(|word| word.starts_with(|c: char| matches!(c, 'A'..='G')) && !word.contains('='));
+    if let Some(tonic) = tonic {
+        let mut chars = tonic.chars();
+        let letter = chars.next()?;
+        let mut fifths = match letter {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => -1,
+            'G' => 1,
+            'A' => 3,
+            _ => 5,
+        };
+
+        let rest = chars.as_str();
+        let mode = match rest.strip_prefix('#') {
+            Some(mode) => {
+                fifths += 7;
+                mode
+            }
+            None => match rest.strip_prefix('b') {
+                Some(mode) => {
+                    fifths -= 7;
+                    mode
+                }
+                None => rest,
+            },
+        };
+
+        let mode = if mode.is_empty() {
+            words
+                .next_if(|word| {
+                    word.starts_with(|c: char| c.is_ascii_alphabetic())
+                        && !word.contains('=')
+                        && !word.eq_ignore_ascii_case("exp")
+                })
+                .unwrap_or_default()
+        } else {
+            mode
+        };
+
+        let mode = mode.to_ascii_lowercase();
+        fifths += match mode.get(..3).unwrap_or(mode.as_str()) {
+            "" | "maj" | "ion" => 0,
+            "mix" => -1,
+            "dor" => -2,
+            "m" | "min" | "aeo" => -3,
+            "phr" => -4,
+            "loc" => -5,
+            "lyd" => 1,
+            _ => return None,
+        };
+
+        // Sharps are added in the order F C G D A E B, and flats in the reverse order.
+        const ORDER: [usize; 7] = [3, 0, 4, 1, 5, 2, 6];
+        match fifths {
+            0..=7 => ORDER[..fifths as usize].iter().for_each(|&i| key[i] = 1),
+            -7..=-1 => ORDER[(7 + fifths) as usize..]
+                .iter()
+                .for_each(|&i| key[i] = -1),
+            _ => return None,
+        }
+    }
+
+    // Explicit accidentals, such as `^f` or `=b`, and clefs and other settings that don't affect
+    // the pitches.
+    for word in words {
+        if word.eq_ignore_ascii_case("exp") {
+            key = [0; 7];
+            continue;
+        }
+
+        let accidental = match word.get(..1)? {
+            "^" => 1,
+            "_" => -1,
+            "=" => 0,
+            _ => continue,
+        };
+
+        let letter = word[1..].chars().next()?.to_ascii_uppercase();
+        key[LETTERS.iter().position(|&l| l == letter)?] = accidental;
+    }
+
+    Some(key)
+}
+
+/// The letter of the pitch, as an index into `LETTERS`, and whether it's flat.
+fn letter(pitch: Pitch) -> (usize, bool) {
+    match pitch {
+        Pitch::C => (0, false),
+        Pitch::Db => (1, true),
+        Pitch::D => (1, false),
+        Pitch::Eb => (2, true),
+        Pitch::E => (2, false),
+        Pitch::F => (3, false),
+        Pitch::Gb => (4, true),
+        Pitch::G => (4, false),
+        Pitch::Ab => (5, true),
+        Pitch::A => (5, false),
+        Pitch::Bb => (6, true),
+        Pitch::B => (6, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_abc() {
+        let ringtone = Ringtone::parse("Hi:d=8,o=5,b=140,s=s,l=1:c,4a#4,16p,d#6.,12e,b,a#,4a")
+            .expect("successful parse");
+
+        assert_eq!(
+            write(&ringtone),
+            "X:1\n\
+             T:Hi\n\
+             M:none\n\
+             L:1/8\n\
+             Q:1/4=140\n\
+             K:C\n\
+             |: .c ._B2 z/ ._e'3/2 .=e2/3 .=b ._b .a2 :|\n"
+        );
+
+        let mut ringtone = Ringtone::parse("Hi:d=4,o=5,b=120:c").expect("successful parse");
+        ringtone.name = "Two\r\nK:D".to_owned();
+        assert!(write(&ringtone).starts_with("X:1\nT:Two  K:D\nM:none\n"));
+    }
+
+    #[test]
+    fn test_read_abc() {
+        let input = r#"%abc-2.1
+X:1
+T:Test: Tune
+C:Trad.
+M:3/4
+L:1/8
+Q:"Allegro" 3/8=60
+K:G
+|: "G"G>A Bc d2 | !trill!e2 {g}f2 [ceg]2 |1 F2 =F2 z2 :|2 (3cBA ^c=c-c2 |]
+Q:1/4=200
+V:2
+ABC |
+"#;
+
+        let (ringtone, warnings) = read(input).expect("successful read");
+
+        assert_eq!(
+            ringtone.to_rtttl(),
+            "Test Tune:d=4,o=5,b=90:8g4.,16a4,8b4,8c,d,e,f#,g,f#4,f4,p,8g4.,16a4,8b4,8c,d,e,f#,g,\
+             12c,12b4,12a4,8c#,c."
+        );
+
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|warning| (warning.line(), warning.column(), warning.kind()))
+                .collect::<Vec<_>>(),
+            [
+                (9, 4, AbcWarningKind::ChordSymbol),
+                (9, 19, AbcWarningKind::Decoration),
+                (9, 29, AbcWarningKind::GraceNotes),
+                (9, 35, AbcWarningKind::Chord),
+                (10, 1, AbcWarningKind::TempoChange),
+                (11, 1, AbcWarningKind::ExtraVoice),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_abc_lengths() {
+        // No header fields apart from the key, so a unit length of an eighth and a tempo of 120.
+        let (ringtone, warnings) =
+            read("K:Bb\nB/ B// B3/ B5 (5BBBBB .c\n").expect("successful read");
+
+        assert_eq!(
+            ringtone.to_rtttl(),
+            "Untitled:d=32,o=4,b=120:16a#,a#,8a#.,2a#,8a#,a#.,a#.,a#.,a#.,a#.,8c5"
+        );
+        assert_eq!(
+            warnings.iter().map(|w| w.kind()).collect::<Vec<_>>(),
+            [
+                AbcWarningKind::SplitNote,
+                AbcWarningKind::Rounded,
+                AbcWarningKind::SplitNote,
+                AbcWarningKind::Rounded,
+                AbcWarningKind::SplitNote,
+                AbcWarningKind::Rounded,
+                AbcWarningKind::SplitNote,
+                AbcWarningKind::Rounded,
+                AbcWarningKind::SplitNote,
+                AbcWarningKind::Rounded,
+                AbcWarningKind::SplitNote,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_abc_round_trip() {
+        for input in [
+            "Hi:d=8,o=5,b=140,s=s,l=1:c,4a#4,16p,d#6.,12e,b,a#,4a",
+            "Nokia:d=8,o=5,b=180:e6,d6,4f#,4g#,c#6,b,4d,4e,b,a,c#,4e,2a",
+            "Range:d=12,o=9,b=900:c,32c0,2p.,1b,b,64a#",
+        ] {
+            let ringtone = Ringtone::parse(input).expect("successful parse");
+            let abc = write(&ringtone);
+
+            assert_eq!(read(&abc), Ok((ringtone, Vec::new())), "{input}");
+        }
+
+        // More loops are written out.
+        let ringtone = Ringtone::parse("Loop:d=4,o=5,b=100,l=2:c,d").expect("successful parse");
+        let (read, _) = read(&write(&ringtone)).expect("successful read");
+
+        assert_eq!(read.to_rtttl(), "Loop:d=4,o=5,b=100:c,d,c,d,c,d");
+    }
+
+    #[test]
+    fn test_read_abc_errors() {
+        assert_eq!(read("X:1\nT:Tune\nCDE\n"), Err(AbcError::MissingKey));
+        assert_eq!(
+            read("L:eighth\nK:C\nC\n"),
+            Err(AbcError::InvalidField {
+                field: 'L',
+                line: 1
+            })
+        );
+        assert_eq!(
+            read("K:C#lyd\nC\n"),
+            Err(AbcError::InvalidField {
+                field: 'K',
+                line: 1
+            })
+        );
+        assert_eq!(
+            read("K:C\nCD#E\n"),
+            Err(AbcError::InvalidMusic { line: 2, column: 3 })
+        );
+        assert_eq!(
+            read("K:C\nCD\"E\n"),
+            Err(AbcError::InvalidMusic { line: 2, column: 3 })
+        );
+        assert_eq!(
+            read("K:C\nC,,,,,\n"),
+            Err(AbcError::OctaveOutOfRange { line: 2, column: 1 })
+        );
+        assert_eq!(read("K:C\n|| %\n"), Err(AbcError::NoNotes));
+
+        // Lengths that overflow, or are too long to write out.
+        assert_eq!(
+            read("L:1/4294967295\nK:C\nC4294967295/4294967295/4294967295/4294967295\n"),
+            Err(AbcError::InvalidMusic { line: 3, column: 1 })
+        );
+        assert_eq!(
+            read("M:18446744073709551615/1\nK:C\nCZ2\n"),
+            Err(AbcError::InvalidMusic { line: 3, column: 2 })
+        );
+        assert_eq!(
+            read("K:C\nCZ4294967295\n"),
+            Err(AbcError::InvalidMusic { line: 2, column: 2 })
+        );
+        assert_eq!(
+            read("K:C\nC512-C\n"),
+            Err(AbcError::InvalidMusic { line: 2, column: 6 })
+        );
+        assert!(read("M:4/4\nK:C\nCZ64\n").is_ok());
+    }
+
+    #[test]
+    fn test_key_signature() {
+        assert_eq!(key_signature("C"), Some([0; 7]));
+        assert_eq!(key_signature("none"), Some([0; 7]));
+        assert_eq!(key_signature("D"), Some([1, 0, 0, 1, 0, 0, 0]));
+        assert_eq!(key_signature("F#m"), Some([1, 0, 0, 1, 1, 0, 0]));
+        assert_eq!(key_signature("Bb"), Some([0, 0, -1, 0, 0, 0, -1]));
+        assert_eq!(key_signature("E dorian"), Some([1, 0, 0, 1, 0, 0, 0]));
+        assert_eq!(key_signature("Cb"), Some([-1; 7]));
+        assert_eq!(key_signature("G clef=bass"), Some([0, 0, 0, 1, 0, 0, 0]));
+        assert_eq!(key_signature("D exp ^f _b"), Some([0, 0, 0, 1, 0, 0, -1]));
+        assert_eq!(key_signature("C#lyd"), None);
+    }
+}
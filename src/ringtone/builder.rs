@@ -0,0 +1,156 @@
+use super::*;
+
+/// Builds a `Ringtone` in code, without going through the text format.
+///
+/// ```
+/// use rtttlsynth::{Duration, Note, Octave, Pitch, Ringtone};
+///
+/// let ringtone = Ringtone::builder("Scale")
+///     .duration(Duration::Eighth)
+///     .octave(Octave::O5)
+///     .tempo(140)
+///     .note(Note::new(Pitch::C))
+///     .note(Note::new(Pitch::D))
+///     .note(Note::new(Pitch::E).with_duration(Duration::Quarter))
+///     .note(Note::rest())
+///     .build()
+///     .expect("valid ringtone");
+///
+/// assert_eq!(ringtone.to_rtttl(), "Scale:d=8,o=5,b=140:c,d,4e,p");
+/// ```
+#[derive(Clone, Debug)]
+pub struct RingtoneBuilder {
+    name: String,
+    settings: Settings,
+    notes: Vec<Note>,
+}
+
+impl RingtoneBuilder {
+    pub fn new(name: impl Into<String>) -> RingtoneBuilder {
+        RingtoneBuilder {
+            name: name.into(),
+            settings: Settings::default(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> RingtoneBuilder {
+        self.name = name.into();
+        self
+    }
+
+    pub fn settings(mut self, settings: Settings) -> RingtoneBuilder {
+        self.settings = settings;
+        self
+    }
+
+    pub fn duration(mut self, duration: Duration) -> RingtoneBuilder {
+        self.settings.duration = duration;
+        self
+    }
+
+    pub fn octave(mut self, octave: Octave) -> RingtoneBuilder {
+        self.settings.octave = octave;
+        self
+    }
+
+    pub fn tempo(mut self, tempo: Tempo) -> RingtoneBuilder {
+        self.settings.tempo = tempo;
+        self
+    }
+
+    /// Sets the loop count, which is capped at `LOOP_FOREVER`.
+    pub fn loops(mut self, loops: u8) -> RingtoneBuilder {
+        self.settings.set_loops(loops);
+        self
+    }
+
+    pub fn style(mut self, style: Style) -> RingtoneBuilder {
+        self.settings.style = style;
+        self
+    }
+
+    pub fn note(mut self, note: Note) -> RingtoneBuilder {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn notes(mut self, notes: impl IntoIterator<Item = Note>) -> RingtoneBuilder {
+        self.notes.extend(notes);
+        self
+    }
+
+    /// Finishes the ringtone, checking that it can be written out as RTTTL and read back again.
+    pub fn build(self) -> Result<Ringtone, BuildError> {
+        if self.name.is_empty() {
+            return Err(BuildError::EmptyName);
+        }
+
+        if self.name.contains(':') {
+            return Err(BuildError::InvalidName);
+        }
+
+        if self.notes.is_empty() {
+            return Err(BuildError::NoNotes);
+        }
+
+        Ok(Ringtone {
+            name: self.name,
+            settings: self.settings,
+            notes: self.notes,
+        })
+    }
+}
+
+/// Starts a builder from an existing ringtone, for edits which add or remove notes.
+impl From<Ringtone> for RingtoneBuilder {
+    fn from(ringtone: Ringtone) -> RingtoneBuilder {
+        RingtoneBuilder {
+            name: ringtone.name,
+            settings: ringtone.settings,
+            notes: ringtone.notes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_errors() {
+        let note = Note::new(Pitch::A);
+
+        assert_eq!(
+            RingtoneBuilder::new("").note(note).build(),
+            Err(BuildError::EmptyName)
+        );
+        assert_eq!(
+            RingtoneBuilder::new("a:b").note(note).build(),
+            Err(BuildError::InvalidName)
+        );
+        assert_eq!(
+            RingtoneBuilder::new("Empty").build(),
+            Err(BuildError::NoNotes)
+        );
+    }
+
+    #[test]
+    fn test_edit_round_trip() {
+        let ringtone =
+            Ringtone::parse("Nokia:d=4,o=5,b=120:16e6,16d6,8f#").expect("successful parse");
+
+        let ringtone = RingtoneBuilder::from(ringtone)
+            .name("Nokia edit")
+            .tempo(90)
+            .note(Note::rest().with_duration(Duration::Half).with_dotted(true))
+            .build()
+            .expect("valid ringtone");
+
+        assert_eq!(
+            ringtone.to_rtttl(),
+            "Nokia edit:d=4,o=5,b=90:16e6,16d6,8f#,2p."
+        );
+        assert_eq!(Ringtone::parse(&ringtone.to_rtttl()), Ok(ringtone));
+    }
+}
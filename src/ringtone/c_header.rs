@@ -0,0 +1,228 @@
+use std::fmt::Write;
+
+use super::*;
+
+/// How many array values to write on each line.
+const VALUES_PER_LINE: usize = 12;
+
+/// Options for generating C source with `Ringtone::to_c_header`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CHeaderOptions {
+    /// Whether to put the arrays in program memory with `PROGMEM`, which saves RAM on AVR boards.
+    pub progmem: bool,
+
+    /// The prefix for the names in the header. By default, it's made from the ringtone's name.
+    pub prefix: Option<String>,
+}
+
+/// Writes a C header for Arduino-compatible boards, with the melody as arrays of frequencies (Hz,
+/// with 0 for silence) and durations (milliseconds), and a function that plays it with `tone()`.
+///
+/// The melody comes from `Ringtone::iter`, so the loops and style are already applied. The
+/// durations are rounded from the start of the melody, so rounding errors don't add up.
+pub(super) fn write(ringtone: &Ringtone, options: &CHeaderOptions) -> String {
+    let prefix = identifier(options.prefix.as_deref().unwrap_or(&ringtone.name));
+    let guard = format!("{}_H", prefix.to_ascii_uppercase());
+    let length = format!("{}_LENGTH", prefix.to_ascii_uppercase());
+
+    let mut frequencies = Vec::new();
+    let mut durations = Vec::new();
+    let mut elapsed = 0.0;
+    let mut elapsed_ms = 0;
+
+    for tone in ringtone.iter() {
+        let (frequency, duration) = match tone {
+            PlayedTone::Note { freq, duration } => (freq.round() as u16, duration),
+            PlayedTone::Silence { duration } => (0, duration),
+        };
+
+        elapsed += duration as f64;
+        let end_ms = (elapsed * 1000.0).round() as u64;
+        frequencies.push(frequency as u64);
+        durations.push(end_ms - elapsed_ms);
+        elapsed_ms = end_ms;
+    }
+
+    // Long notes at slow tempos need more than 16 bits.
+    let (duration_type, read_duration) = if durations.iter().all(|&ms| ms <= u16::MAX as u64) {
+        ("uint16_t", "pgm_read_word")
+    } else {
+        ("uint32_t", "pgm_read_dword")
+    };
+    let progmem = if options.progmem { " PROGMEM" } else { "" };
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// {}, generated by rtttlsynth.",
+        ringtone.name.replace('\n', " ")
+    )
+    .expect("write to string");
+    writeln!(out, "#ifndef {guard}").expect("write to string");
+    writeln!(out, "#define {guard}").expect("write to string");
+    out.push('\n');
+    out.push_str("#include <Arduino.h>\n");
+    out.push('\n');
+    writeln!(out, "#define {length} {}", frequencies.len()).expect("write to string");
+    out.push('\n');
+    writeln!(out, "// Frequencies in Hz, or 0 for silence.").expect("write to string");
+    write_array(
+        &mut out,
+        &format!("static const uint16_t {prefix}_frequencies[{length}]{progmem}"),
+        &frequencies,
+    );
+    out.push('\n');
+    writeln!(out, "// Durations in milliseconds.").expect("write to string");
+    write_array(
+        &mut out,
+        &format!("static const {duration_type} {prefix}_durations[{length}]{progmem}"),
+        &durations,
+    );
+    out.push('\n');
+
+    let (frequency, duration) = if options.progmem {
+        (
+            format!("pgm_read_word(&{prefix}_frequencies[i])"),
+            format!("{read_duration}(&{prefix}_durations[i])"),
+        )
+    } else {
+        (
+            format!("{prefix}_frequencies[i]"),
+            format!("{prefix}_durations[i]"),
+        )
+    };
+
+    writeln!(
+        out,
+        "// Plays the melody on a buzzer connected to the pin, returning when it ends."
+    )
+    .expect("write to string");
+    writeln!(out, "static inline void {prefix}_play(uint8_t pin) {{").expect("write to string");
+    writeln!(out, "  for (size_t i = 0; i < {length}; i++) {{").expect("write to string");
+    writeln!(out, "    uint16_t frequency = {frequency};").expect("write to string");
+    writeln!(out, "    {duration_type} duration = {duration};").expect("write to string");
+    out.push_str("    if (frequency > 0) {\n");
+    out.push_str("      tone(pin, frequency, duration);\n");
+    out.push_str("    }\n");
+    out.push_str("    delay(duration);\n");
+    out.push_str("  }\n");
+    out.push_str("  noTone(pin);\n");
+    out.push_str("}\n");
+    out.push('\n');
+    writeln!(out, "#endif // {guard}").expect("write to string");
+
+    out
+}
+
+fn write_array(out: &mut String, declaration: &str, values: &[u64]) {
+    writeln!(out, "{declaration} = {{").expect("write to string");
+
+    for line in values.chunks(VALUES_PER_LINE) {
+        let line: Vec<_> = line.iter().map(u64::to_string).collect();
+        writeln!(out, "  {},", line.join(", ")).expect("write to string");
+    }
+
+    out.push_str("};\n");
+}
+
+/// Makes a C identifier from the name, in lowercase with underscores between the words.
+fn identifier(name: &str) -> String {
+    let mut identifier = String::new();
+
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            identifier.push(c.to_ascii_lowercase());
+        } else if !identifier.is_empty() && !identifier.ends_with('_') {
+            identifier.push('_');
+        }
+    }
+
+    let identifier = identifier.trim_end_matches('_');
+    if identifier.is_empty() {
+        "melody".to_owned()
+    } else if identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("melody_{identifier}")
+    } else {
+        identifier.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_c_header() {
+        let ringtone = Ringtone::parse("Beep:d=4,o=5,b=120:a,8p,c6").expect("successful parse");
+
+        assert_eq!(
+            write(&ringtone, &CHeaderOptions::default()),
+            "// Beep, generated by rtttlsynth.
+#ifndef BEEP_H
+#define BEEP_H
+
+#include <Arduino.h>
+
+#define BEEP_LENGTH 3
+
+// Frequencies in Hz, or 0 for silence.
+static const uint16_t beep_frequencies[BEEP_LENGTH] = {
+  880, 0, 1047,
+};
+
+// Durations in milliseconds.
+static const uint16_t beep_durations[BEEP_LENGTH] = {
+  500, 250, 500,
+};
+
+// Plays the melody on a buzzer connected to the pin, returning when it ends.
+static inline void beep_play(uint8_t pin) {
+  for (size_t i = 0; i < BEEP_LENGTH; i++) {
+    uint16_t frequency = beep_frequencies[i];
+    uint16_t duration = beep_durations[i];
+    if (frequency > 0) {
+      tone(pin, frequency, duration);
+    }
+    delay(duration);
+  }
+  noTone(pin);
+}
+
+#endif // BEEP_H
+"
+        );
+    }
+
+    #[test]
+    fn test_write_c_header_progmem() {
+        // Natural style leaves a gap after each note, and the durations are rounded from the
+        // start so they add up to the length of the melody.
+        let ringtone = Ringtone::parse("Slow:d=1,o=5,b=3,s=n:c,c").expect("successful parse");
+        let options = CHeaderOptions {
+            progmem: true,
+            prefix: Some("2 Slow!".to_owned()),
+        };
+        let header = write(&ringtone, &options);
+
+        assert!(header.contains(
+            "static const uint16_t melody_2_slow_frequencies[MELODY_2_SLOW_LENGTH] PROGMEM = {\n  \
+             523, 0, 523, 0,\n};"
+        ));
+        assert!(header.contains(
+            "static const uint32_t melody_2_slow_durations[MELODY_2_SLOW_LENGTH] PROGMEM = {\n  \
+             75000, 5000, 75000, 5000,\n};"
+        ));
+        assert!(
+            header.contains("uint16_t frequency = pgm_read_word(&melody_2_slow_frequencies[i]);")
+        );
+        assert!(header.contains("uint32_t duration = pgm_read_dword(&melody_2_slow_durations[i]);"));
+    }
+
+    #[test]
+    fn test_identifier() {
+        assert_eq!(identifier("Nokia"), "nokia");
+        assert_eq!(identifier("  Für Elise (2) "), "f_r_elise_2");
+        assert_eq!(identifier("99 Luftballons"), "melody_99_luftballons");
+        assert_eq!(identifier("!!!"), "melody");
+    }
+}
@@ -0,0 +1,184 @@
+use super::*;
+
+/// A file of ringtones, one per line, as ringtone archives are usually distributed.
+///
+/// Blank lines are skipped, as are comment lines starting with `#` or `//`. Every other line is
+/// parsed separately, so one broken ringtone doesn't stop the rest from being read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RingtoneCollection {
+    entries: Vec<CollectionEntry>,
+}
+
+/// A single line from a `RingtoneCollection`, and the result of parsing it.
+///
+/// Positions in the error and warnings are relative to the whole collection, not the line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CollectionEntry {
+    line: usize,
+    result: Result<Ringtone, RtttlError>,
+    warnings: Vec<ParseWarning>,
+}
+
+impl RingtoneCollection {
+    pub fn parse(input: &str) -> RingtoneCollection {
+        RingtoneCollection::parse_with(input, &ParseOptions::default())
+    }
+
+    pub fn parse_with(input: &str, options: &ParseOptions) -> RingtoneCollection {
+        let mut entries = Vec::new();
+        let mut offset = 0;
+
+        for (i, line) in input.split_inclusive('\n').enumerate() {
+            let start = offset;
+            offset += line.len();
+
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("//") {
+                continue;
+            }
+
+            // Positions from the parser are relative to the trimmed line, so shift them to be
+            // relative to the whole input.
+            let start = start + line.len() - line.trim_start().len();
+
+            let (result, warnings) = match Ringtone::parse_with_warnings(trimmed, options) {
+                Ok((ringtone, warnings)) => {
+                    let warnings = warnings
+                        .iter()
+                        .map(|w| ParseWarning::new(input, start + w.offset(), w.kind()))
+                        .collect();
+
+                    (Ok(ringtone), warnings)
+                }
+                Err(e) => {
+                    let e = RtttlError::new(input, start + e.offset(), e.section(), e.expected());
+
+                    (Err(e), Vec::new())
+                }
+            };
+
+            entries.push(CollectionEntry {
+                line: i + 1,
+                result,
+                warnings,
+            });
+        }
+
+        RingtoneCollection { entries }
+    }
+
+    pub fn entries(&self) -> &[CollectionEntry] {
+        &self.entries
+    }
+
+    /// The ringtones which parsed successfully.
+    pub fn ringtones(&self) -> impl Iterator<Item = &Ringtone> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.result.as_ref().ok())
+    }
+
+    /// The errors from the lines which failed to parse.
+    pub fn errors(&self) -> impl Iterator<Item = &RtttlError> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.result.as_ref().err())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl IntoIterator for RingtoneCollection {
+    type Item = CollectionEntry;
+    type IntoIter = std::vec::IntoIter<CollectionEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl CollectionEntry {
+    /// Line number (1-based) of the ringtone in the collection.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn result(&self) -> Result<&Ringtone, &RtttlError> {
+        self.result.as_ref()
+    }
+
+    pub fn into_result(self) -> Result<Ringtone, RtttlError> {
+        self.result
+    }
+
+    /// Problems worked around when parsing in lenient mode.
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_collection() {
+        let input = "\
+# Classic tunes
+Nokia:d=4,o=5,b=120:16e6,16d6,8f#,8g#
+
+// This one is broken
+  Broken:d=4,o=5,b=120:8a,8x
+Scale:d=8,o=5,b=140:c,d,e
+";
+
+        let collection = RingtoneCollection::parse(input);
+
+        assert_eq!(collection.len(), 3);
+
+        let lines: Vec<_> = collection.entries().iter().map(|e| e.line()).collect();
+        assert_eq!(lines, [2, 5, 6]);
+
+        let names: Vec<_> = collection.ringtones().map(|r| r.name()).collect();
+        assert_eq!(names, ["Nokia", "Scale"]);
+
+        let errors: Vec<_> = collection.errors().collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line(), 5);
+        assert_eq!(errors[0].column(), 28);
+        assert_eq!(
+            errors[0].snippet(),
+            "  Broken:d=4,o=5,b=120:8a,8x\n                           ^"
+        );
+    }
+
+    #[test]
+    fn test_parse_collection_lenient() {
+        let input = "One:d=4:a,b\n\nTwo:d=4:C,d,\n";
+        let options = ParseOptions {
+            lenient: true,
+            ..ParseOptions::default()
+        };
+
+        let collection = RingtoneCollection::parse_with(input, &options);
+
+        let warnings: Vec<_> = collection.entries()[1]
+            .warnings()
+            .iter()
+            .map(|w| (w.line(), w.column(), w.kind()))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                (3, 9, WarningKind::Uppercase),
+                (3, 13, WarningKind::EmptyItem)
+            ]
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use super::*;
+
+/// The durations that the 8 and 9 keys step through, from the shortest.
+const DURATIONS: [Duration; 6] = [
+    Duration::ThirtySecond,
+    Duration::Sixteenth,
+    Duration::Eighth,
+    Duration::Quarter,
+    Duration::Half,
+    Duration::Whole,
+];
+
+/// The composer's three octaves, from octave 1 (A is 440 Hz).
+const OCTAVES: [Octave; 3] = [Octave::O4, Octave::O5, Octave::O6];
+
+/// The duration and octave of the first note, before any keys change them.
+const FIRST_DURATION: usize = 3;
+const FIRST_OCTAVE: usize = 0;
+
+/// Writes the ringtone as the keys to press in a Nokia phone's Composer, one group of keys per
+/// note separated by spaces.
+///
+/// In the Composer, keys 1 to 7 enter the notes C to B and 0 a rest. Each new note has the same
+/// duration and octave as the one before it, starting from a quarter note in octave 1, and the
+/// keys after it change it: `#` makes it sharp, `*` moves it up an octave (going back round to
+/// octave 1 after octave 3), and 8 and 9 make it shorter and longer. So `1 2#8` is a quarter C
+/// and then an eighth D#.
+///
+/// The Composer has no loops, so they're written out in full, and the tempo is set in its menu
+/// rather than with keys.
+pub(super) fn write(ringtone: &Ringtone) -> Result<String, ComposerError> {
+    let settings = &ringtone.settings;
+    let mut groups = Vec::new();
+    let mut duration_index = FIRST_DURATION;
+    let mut octave_index = FIRST_OCTAVE;
+
+    // The keys for each play of the melody depend on where the last one ended.
+    let plays = (0..settings.plays()).flat_map(|_| ringtone.notes.iter().enumerate());
+
+    for (i, note) in plays {
+        let duration = note.duration.unwrap_or(settings.duration);
+        let new_duration = DURATIONS
+            .iter()
+            .position(|&d| d == duration)
+            .filter(|_| !note.dotted)
+            .ok_or(ComposerError::UnsupportedDuration(i))?;
+
+        let mut group = String::new();
+
+        match note.pitch {
+            Some(pitch) => {
+                let octave = note.octave.unwrap_or(settings.octave);
+                let new_octave = OCTAVES
+                    .iter()
+                    .position(|&o| o == octave)
+                    .ok_or(ComposerError::UnsupportedOctave(i))?;
+
+                let (key, sharp) = key(pitch);
+                group.push(key);
+                if sharp {
+                    group.push('#');
+                }
+
+                group.push_str(&"*".repeat((new_octave + OCTAVES.len() - octave_index) % 3));
+                octave_index = new_octave;
+            }
+            None => group.push('0'),
+        }
+
+        if new_duration < duration_index {
+            group.push_str(&"8".repeat(duration_index - new_duration));
+        } else {
+            group.push_str(&"9".repeat(new_duration - duration_index));
+        }
+        duration_index = new_duration;
+
+        groups.push(group);
+    }
+
+    Ok(groups.join(" "))
+}
+
+/// Reads the keys pressed in a Nokia phone's Composer, as written by `write`. Spaces and line
+/// breaks between the keys are ignored.
+///
+/// The keys don't include the tempo, so it's RTTTL's default, and the default duration and octave
+/// are chosen by `Ringtone::compact`.
+pub(super) fn read(input: &str) -> Result<Ringtone, ComposerError> {
+    let mut notes: Vec<Note> = Vec::new();
+    let mut duration_index = FIRST_DURATION;
+    let mut octave_index = FIRST_OCTAVE;
+
+    for (offset, key) in input.char_indices() {
+        match key {
+            '0'..='7' => {
+                let pitch = match key {
+                    '1' => Some(Pitch::C),
+                    '2' => Some(Pitch::D),
+                    '3' => Some(Pitch::E),
+                    '4' => Some(Pitch::F),
+                    '5' => Some(Pitch::G),
+                    '6' => Some(Pitch::A),
+                    '7' => Some(Pitch::B),
+                    _ => None,
+                };
+
+                notes.push(Note {
+                    duration: Some(DURATIONS[duration_index]),
+                    pitch,
+                    octave: pitch.map(|_| OCTAVES[octave_index]),
+                    dotted: false,
+                });
+            }
+            // The Composer ignores presses that would go past the shortest or longest duration.
+            '8' | '9' => {
+                duration_index = match key {
+                    '8' => duration_index.saturating_sub(1),
+                    _ => (duration_index + 1).min(DURATIONS.len() - 1),
+                };
+
+                if let Some(note) = notes.last_mut() {
+                    note.duration = Some(DURATIONS[duration_index]);
+                }
+            }
+            '*' => {
+                octave_index = (octave_index + 1) % OCTAVES.len();
+
+                if let Some(note) = notes.last_mut() {
+                    if note.pitch.is_some() {
+                        note.octave = Some(OCTAVES[octave_index]);
+                    }
+                }
+            }
+            // Pressing `#` again takes the sharp off. E and B have no sharps in the Composer.
+            '#' => {
+                let note = notes.last_mut().ok_or(ComposerError::InvalidKey(offset))?;
+                note.pitch = match note.pitch {
+                    Some(Pitch::C) => Some(Pitch::Db),
+                    Some(Pitch::Db) => Some(Pitch::C),
+                    Some(Pitch::D) => Some(Pitch::Eb),
+                    Some(Pitch::Eb) => Some(Pitch::D),
+                    Some(Pitch::F) => Some(Pitch::Gb),
+                    Some(Pitch::Gb) => Some(Pitch::F),
+                    Some(Pitch::G) => Some(Pitch::Ab),
+                    Some(Pitch::Ab) => Some(Pitch::G),
+                    Some(Pitch::A) => Some(Pitch::Bb),
+                    Some(Pitch::Bb) => Some(Pitch::A),
+                    _ => return Err(ComposerError::InvalidKey(offset)),
+                };
+            }
+            key if key.is_whitespace() => {}
+            _ => return Err(ComposerError::InvalidKey(offset)),
+        }
+    }
+
+    if notes.is_empty() {
+        return Err(ComposerError::NoNotes);
+    }
+
+    let mut ringtone = Ringtone {
+        name: UNTITLED.to_owned(),
+        settings: Settings::default(),
+        notes,
+    };
+    ringtone.compact();
+
+    Ok(ringtone)
+}
+
+/// The key for the note, and whether it needs `#` to make it sharp.
+fn key(pitch: Pitch) -> (char, bool) {
+    match pitch {
+        Pitch::C => ('1', false),
+        Pitch::Db => ('1', true),
+        Pitch::D => ('2', false),
+        Pitch::Eb => ('2', true),
+        Pitch::E => ('3', false),
+        Pitch::F => ('4', false),
+        Pitch::Gb => ('4', true),
+        Pitch::G => ('5', false),
+        Pitch::Ab => ('5', true),
+        Pitch::A => ('6', false),
+        Pitch::Bb => ('6', true),
+        Pitch::B => ('7', false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_composer() {
+        let ringtone =
+            Ringtone::parse("Nokia:d=8,o=4,b=125,l=1:e5,d5,4f#,4g#,c#5,b,4d,4e,2c6,1p,32p")
+                .expect("successful parse");
+
+        assert_eq!(
+            write(&ringtone).expect("successful write"),
+            "3*8 2 4#**9 5# 1#*8 7** 29 3 1**9 09 088888 \
+             3**99 2 4#**9 5# 1#*8 7** 29 3 1**9 09 088888"
+        );
+    }
+
+    #[test]
+    fn test_write_composer_errors() {
+        let unsupported = |rtttl| {
+            write(&Ringtone::parse(rtttl).expect("successful parse")).expect_err("failed write")
+        };
+
+        assert_eq!(
+            unsupported("Low:d=4,o=4,b=125:c,c3"),
+            ComposerError::UnsupportedOctave(1)
+        );
+        assert_eq!(
+            unsupported("High:d=4,o=4,b=125:c,c7"),
+            ComposerError::UnsupportedOctave(1)
+        );
+        assert_eq!(
+            unsupported("Dotted:d=4,o=4,b=125:c,c."),
+            ComposerError::UnsupportedDuration(1)
+        );
+        assert_eq!(
+            unsupported("Triplet:d=4,o=4,b=125:12c"),
+            ComposerError::UnsupportedDuration(0)
+        );
+    }
+
+    #[test]
+    fn test_read_composer() {
+        // Each note takes the duration and octave of the one before it, and the keys after it
+        // change them.
+        let ringtone =
+            read("3*8 2 4#*9 5# 1#* 7**\n2 3 0 6###999999 1*** 8").expect("successful read");
+
+        assert_eq!(
+            ringtone.to_string(),
+            "Untitled:d=4,o=6,b=120:8e5,8d5,f#,g#,c#4,b,d,e,p,1a#,2c"
+        );
+
+        let written =
+            Ringtone::parse("Untitled:d=8,o=5,b=120:c,d#,4e,32p,a6,2g4").expect("successful parse");
+        let read = read(&write(&written).expect("successful write")).expect("successful read");
+
+        assert_eq!(read, written);
+    }
+
+    #[test]
+    fn test_read_composer_errors() {
+        assert_eq!(read("#1"), Err(ComposerError::InvalidKey(0)));
+        assert_eq!(read("1 3#"), Err(ComposerError::InvalidKey(3)));
+        assert_eq!(read("1 0#"), Err(ComposerError::InvalidKey(3)));
+        assert_eq!(read("1 2 c"), Err(ComposerError::InvalidKey(4)));
+        assert_eq!(read("89* "), Err(ComposerError::NoNotes));
+    }
+}
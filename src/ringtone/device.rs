@@ -0,0 +1,321 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use super::*;
+
+/// The tempos that Nokia phones can play, in beats per minute. These are the 32 values of the
+/// tempo instruction in the Smart Messaging format.
+pub const NOKIA_TEMPOS: [Tempo; 32] = [
+    25, 28, 31, 35, 40, 45, 50, 56, 63, 70, 80, 90, 100, 112, 125, 140, 160, 180, 200, 225, 250,
+    285, 320, 355, 400, 450, 500, 565, 635, 715, 800, 900,
+];
+
+/// The limits of a device which plays ringtones.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceProfile {
+    /// A name for the device, for reporting.
+    pub name: String,
+
+    /// The octaves that the device can play.
+    pub octaves: RangeInclusive<Octave>,
+
+    /// The tempos that the device can play, or `None` for any tempo.
+    pub tempos: Option<Vec<Tempo>>,
+
+    /// The longest ringtone name that the device will accept, in characters.
+    pub max_name_length: Option<usize>,
+
+    /// The most notes that the device will store, including silences.
+    pub max_notes: Option<usize>,
+}
+
+/// A change made by `Ringtone::fit` to make the ringtone playable on a device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Adjustment {
+    /// The name was cut down to the device's maximum length.
+    NameShortened { from: String, to: String },
+    /// The tempo was snapped to the nearest one that the device can play.
+    TempoSnapped { from: Tempo, to: Tempo },
+    /// Notes past the device's maximum were dropped.
+    NotesTruncated { from: usize, to: usize },
+    /// The whole ringtone was moved up (positive) or down (negative) by this many octaves.
+    Transposed { octaves: i8 },
+    /// The melody spans more octaves than the device has, so this note was moved into range
+    /// separately. The index is into the adjusted notes.
+    NoteClamped {
+        note: usize,
+        from: Octave,
+        to: Octave,
+    },
+}
+
+impl DeviceProfile {
+    /// The original Nokia phones, which RTTTL was designed for.
+    pub fn nokia() -> DeviceProfile {
+        DeviceProfile {
+            name: "Nokia".to_owned(),
+            octaves: Octave::O4..=Octave::O7,
+            tempos: Some(NOKIA_TEMPOS.to_vec()),
+            max_name_length: Some(10),
+            max_notes: Some(50),
+        }
+    }
+
+    /// A device with no limits, for players that accept everything we can parse.
+    pub fn unrestricted() -> DeviceProfile {
+        DeviceProfile {
+            name: "Unrestricted".to_owned(),
+            octaves: Octave::O0..=Octave::O9,
+            tempos: None,
+            max_name_length: None,
+            max_notes: None,
+        }
+    }
+
+    pub fn supports_tempo(&self, tempo: Tempo) -> bool {
+        self.tempos
+            .as_ref()
+            .is_none_or(|tempos| tempos.contains(&tempo))
+    }
+
+    /// The supported tempo closest to the given one. Ties go to the slower tempo.
+    pub fn nearest_tempo(&self, tempo: Tempo) -> Tempo {
+        let Some(tempos) = &self.tempos else {
+            return tempo;
+        };
+
+        tempos
+            .iter()
+            .copied()
+            .min_by_key(|&t| (t.abs_diff(tempo), t))
+            .unwrap_or(tempo)
+    }
+}
+
+/// Adjusts the ringtone to fit the device profile, returning each change that was made.
+pub(super) fn fit(ringtone: &mut Ringtone, profile: &DeviceProfile) -> Vec<Adjustment> {
+    let mut adjustments = Vec::new();
+
+    // Keep at least one character, since RTTTL needs a name.
+    if let Some(max) = profile.max_name_length.map(|max| max.max(1)) {
+        if ringtone.name.chars().count() > max {
+            let from = std::mem::take(&mut ringtone.name);
+            let shorten = |name: &str| -> String {
+                let to: String = name.trim_start().chars().take(max).collect();
+                to.trim_end().to_owned()
+            };
+
+            // A name that's all spaces has nothing to keep.
+            ringtone.name = Some(shorten(&from))
+                .filter(|to| !to.is_empty())
+                .unwrap_or_else(|| shorten(UNTITLED));
+            adjustments.push(Adjustment::NameShortened {
+                from,
+                to: ringtone.name.clone(),
+            });
+        }
+    }
+
+    let tempo = profile.nearest_tempo(ringtone.settings.tempo);
+    if tempo != ringtone.settings.tempo {
+        adjustments.push(Adjustment::TempoSnapped {
+            from: ringtone.settings.tempo,
+            to: tempo,
+        });
+        ringtone.settings.tempo = tempo;
+    }
+
+    // Keep at least one note, so the ringtone is still valid with a nonsensical profile.
+    if let Some(max) = profile.max_notes.map(|max| max.max(1)) {
+        if ringtone.notes.len() > max {
+            adjustments.push(Adjustment::NotesTruncated {
+                from: ringtone.notes.len(),
+                to: max,
+            });
+            ringtone.notes.truncate(max);
+        }
+    }
+
+    transpose(ringtone, profile, &mut adjustments);
+
+    adjustments
+}
+
+/// Moves the notes into the device's octave range. If the melody fits in the range, it's moved as
+/// a whole so it stays in tune with itself. Otherwise, the notes that still stick out are clamped
+/// to the nearest octave in range.
+fn transpose(ringtone: &mut Ringtone, profile: &DeviceProfile, adjustments: &mut Vec<Adjustment>) {
+    let low = profile.octaves.start().number() as i8;
+    let high = profile.octaves.end().number() as i8;
+    let default = ringtone.settings.octave;
+
+    let octaves: Vec<i8> = ringtone
+        .notes
+        .iter()
+        .filter(|note| note.pitch.is_some())
+        .map(|note| note.octave.unwrap_or(default).number() as i8)
+        .collect();
+
+    let (Some(&lowest), Some(&highest)) = (octaves.iter().min(), octaves.iter().max()) else {
+        return;
+    };
+
+    let shift = if lowest < low {
+        // Move up as far as needed, but no further than fits under the top of the range.
+        (low - lowest).min((high - highest).max(0))
+    } else if highest > high {
+        (high - highest).max((low - lowest).min(0))
+    } else {
+        0
+    };
+
+    let clamp = |octave: Octave, shift: i8| {
+        let shifted = (octave.number() as i8 + shift).clamp(low, high);
+        Octave::from_number(shifted as u8).expect("octave in range")
+    };
+
+    if shift != 0 {
+        adjustments.push(Adjustment::Transposed { octaves: shift });
+    }
+
+    let new_default = clamp(default, shift);
+
+    for (i, note) in ringtone.notes.iter_mut().enumerate() {
+        let from = note.octave.unwrap_or(default);
+        let shifted = from.number() as i8 + shift;
+        let to = clamp(from, shift);
+
+        if note.pitch.is_some() && to.number() as i8 != shifted {
+            adjustments.push(Adjustment::NoteClamped { note: i, from, to });
+        }
+
+        // Notes that used the default keep doing so if they still can, but rests never need
+        // an octave.
+        note.octave = match note.octave {
+            None if to == new_default || note.pitch.is_none() => None,
+            _ => Some(to),
+        };
+    }
+
+    ringtone.settings.octave = new_default;
+}
+
+impl fmt::Display for Adjustment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Adjustment::NameShortened { from, to } => {
+                write!(f, "shortened name from \"{from}\" to \"{to}\"")
+            }
+            Adjustment::TempoSnapped { from, to } => {
+                write!(f, "changed tempo from {from} to {to}")
+            }
+            Adjustment::NotesTruncated { from, to } => {
+                write!(f, "dropped the last {} of {from} notes", from - to)
+            }
+            Adjustment::Transposed { octaves } if *octaves > 0 => {
+                write!(f, "transposed up {octaves} octave(s)")
+            }
+            Adjustment::Transposed { octaves } => {
+                write!(f, "transposed down {} octave(s)", -octaves)
+            }
+            Adjustment::NoteClamped { note, from, to } => {
+                write!(f, "moved note {} from octave {from} to {to}", note + 1)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearest_tempo() {
+        let nokia = DeviceProfile::nokia();
+
+        assert_eq!(nokia.nearest_tempo(120), 125);
+        assert_eq!(nokia.nearest_tempo(118), 112);
+        assert_eq!(nokia.nearest_tempo(10), 25);
+        assert_eq!(nokia.nearest_tempo(2000), 900);
+        assert_eq!(DeviceProfile::unrestricted().nearest_tempo(120), 120);
+    }
+
+    #[test]
+    fn test_fit() {
+        let mut ringtone = Ringtone::parse("Much Too Long Name:d=4,o=3,b=120:c,e,g,p,c4,8p,e,g")
+            .expect("successful parse");
+        let profile = DeviceProfile {
+            max_notes: Some(6),
+            ..DeviceProfile::nokia()
+        };
+
+        let adjustments = ringtone.fit(&profile);
+
+        assert_eq!(
+            ringtone.to_rtttl(),
+            "Much Too L:d=4,o=4,b=125:c,e,g,p,c5,8p"
+        );
+        assert_eq!(
+            adjustments,
+            [
+                Adjustment::NameShortened {
+                    from: "Much Too Long Name".to_owned(),
+                    to: "Much Too L".to_owned()
+                },
+                Adjustment::TempoSnapped { from: 120, to: 125 },
+                Adjustment::NotesTruncated { from: 8, to: 6 },
+                Adjustment::Transposed { octaves: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fit_name() {
+        let fit_name = |name: &str, max| {
+            let mut ringtone =
+                Ringtone::parse(&format!("{name}:d=4,o=5,b=125:c")).expect("successful parse");
+            let profile = DeviceProfile {
+                max_name_length: Some(max),
+                ..DeviceProfile::nokia()
+            };
+            ringtone.fit(&profile);
+
+            let rtttl = ringtone.to_rtttl();
+            assert_eq!(Ringtone::parse(&rtttl), Ok(ringtone.clone()), "{rtttl}");
+            ringtone.name
+        };
+
+        assert_eq!(fit_name("          Song", 10), "Song");
+        assert_eq!(fit_name("            ", 10), "Untitled");
+        assert_eq!(fit_name("Song", 0), "S");
+        assert_eq!(fit_name("  ", 0), "U");
+    }
+
+    #[test]
+    fn test_fit_clamped() {
+        let mut ringtone = Ringtone::parse("Wide:d=4,o=5,b=100:c3,c,c8").expect("successful parse");
+
+        let adjustments = ringtone.fit(&DeviceProfile::nokia());
+
+        assert_eq!(ringtone.to_rtttl(), "Wide:d=4,o=5,b=100:c4,c,c7");
+        assert_eq!(
+            adjustments,
+            [
+                Adjustment::NoteClamped {
+                    note: 0,
+                    from: Octave::O3,
+                    to: Octave::O4
+                },
+                Adjustment::NoteClamped {
+                    note: 2,
+                    from: Octave::O8,
+                    to: Octave::O7
+                },
+            ]
+        );
+
+        let fitted = ringtone.clone();
+        assert!(ringtone.fit(&DeviceProfile::nokia()).is_empty());
+        assert_eq!(ringtone, fitted);
+    }
+}
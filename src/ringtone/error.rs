@@ -0,0 +1,548 @@
+use std::fmt;
+
+use nom::error::{VerboseError, VerboseErrorKind};
+
+use super::{Octave, Tempo};
+
+/// The section of the ringtone that was being parsed when an error occurred.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section {
+    /// The freeform name before the first `:`.
+    Name,
+    /// The `d=`, `o=`, `b=` and other settings between the two `:` separators.
+    Settings,
+    /// The comma separated list of notes.
+    Notes,
+}
+
+impl Section {
+    /// The parser labels each section with a context, which we map back to the enum here.
+    pub(super) const NAME: &'static str = "name section";
+    pub(super) const SETTINGS: &'static str = "settings section";
+    pub(super) const NOTES: &'static str = "notes section";
+
+    fn from_context(context: &str) -> Option<Section> {
+        match context {
+            Section::NAME => Some(Section::Name),
+            Section::SETTINGS => Some(Section::Settings),
+            Section::NOTES => Some(Section::Notes),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Section::Name => f.write_str("name"),
+            Section::Settings => f.write_str("settings"),
+            Section::Notes => f.write_str("notes"),
+        }
+    }
+}
+
+/// A failure to parse a ringtone, with enough information to point the user at the problem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RtttlError {
+    offset: usize,
+    line: usize,
+    column: usize,
+    section: Option<Section>,
+    expected: Option<&'static str>,
+    snippet: String,
+}
+
+impl RtttlError {
+    /// Builds the error from the nom error trace. The first entry in the trace is the innermost
+    /// failure, so that's where we point. The contexts wrapping it go from most to least specific,
+    /// which gives us what was expected and which section we were in.
+    pub(super) fn from_verbose(input: &str, e: VerboseError<&str>) -> RtttlError {
+        let offset = e
+            .errors
+            .first()
+            .map_or(0, |(rest, _)| input.len() - rest.len());
+
+        let mut contexts = e.errors.iter().filter_map(|(_, kind)| match kind {
+            VerboseErrorKind::Context(context) => Some(*context),
+            _ => None,
+        });
+
+        let expected = contexts
+            .clone()
+            .find(|context| Section::from_context(context).is_none());
+        let section = contexts.find_map(Section::from_context);
+
+        RtttlError::new(input, offset, section, expected)
+    }
+
+    pub(super) fn new(
+        input: &str,
+        offset: usize,
+        section: Option<Section>,
+        expected: Option<&'static str>,
+    ) -> RtttlError {
+        let (line, column) = line_column(input, offset);
+        let line_start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[offset..]
+            .find('\n')
+            .map_or(input.len(), |i| offset + i);
+
+        let source_line = input[line_start..line_end].trim_end_matches('\r');
+        let snippet = format!("{source_line}\n{:>column$}", "^");
+
+        RtttlError {
+            offset,
+            line,
+            column,
+            section,
+            expected,
+            snippet,
+        }
+    }
+
+    /// Byte offset into the input where parsing failed.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Line number (1-based) where parsing failed.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Column (1-based, in characters) where parsing failed.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// The section of the ringtone being parsed, if known.
+    pub fn section(&self) -> Option<Section> {
+        self.section
+    }
+
+    /// A description of what the parser expected to find, e.g. `pitch a-g or p`.
+    pub fn expected(&self) -> Option<&'static str> {
+        self.expected
+    }
+
+    /// The offending line of the input, with a caret underneath pointing at the failure.
+    pub fn snippet(&self) -> &str {
+        &self.snippet
+    }
+}
+
+impl fmt::Display for RtttlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.expected {
+            Some(expected) => write!(f, "expected {expected}")?,
+            None => f.write_str("invalid ringtone")?,
+        }
+
+        if let Some(section) = self.section {
+            write!(f, " in {section}")?;
+        }
+
+        write!(
+            f,
+            " at line {}, column {}:\n{}",
+            self.line, self.column, self.snippet
+        )
+    }
+}
+
+impl std::error::Error for RtttlError {}
+
+/// Line and column of a byte offset into the input. Both are 1-based, and the column counts
+/// characters rather than bytes.
+pub(super) fn line_column(input: &str, offset: usize) -> (usize, usize) {
+    let line_start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = input[..offset].matches('\n').count() + 1;
+    let column = input[line_start..offset].chars().count() + 1;
+
+    (line, column)
+}
+
+/// A problem that the lenient parser worked around. See `ParseOptions::lenient`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseWarning {
+    offset: usize,
+    line: usize,
+    column: usize,
+    kind: WarningKind,
+}
+
+/// The kinds of problem that the lenient parser can work around.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    /// There was no name section, so the ringtone was given a placeholder name.
+    MissingName,
+    /// There was no settings section, so the defaults were used.
+    MissingSettings,
+    /// A setting or note was in uppercase.
+    Uppercase,
+    /// A note was written with a `b` flat suffix, and was respelled as a sharp.
+    FlatSuffix,
+    /// An empty item between commas, or a trailing comma, was skipped.
+    EmptyItem,
+    /// A line break in the middle of the name or a note was removed.
+    LineBreak,
+    /// A setting couldn't be understood, and was skipped.
+    InvalidSetting,
+    /// A note couldn't be understood, and was skipped.
+    InvalidNote,
+}
+
+impl ParseWarning {
+    pub(super) fn new(input: &str, offset: usize, kind: WarningKind) -> ParseWarning {
+        let (line, column) = line_column(input, offset);
+
+        ParseWarning {
+            offset,
+            line,
+            column,
+            kind,
+        }
+    }
+
+    /// Byte offset into the input of the problem.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Line number (1-based) of the problem.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Column (1-based, in characters) of the problem.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn kind(&self) -> WarningKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WarningKind::MissingName => f.write_str("missing name"),
+            WarningKind::MissingSettings => f.write_str("missing settings, using defaults"),
+            WarningKind::Uppercase => f.write_str("uppercase converted to lowercase"),
+            WarningKind::FlatSuffix => f.write_str("flat respelled as sharp"),
+            WarningKind::EmptyItem => f.write_str("empty item skipped"),
+            WarningKind::LineBreak => f.write_str("line break removed"),
+            WarningKind::InvalidSetting => f.write_str("invalid setting skipped"),
+            WarningKind::InvalidNote => f.write_str("invalid note skipped"),
+        }
+    }
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.kind, self.line, self.column
+        )
+    }
+}
+
+/// A ringtone built in code that couldn't be written out as RTTTL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// The name is empty. RTTTL needs at least one character before the first `:`.
+    EmptyName,
+    /// The name contains a `:`, which would be read back as the end of the name.
+    InvalidName,
+    /// There are no notes. RTTTL needs at least one note or silence.
+    NoNotes,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::EmptyName => f.write_str("ringtone name is empty"),
+            BuildError::InvalidName => f.write_str("ringtone name contains ':'"),
+            BuildError::NoNotes => f.write_str("ringtone has no notes"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// A Standard MIDI File that couldn't be imported, or a ringtone that couldn't be exported as one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiError {
+    /// The data doesn't start with a MIDI header.
+    NotMidi,
+    /// The file ends part way through a chunk or event.
+    Truncated,
+    /// An event that isn't valid in a MIDI file.
+    InvalidEvent,
+    /// The file is timed in SMPTE frames rather than ticks per quarter note.
+    UnsupportedTiming,
+    /// The requested track isn't in the file.
+    NoSuchTrack(usize),
+    /// There are no notes to import from the selected track and channel.
+    NoNotes,
+    /// The note at this index is above G9, the highest MIDI key.
+    KeyOutOfRange(usize),
+}
+
+impl fmt::Display for MidiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiError::NotMidi => f.write_str("not a MIDI file"),
+            MidiError::Truncated => f.write_str("MIDI file is truncated"),
+            MidiError::InvalidEvent => f.write_str("MIDI file has an invalid event"),
+            MidiError::UnsupportedTiming => f.write_str("SMPTE timing is not supported"),
+            MidiError::NoSuchTrack(track) => write!(f, "MIDI file has no track {track}"),
+            MidiError::NoNotes => f.write_str("no notes to import"),
+            MidiError::KeyOutOfRange(i) => {
+                write!(f, "note {} is above G9, the highest MIDI key", i + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MidiError {}
+
+/// A ringtone that couldn't be converted to or from a Nokia Smart Messaging payload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OtaError {
+    /// The name is longer than the 15 characters the format allows.
+    NameTooLong,
+    /// The name has characters outside Latin-1, or a `:` that RTTTL can't have in a name.
+    InvalidName,
+    /// The tempo isn't one of `NOKIA_TEMPOS`. `Ringtone::fit` can snap it to one.
+    UnsupportedTempo(Tempo),
+    /// The octave is outside 4-7, which are the only ones the format has.
+    UnsupportedOctave(Octave),
+    /// The note at this index has a duration the format can't express: a 64th note, or a dotted
+    /// triplet.
+    UnsupportedDuration(usize),
+    /// The loop count is more than the format's maximum of 15.
+    TooManyLoops,
+    /// The ringtone needs more than the 255 instructions that fit in a pattern.
+    TooManyNotes,
+    /// The payload ends part way through.
+    Truncated,
+    /// The payload doesn't start with the ringing tone programming and sound commands.
+    InvalidHeader,
+    /// The song isn't a basic song, which is the only type that holds a ringtone.
+    UnsupportedSongType,
+    /// An instruction, or a value in one, that isn't in the format.
+    InvalidInstruction,
+    /// A pattern repeats an earlier pattern which doesn't exist.
+    UndefinedPattern,
+    /// Something valid in the format that RTTTL can't express, such as a double dotted note or a
+    /// tempo change part way through.
+    Unrepresentable,
+    /// The payload has no notes.
+    NoNotes,
+    /// There's data after the end of the payload.
+    TrailingData,
+}
+
+impl fmt::Display for OtaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OtaError::NameTooLong => f.write_str("name is longer than 15 characters"),
+            OtaError::InvalidName => f.write_str("name has characters that can't be encoded"),
+            OtaError::UnsupportedTempo(tempo) => write!(f, "tempo {tempo} isn't a Nokia tempo"),
+            OtaError::UnsupportedOctave(octave) => write!(f, "octave {octave} is outside 4-7"),
+            OtaError::UnsupportedDuration(i) => {
+                write!(f, "note {} has a duration that can't be encoded", i + 1)
+            }
+            OtaError::TooManyLoops => f.write_str("loop count is more than 15"),
+            OtaError::TooManyNotes => f.write_str("ringtone has too many notes"),
+            OtaError::Truncated => f.write_str("ringtone payload is truncated"),
+            OtaError::InvalidHeader => f.write_str("not a ringing tone programming payload"),
+            OtaError::UnsupportedSongType => f.write_str("song type isn't supported"),
+            OtaError::InvalidInstruction => {
+                f.write_str("ringtone payload has an invalid instruction")
+            }
+            OtaError::UndefinedPattern => f.write_str("pattern repeats an undefined pattern"),
+            OtaError::Unrepresentable => f.write_str("ringtone payload can't be written as RTTTL"),
+            OtaError::NoNotes => f.write_str("ringtone payload has no notes"),
+            OtaError::TrailingData => f.write_str("data after the end of the ringtone payload"),
+        }
+    }
+}
+
+impl std::error::Error for OtaError {}
+
+/// A ringtone that couldn't be converted to or from iMelody.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImelodyError {
+    /// A required line is missing: `BEGIN:IMELODY`, `MELODY` or `END:IMELODY`.
+    MissingField(&'static str),
+    /// A header line has a value that iMelody doesn't allow, e.g. `BEAT:fast`.
+    InvalidField(&'static str),
+    /// Something in the melody that isn't a note, rest or other iMelody item, at this byte offset
+    /// into the melody.
+    InvalidMelody(usize),
+    /// Valid iMelody that RTTTL can't express, such as a double dotted note or a repeat that
+    /// goes on forever, at this byte offset into the melody.
+    Unrepresentable(usize),
+    /// The melody has no notes.
+    NoNotes,
+    /// The tempo is outside the 25-900 beats per minute that iMelody allows.
+    UnsupportedTempo(Tempo),
+    /// Octave 0 is below the lowest iMelody octave.
+    UnsupportedOctave(Octave),
+    /// The note at this index has a duration iMelody can't express: a 64th note, or a dotted
+    /// triplet.
+    UnsupportedDuration(usize),
+}
+
+impl fmt::Display for ImelodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImelodyError::MissingField(field) => write!(f, "iMelody is missing {field}"),
+            ImelodyError::InvalidField(field) => write!(f, "iMelody has an invalid {field}"),
+            ImelodyError::InvalidMelody(offset) => {
+                write!(f, "invalid iMelody melody at offset {offset}")
+            }
+            ImelodyError::Unrepresentable(offset) => {
+                write!(
+                    f,
+                    "iMelody melody at offset {offset} can't be written as RTTTL"
+                )
+            }
+            ImelodyError::NoNotes => f.write_str("iMelody melody has no notes"),
+            ImelodyError::UnsupportedTempo(tempo) => {
+                write!(f, "tempo {tempo} is outside the iMelody range 25-900")
+            }
+            ImelodyError::UnsupportedOctave(octave) => {
+                write!(f, "octave {octave} is below the iMelody range")
+            }
+            ImelodyError::UnsupportedDuration(i) => {
+                write!(
+                    f,
+                    "note {} has a duration that iMelody can't express",
+                    i + 1
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImelodyError {}
+
+/// An ABC tune that couldn't be imported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AbcError {
+    /// There's no `K:` field, which ends the header and starts the music.
+    MissingKey,
+    /// A field on this line has a value that couldn't be understood, e.g. `L:eighth`.
+    InvalidField { field: char, line: usize },
+    /// Something in the music that isn't ABC, at this line and column.
+    InvalidMusic { line: usize, column: usize },
+    /// The note at this line and column is outside RTTTL's octaves.
+    OctaveOutOfRange { line: usize, column: usize },
+    /// The tune has no notes.
+    NoNotes,
+}
+
+impl fmt::Display for AbcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AbcError::MissingKey => f.write_str("ABC tune has no K: field"),
+            AbcError::InvalidField { field, line } => {
+                write!(f, "invalid ABC {field}: field on line {line}")
+            }
+            AbcError::InvalidMusic { line, column } => {
+                write!(f, "invalid ABC music at {line}:{column}")
+            }
+            AbcError::OctaveOutOfRange { line, column } => {
+                write!(
+                    f,
+                    "ABC note at {line}:{column} is outside the RTTTL octaves"
+                )
+            }
+            AbcError::NoNotes => f.write_str("ABC tune has no notes"),
+        }
+    }
+}
+
+impl std::error::Error for AbcError {}
+
+/// An MML string that couldn't be imported, or a ringtone that can't be written as MML.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MmlError {
+    /// Something that isn't an MML command, at this byte offset.
+    InvalidCommand(usize),
+    /// A command with a missing or out of range number, at this byte offset.
+    InvalidValue(usize),
+    /// Valid MML that RTTTL can't express, at this byte offset: a length such as `L5`, a double
+    /// dotted note, or a change of tempo or style after the first note.
+    Unrepresentable(usize),
+    /// There are no notes.
+    NoNotes,
+    /// The tempo is outside the 32 to 255 that BASIC allows.
+    UnsupportedTempo(Tempo),
+    /// The note at this index is a 96th triplet, which is shorter than MML's shortest length.
+    UnsupportedDuration(usize),
+}
+
+impl fmt::Display for MmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MmlError::InvalidCommand(offset) => write!(f, "invalid MML command at offset {offset}"),
+            MmlError::InvalidValue(offset) => write!(f, "invalid MML value at offset {offset}"),
+            MmlError::Unrepresentable(offset) => {
+                write!(f, "MML at offset {offset} can't be written as RTTTL")
+            }
+            MmlError::NoNotes => f.write_str("MML has no notes"),
+            MmlError::UnsupportedTempo(tempo) => {
+                write!(f, "tempo {tempo} is outside MML's range of 32 to 255")
+            }
+            MmlError::UnsupportedDuration(i) => {
+                write!(f, "note {} is shorter than MML's shortest length", i + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MmlError {}
+
+/// Nokia Composer keys that couldn't be imported, or a ringtone that can't be entered in the
+/// Composer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComposerError {
+    /// A key that isn't on the keypad, or a `#` that can't go on the note before it, at this byte
+    /// offset.
+    InvalidKey(usize),
+    /// There are no notes.
+    NoNotes,
+    /// The note at this index is outside the Composer's three octaves, RTTTL's 4 to 6.
+    UnsupportedOctave(usize),
+    /// The note at this index is dotted, a triplet or a 64th note, which the Composer doesn't have.
+    UnsupportedDuration(usize),
+}
+
+impl fmt::Display for ComposerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComposerError::InvalidKey(offset) => {
+                write!(f, "invalid Composer key at offset {offset}")
+            }
+            ComposerError::NoNotes => f.write_str("Composer keys have no notes"),
+            ComposerError::UnsupportedOctave(i) => {
+                write!(f, "note {} is outside the Composer's octaves", i + 1)
+            }
+            ComposerError::UnsupportedDuration(i) => {
+                write!(
+                    f,
+                    "note {} has a duration that the Composer can't enter",
+                    i + 1
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ComposerError {}
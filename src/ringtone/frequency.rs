@@ -10,16 +10,11 @@ const FREQ_A4: f32 = 440.0;
 /// temperament.
 ///
 /// https://en.wikipedia.org/wiki/12_equal_temperament
-const STEP: f32 = 1.059463094359;
+const STEP: f32 = 1.059_463_1;
 
 pub(super) fn fundamental(pitch: Pitch, octave: Octave) -> f32 {
-    // Each octave shift doubles the frequency.
-    let octave_multiplier = match octave {
-        Octave::O4 => 1.0,
-        Octave::O5 => 2.0,
-        Octave::O6 => 4.0,
-        Octave::O7 => 8.0,
-    };
+    // Each octave shift doubles the frequency, relative to the octave containing A4.
+    let octave_multiplier = 2.0f32.powi(octave.number() as i32 - 4);
 
     // Each pitch shift is a step in the chromatic scale, so we multiply by a power of the step
     // multiplier.
@@ -71,5 +66,12 @@ mod tests {
         check(fundamental(Pitch::B, Octave::O6), 1975.53);
         check(fundamental(Pitch::D, Octave::O7), 2349.32);
         check(fundamental(Pitch::B, Octave::O5), 987.77);
+
+        // The octaves outside the original Nokia range.
+        check(fundamental(Pitch::A, Octave::O0), 27.5);
+        check(fundamental(Pitch::C, Octave::O1), 32.70);
+        check(fundamental(Pitch::G, Octave::O3), 196.0);
+        check(fundamental(Pitch::C, Octave::O8), 4186.01);
+        check(fundamental(Pitch::B, Octave::O9), 15804.27);
     }
 }
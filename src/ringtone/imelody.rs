@@ -0,0 +1,575 @@
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+use super::*;
+
+const BEGIN: &str = "BEGIN:IMELODY";
+const END: &str = "END:IMELODY";
+
+/// iMelody lines are folded at this many characters, continuing on a line that starts with a
+/// space.
+const LINE_LENGTH: usize = 75;
+
+const TEMPOS: RangeInclusive<Tempo> = 25..=900;
+
+/// The most times a repeat can play when it's written out, which keeps the melody to a sensible
+/// size.
+const REPEATS: RangeInclusive<u32> = 1..=256;
+
+/// Items in the melody that control the phone's lights and vibration, which don't affect the
+/// sound.
+const EFFECTS: [&str; 6] = ["ledon", "ledoff", "vibeon", "vibeoff", "backon", "backoff"];
+
+/// Writes the ringtone as an iMelody file.
+///
+/// iMelody octaves are numbered one lower than RTTTL's, so its default `*4` is RTTTL's `o=5`. The
+/// octave prefix is only written when it changes, and a ringtone with loops becomes a repeat of
+/// the whole melody.
+pub(super) fn write(ringtone: &Ringtone) -> Result<String, ImelodyError> {
+    let settings = &ringtone.settings;
+
+    if !TEMPOS.contains(&settings.tempo) {
+        return Err(ImelodyError::UnsupportedTempo(settings.tempo));
+    }
+
+    let mut melody = String::new();
+    let mut octave = Octave::O5;
+
+    for (i, note) in ringtone.notes.iter().enumerate() {
+        let duration = note.duration.unwrap_or(settings.duration);
+        let (code, triplet) = match duration_code(duration) {
+            Some((_, true)) if note.dotted => return Err(ImelodyError::UnsupportedDuration(i)),
+            Some(code) => code,
+            None => return Err(ImelodyError::UnsupportedDuration(i)),
+        };
+
+        match note.pitch {
+            Some(pitch) => {
+                let note_octave = note.octave.unwrap_or(settings.octave);
+                if note_octave != octave {
+                    let number = note_octave
+                        .number()
+                        .checked_sub(1)
+                        .ok_or(ImelodyError::UnsupportedOctave(note_octave))?;
+                    write!(melody, "*{number}").expect("write to string");
+                    octave = note_octave;
+                }
+
+                melody.push_str(pitch_name(pitch));
+            }
+            None => melody.push('r'),
+        }
+
+        write!(melody, "{code}").expect("write to string");
+
+        if note.dotted {
+            melody.push('.');
+        } else if triplet {
+            melody.push(';');
+        }
+    }
+
+    // The repeat count is how many times the melody plays in total, or 0 for forever.
+    if settings.loops == LOOP_FOREVER {
+        melody = format!("({melody}@0)");
+    } else if settings.loops > 0 {
+        melody = format!("({melody}@{})", settings.loops as u32 + 1);
+    }
+
+    let style = match settings.style {
+        Style::Natural => 0,
+        Style::Continuous => 1,
+        Style::Staccato => 2,
+    };
+
+    // A line break in the name would start another field.
+    let name = ringtone.name.replace(['\r', '\n'], " ");
+
+    let mut out = String::new();
+    for line in [
+        BEGIN.to_owned(),
+        "VERSION:1.2".to_owned(),
+        "FORMAT:CLASS1.0".to_owned(),
+        format!("NAME:{name}"),
+        format!("BEAT:{}", settings.tempo),
+        format!("STYLE:S{style}"),
+        format!("MELODY:{melody}"),
+        END.to_owned(),
+    ] {
+        fold(&mut out, &line);
+    }
+
+    Ok(out)
+}
+
+/// Reads an iMelody file.
+///
+/// The lights, vibration and volume are skipped, since they don't change the melody. Repeats are
+/// written out in full, unless a single repeat covers the whole melody, in which case it becomes
+/// the loop count. The default duration and octave are chosen by `Ringtone::compact`.
+pub(super) fn read(input: &str) -> Result<Ringtone, ImelodyError> {
+    let unfolded = input
+        .replace("\r\n", "\n")
+        .replace("\n ", "")
+        .replace("\n\t", "");
+    let mut lines = unfolded
+        .lines()
+        .map(str::trim_end)
+        .filter(|l| !l.is_empty());
+
+    if !lines.next().is_some_and(|l| l.eq_ignore_ascii_case(BEGIN)) {
+        return Err(ImelodyError::MissingField(BEGIN));
+    }
+
+    // iMelody's default style is natural, unlike RTTTL's.
+    let mut settings = Settings {
+        style: Style::Natural,
+        ..Settings::default()
+    };
+    let mut name = None;
+    let mut melody = None;
+    let mut ended = false;
+
+    for line in lines {
+        let (key, value) = line
+            .split_once(':')
+            .ok_or(ImelodyError::InvalidField("header line"))?;
+
+        // VERSION, FORMAT, COMPOSER, VOLUME and any extensions don't affect the melody.
+        match key.to_ascii_uppercase().as_str() {
+            "NAME" => name = Some(value),
+            "BEAT" => {
+                settings.tempo = value
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|tempo| TEMPOS.contains(tempo))
+                    .ok_or(ImelodyError::InvalidField("BEAT"))?;
+            }
+            "STYLE" => {
+                settings.style = match value.trim() {
+                    "S0" => Style::Natural,
+                    "S1" => Style::Continuous,
+                    "S2" => Style::Staccato,
+                    _ => return Err(ImelodyError::InvalidField("STYLE")),
+                };
+            }
+            "MELODY" => melody = Some(value),
+            "END" if value.eq_ignore_ascii_case("IMELODY") => {
+                ended = true;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    if !ended {
+        return Err(ImelodyError::MissingField(END));
+    }
+
+    let melody = melody.ok_or(ImelodyError::MissingField("MELODY"))?;
+    let (notes, loops) = parse_melody(melody)?;
+    settings.loops = loops;
+
+    let name = name
+        .map(|name| name.replace(':', "").trim().to_owned())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| UNTITLED.to_owned());
+
+    let mut ringtone = Ringtone {
+        name,
+        settings,
+        notes,
+    };
+    ringtone.compact();
+
+    Ok(ringtone)
+}
+
+/// A repeated part of the melody, as the range of notes, the number of times it plays, and the
+/// offset of the repeat count in the melody.
+struct Repeat {
+    notes: std::ops::Range<usize>,
+    count: u32,
+    offset: usize,
+}
+
+/// Parses the melody into notes with every field filled in, and the loop count.
+fn parse_melody(melody: &str) -> Result<(Vec<Note>, u8), ImelodyError> {
+    let bytes = melody.as_bytes();
+    let mut notes = Vec::new();
+    let mut repeats = Vec::new();
+    let mut repeat_start = None;
+    let mut octave = Octave::O5;
+    let mut i = 0;
+
+    let digits = |from: usize| {
+        bytes[from..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+
+    while i < bytes.len() {
+        let start = i;
+        let invalid = ImelodyError::InvalidMelody(start);
+
+        if let Some(effect) = EFFECTS
+            .iter()
+            .find(|effect| melody[i..].starts_with(*effect))
+        {
+            i += effect.len();
+            continue;
+        }
+
+        match bytes[i] {
+            b'*' => {
+                octave = match bytes.get(i + 1) {
+                    Some(&digit @ b'0'..=b'8') => Octave::from_number(digit - b'0' + 1),
+                    _ => None,
+                }
+                .ok_or(invalid)?;
+                i += 2;
+            }
+            // Repeats can't be nested.
+            b'(' if repeat_start.is_none() => {
+                repeat_start = Some(notes.len());
+                i += 1;
+            }
+            b'@' => {
+                let from = repeat_start.take().ok_or(invalid)?;
+                let len = digits(i + 1);
+                let count = melody[i + 1..i + 1 + len].parse().map_err(|_| invalid)?;
+                i += 1 + len;
+
+                // The repeat can change the volume each time round.
+                if melody[i..].starts_with("V+") || melody[i..].starts_with("V-") {
+                    i += 2;
+                }
+
+                if bytes.get(i) != Some(&b')') {
+                    return Err(ImelodyError::InvalidMelody(i));
+                }
+                i += 1;
+
+                repeats.push(Repeat {
+                    notes: from..notes.len(),
+                    count,
+                    offset: start + 1,
+                });
+            }
+            b'V' => {
+                i += match bytes.get(i + 1) {
+                    Some(b'+' | b'-') => 2,
+                    Some(b'0'..=b'9') => 1 + digits(i + 1),
+                    _ => return Err(invalid),
+                };
+            }
+            _ => {
+                let (pitch, len) = match (bytes[i], bytes.get(i + 1)) {
+                    (b'r', _) => (None, 1),
+                    (b'#', Some(&letter)) => (Some(sharp(letter).ok_or(invalid)?), 2),
+                    (b'&', Some(&letter)) => (Some(flat(letter).ok_or(invalid)?), 2),
+                    (letter, _) => (Some(natural(letter).ok_or(invalid)?), 1),
+                };
+                i += len;
+
+                let code = match bytes.get(i) {
+                    Some(&digit @ b'0'..=b'5') => digit - b'0',
+                    _ => return Err(invalid),
+                };
+                i += 1;
+
+                let specifier = bytes
+                    .get(i)
+                    .copied()
+                    .filter(|b| matches!(b, b'.' | b':' | b';'));
+                if specifier.is_some() {
+                    i += 1;
+                }
+
+                let (duration, dotted) = match specifier {
+                    None => (duration_from_code(code, false), false),
+                    Some(b'.') => (duration_from_code(code, false), true),
+                    Some(b';') => (duration_from_code(code, true), false),
+                    // Double dotted.
+                    _ => (None, false),
+                };
+                let duration = duration.ok_or(ImelodyError::Unrepresentable(start))?;
+
+                notes.push(Note {
+                    duration: Some(duration),
+                    pitch,
+                    octave: pitch.map(|_| octave),
+                    dotted,
+                });
+            }
+        }
+    }
+
+    if repeat_start.is_some() {
+        return Err(ImelodyError::InvalidMelody(bytes.len()));
+    }
+
+    if notes.is_empty() {
+        return Err(ImelodyError::NoNotes);
+    }
+
+    // A repeat of the whole melody is the loop count. A count of 0 repeats forever.
+    if let [repeat] = repeats.as_slice() {
+        if repeat.notes == (0..notes.len()) {
+            match repeat.count {
+                0 => return Ok((notes, LOOP_FOREVER)),
+                count if count <= LOOP_FOREVER as u32 => return Ok((notes, (count - 1) as u8)),
+                _ => {}
+            }
+        }
+    }
+
+    // Otherwise write out the repeats, from the last so the earlier ranges stay valid.
+    for repeat in repeats.iter().rev() {
+        if !REPEATS.contains(&repeat.count) {
+            return Err(ImelodyError::Unrepresentable(repeat.offset));
+        }
+
+        let part = notes[repeat.notes.clone()].to_vec();
+        let extra = part.repeat(repeat.count as usize - 1);
+        notes.splice(repeat.notes.end..repeat.notes.end, extra);
+    }
+
+    Ok((notes, 0))
+}
+
+/// Writes the line, folding it if it's too long.
+fn fold(out: &mut String, line: &str) {
+    let mut chars = line.chars().peekable();
+    let mut length = LINE_LENGTH;
+
+    loop {
+        out.extend(chars.by_ref().take(length));
+        out.push_str("\r\n");
+
+        if chars.peek().is_none() {
+            break;
+        }
+
+        // The space at the start of a continuation line counts towards its length.
+        out.push(' ');
+        length = LINE_LENGTH - 1;
+    }
+}
+
+/// The duration code, and whether it's a triplet (`;`, for two thirds of the length).
+fn duration_code(duration: Duration) -> Option<(u8, bool)> {
+    let code = match duration {
+        Duration::Whole => (0, false),
+        Duration::Half => (1, false),
+        Duration::HalfTriplet => (1, true),
+        Duration::Quarter => (2, false),
+        Duration::QuarterTriplet => (2, true),
+        Duration::Eighth => (3, false),
+        Duration::EighthTriplet => (3, true),
+        Duration::Sixteenth => (4, false),
+        Duration::SixteenthTriplet => (4, true),
+        Duration::ThirtySecond => (5, false),
+        Duration::ThirtySecondTriplet => (5, true),
+        Duration::SixtyFourth | Duration::SixtyFourthTriplet => return None,
+    };
+
+    Some(code)
+}
+
+fn duration_from_code(code: u8, triplet: bool) -> Option<Duration> {
+    (1..=96)
+        .filter_map(Duration::from_denominator)
+        .find(|&duration| duration_code(duration) == Some((code, triplet)))
+}
+
+/// iMelody writes sharps and flats before the letter.
+fn pitch_name(pitch: Pitch) -> &'static str {
+    match pitch {
+        Pitch::A => "a",
+        Pitch::Bb => "#a",
+        Pitch::B => "b",
+        Pitch::C => "c",
+        Pitch::Db => "#c",
+        Pitch::D => "d",
+        Pitch::Eb => "#d",
+        Pitch::E => "e",
+        Pitch::F => "f",
+        Pitch::Gb => "#f",
+        Pitch::G => "g",
+        Pitch::Ab => "#g",
+    }
+}
+
+fn natural(letter: u8) -> Option<Pitch> {
+    let pitch = match letter {
+        b'a' => Pitch::A,
+        b'b' => Pitch::B,
+        b'c' => Pitch::C,
+        b'd' => Pitch::D,
+        b'e' => Pitch::E,
+        b'f' => Pitch::F,
+        b'g' => Pitch::G,
+        _ => return None,
+    };
+
+    Some(pitch)
+}
+
+/// Only the sharps and flats of the black keys exist in iMelody, so there's no `#e` or `&c`.
+fn sharp(letter: u8) -> Option<Pitch> {
+    let pitch = match letter {
+        b'a' => Pitch::Bb,
+        b'c' => Pitch::Db,
+        b'd' => Pitch::Eb,
+        b'f' => Pitch::Gb,
+        b'g' => Pitch::Ab,
+        _ => return None,
+    };
+
+    Some(pitch)
+}
+
+fn flat(letter: u8) -> Option<Pitch> {
+    let pitch = match letter {
+        b'a' => Pitch::Ab,
+        b'b' => Pitch::Bb,
+        b'd' => Pitch::Db,
+        b'e' => Pitch::Eb,
+        b'g' => Pitch::Gb,
+        _ => return None,
+    };
+
+    Some(pitch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_imelody() {
+        let ringtone = Ringtone::parse("Hi:d=4,o=5,b=125,s=s,l=1:8c6,a#.,12p,f4,1g4")
+            .expect("successful parse");
+
+        assert_eq!(
+            write(&ringtone),
+            Ok("BEGIN:IMELODY\r\n\
+                VERSION:1.2\r\n\
+                FORMAT:CLASS1.0\r\n\
+                NAME:Hi\r\n\
+                BEAT:125\r\n\
+                STYLE:S2\r\n\
+                MELODY:(*5c3*4#a2.r3;*3f2g0@2)\r\n\
+                END:IMELODY\r\n"
+                .to_owned())
+        );
+
+        let mut ringtone = Ringtone::parse("Hi:d=4,o=5,b=125:c").expect("successful parse");
+        ringtone.name = "Two\r\nBEAT:900".to_owned();
+        let imelody = write(&ringtone).expect("successful write");
+        assert!(imelody.contains("\r\nNAME:Two  BEAT:900\r\nBEAT:125\r\n"));
+
+        let ringtone = Ringtone::parse("Slow:d=4,o=5,b=20:c").expect("successful parse");
+        assert_eq!(write(&ringtone), Err(ImelodyError::UnsupportedTempo(20)));
+
+        let ringtone = Ringtone::parse("Low:d=4,o=5,b=100:c,c0").expect("successful parse");
+        assert_eq!(
+            write(&ringtone),
+            Err(ImelodyError::UnsupportedOctave(Octave::O0))
+        );
+
+        let ringtone = Ringtone::parse("Short:d=4,o=5,b=100:c,64c").expect("successful parse");
+        assert_eq!(write(&ringtone), Err(ImelodyError::UnsupportedDuration(1)));
+    }
+
+    #[test]
+    fn test_read_imelody() {
+        let input = "\
+BEGIN:IMELODY\r
+VERSION:1.2\r
+FORMAT:CLASS1.0\r
+NAME:Tune\r
+COMPOSER:Someone\r
+BEAT:180\r
+VOLUME:V8\r
+MELODY:ledonc3d3vibeon&e3*5(c2@2V+)V-r3.\r
+ *3#f1;ledoff\r
+END:IMELODY\r
+";
+
+        let ringtone = read(input).expect("successful read");
+
+        assert_eq!(
+            ringtone.to_rtttl(),
+            "Tune:d=8,o=5,b=180,s=n:c,d,d#,4c6,4c6,p.,3f#4"
+        );
+    }
+
+    #[test]
+    fn test_imelody_round_trip() {
+        for input in [
+            "Hi:d=4,o=5,b=125,s=s,l=1:8c6,a#.,12p,f,1g",
+            "Nokia:d=8,o=5,b=180:e6,d6,4f#,4g#,c#6,b,4d,4e,b,a,c#,4e,2a",
+            "Range:d=16,o=9,b=900,s=c:c,32c1,2p.,1b,b",
+            "Forever:d=4,o=5,b=100,l=15:c,d",
+        ] {
+            let ringtone = Ringtone::parse(input).expect("successful parse");
+            let imelody = write(&ringtone).expect("successful write");
+
+            assert_eq!(read(&imelody), Ok(ringtone), "{input}");
+        }
+
+        // More plays than a loop count can give are written out.
+        let read_melody = |melody: &str| read(&format!("{BEGIN}\nMELODY:{melody}\n{END}\n"));
+        assert_eq!(
+            read_melody("(c2@16)").map(|r| r.to_rtttl()),
+            Ok(format!("Untitled:d=4,o=5,b=120,s=n:{}c", "c,".repeat(15)))
+        );
+
+        // Long melodies are folded.
+        let ringtone = Ringtone::parse(include_str!("../../examples/badinerie.txt").trim())
+            .expect("successful parse");
+        let imelody = write(&ringtone).expect("successful write");
+
+        assert!(imelody.lines().all(|line| line.len() <= LINE_LENGTH + 1));
+        assert_eq!(
+            read(&imelody).map(|r| r.to_rtttl()),
+            Ok(ringtone.to_rtttl())
+        );
+    }
+
+    #[test]
+    fn test_read_imelody_errors() {
+        let read = |melody: &str| read(&format!("{BEGIN}\nMELODY:{melody}\n{END}\n"));
+
+        assert_eq!(read("c2x"), Err(ImelodyError::InvalidMelody(2)));
+        assert_eq!(read("#e2"), Err(ImelodyError::InvalidMelody(0)));
+        assert_eq!(read("c2d:"), Err(ImelodyError::InvalidMelody(2)));
+        assert_eq!(read("c2d2:"), Err(ImelodyError::Unrepresentable(2)));
+        assert_eq!(read("c0;"), Err(ImelodyError::Unrepresentable(0)));
+        assert_eq!(read("(c2(d2@2)@2)"), Err(ImelodyError::InvalidMelody(3)));
+        assert_eq!(read("(c2@0)d2"), Err(ImelodyError::Unrepresentable(4)));
+        assert_eq!(
+            read("(c2d2@4000000000)e2"),
+            Err(ImelodyError::Unrepresentable(6))
+        );
+        assert_eq!(read("(c2@257)"), Err(ImelodyError::Unrepresentable(4)));
+        assert_eq!(read("ledon"), Err(ImelodyError::NoNotes));
+
+        assert_eq!(
+            super::read("MELODY:c2"),
+            Err(ImelodyError::MissingField(BEGIN))
+        );
+        assert_eq!(
+            super::read(&format!("{BEGIN}\nMELODY:c2\n")),
+            Err(ImelodyError::MissingField(END))
+        );
+        assert_eq!(
+            super::read(&format!("{BEGIN}\nBEAT:fast\nMELODY:c2\n{END}")),
+            Err(ImelodyError::InvalidField("BEAT"))
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use super::*;
 
 /// A note from the ringtone, in a form that can be played.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PlayedTone {
     /// A note with a frequency (Hz) and duration (seconds).
     Note { freq: f32, duration: f32 },
@@ -9,74 +10,173 @@ pub enum PlayedTone {
     Silence { duration: f32 },
 }
 
-/// Iterator over the tones in a ringtone, which applies the defaults and converts to the
-/// `PlayedTone` type.
-pub(super) struct PlayedNoteIter<'a, I>
+/// A note from the ringtone with the defaults and style applied, but still timed in ticks. The
+/// file formats use this, since they have their own idea of tempo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum TickedTone {
+    Note {
+        pitch: Pitch,
+        octave: Octave,
+        ticks: u32,
+    },
+    Silence {
+        ticks: u32,
+    },
+}
+
+/// Iterator over the tones in a ringtone, which applies the defaults and the style.
+pub(super) struct TickedToneIter<'a, I>
 where
     I: Iterator<Item = &'a Note>,
 {
-    /// The time step of a 32nd note, in seconds.
-    tempo_step: f32,
     /// Iterator over the notes in the ringtone.
     notes: I,
     /// The default settings for the ringtone.
     settings: &'a Settings,
+    /// The silent part of the last note, when the style doesn't let it sound for its whole
+    /// duration.
+    gap: Option<TickedTone>,
+}
+
+pub(super) fn iter<'a>(ringtone: &'a Ringtone) -> impl Iterator<Item = PlayedTone> + 'a {
+    let tempo = ringtone.settings.tempo as u32;
+
+    // The note length is an exact number of ticks, and the tempo is in quarter notes per minute.
+    // Only the final division is inexact.
+    let seconds = move |ticks: u32| (ticks * 60) as f32 / (TICKS_PER_QUARTER * tempo) as f32;
+
+    ticked(ringtone).map(move |tone| match tone {
+        TickedTone::Note {
+            pitch,
+            octave,
+            ticks,
+        } => PlayedTone::Note {
+            // Convert the pitch and octave to a frequency in Hz.
+            freq: frequency::fundamental(pitch, octave),
+            duration: seconds(ticks),
+        },
+        TickedTone::Silence { ticks } => PlayedTone::Silence {
+            duration: seconds(ticks),
+        },
+    })
 }
 
-pub(super) fn iter<'a>(
+pub(super) fn ticked<'a>(
     ringtone: &'a Ringtone,
-) -> PlayedNoteIter<'a, impl Iterator<Item = &'a Note>> {
-    // Tempo in quarter notes per minute, converted to a time step for a 32nd note.
-    let tempo_step = 60.0 / 8.0 / ringtone.settings.tempo as f32;
+) -> TickedToneIter<'a, impl Iterator<Item = &'a Note>> {
+    // The melody plays once, then repeats for the loop count.
+    let plays = ringtone.settings.plays();
 
-    PlayedNoteIter {
-        tempo_step,
-        notes: ringtone.notes.iter(),
+    TickedToneIter {
+        notes: std::iter::repeat_n(&ringtone.notes, plays).flatten(),
         settings: &ringtone.settings,
+        gap: None,
     }
 }
 
-impl<'a, I> Iterator for PlayedNoteIter<'a, I>
+impl<'a, I> Iterator for TickedToneIter<'a, I>
 where
     I: Iterator<Item = &'a Note>,
 {
-    type Item = PlayedTone;
+    type Item = TickedTone;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(gap) = self.gap.take() {
+            return Some(gap);
+        }
+
         self.notes.next().map(|note| {
             // Use the default octave if the note doesn't specify one.
             let octave = note.octave.unwrap_or(self.settings.octave);
+            let ticks = note.ticks(self.settings);
 
-            // Use the default duration if the note doesn't specify one. Convert each note to its
-            // time as a multiple of 32nd notes.
-            let time = match note.duration.unwrap_or(self.settings.duration) {
-                Duration::ThirtySecond => 1.0,
-                Duration::Sixteenth => 2.0,
-                Duration::Eighth => 4.0,
-                Duration::Quarter => 8.0,
-                Duration::Half => 16.0,
-                Duration::Whole => 32.0,
-            };
-
-            // Dotted notes are 1.5 times as long.
-            let time = if note.dotted { time * 1.5 } else { time };
-
-            // Convert the note to a `PlayedTone`, either a note or a silence depending on whether
+            // Convert the note to a `TickedTone`, either a note or a silence depending on whether
             // the note has a pitch.
             match note.pitch {
-                None => PlayedTone::Silence {
-                    duration: time * self.tempo_step,
-                },
+                None => TickedTone::Silence { ticks },
                 Some(pitch) => {
-                    // Convert the pitch and octave to a frequency in Hz.
-                    let freq = frequency::fundamental(pitch, octave);
+                    // The style decides how much of the note sounds, and the rest is played as a
+                    // silence after it.
+                    let (num, den) = self.settings.style.articulation();
+                    let sounding = ticks * num / den;
+                    if sounding < ticks {
+                        self.gap = Some(TickedTone::Silence {
+                            ticks: ticks - sounding,
+                        });
+                    }
 
-                    PlayedTone::Note {
-                        freq,
-                        duration: time * self.tempo_step,
+                    TickedTone::Note {
+                        pitch,
+                        octave,
+                        ticks: sounding,
                     }
                 }
             }
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn durations(input: &str) -> Vec<f32> {
+        let ringtone = Ringtone::parse(input).expect("successful parse");
+
+        ringtone
+            .iter()
+            .map(|tone| match tone {
+                PlayedTone::Note { duration, .. } => duration,
+                PlayedTone::Silence { duration } => duration,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_timing() {
+        // At 60 bpm, a quarter note is exactly one second.
+        assert_eq!(
+            durations("Timing:d=4,o=5,b=60:1a,2a,a,8p,16a,32a,64a,4a.,8p."),
+            [4.0, 2.0, 1.0, 0.5, 0.25, 0.125, 0.0625, 1.5, 0.75]
+        );
+
+        // Three triplets take the time of one of the next longest plain note.
+        let triplets =
+            durations("Triplets:d=4,o=5,b=90:12a,12b,12c,4p,3a,3b,3c,1p,96a,96b,96c,32p");
+        for group in triplets.chunks(4) {
+            let sum: f32 = group[..3].iter().sum();
+            assert!((sum - group[3]).abs() < 1e-6, "{group:?}");
+        }
+
+        // Dotted triplets are still exact.
+        assert_eq!(durations("Dotted:d=4,o=5,b=60:6a.,96p."), [1.0, 0.0625]);
+    }
+
+    #[test]
+    fn test_loops() {
+        assert_eq!(
+            durations("Loops:d=4,o=5,b=60,l=2:a,8p"),
+            [1.0, 0.5].repeat(3)
+        );
+
+        // Repeating forever plays once.
+        assert_eq!(durations("Forever:d=4,o=5,b=60,l=15:a,8p"), [1.0, 0.5]);
+    }
+
+    #[test]
+    fn test_style() {
+        // The gap only applies to notes, not to rests.
+        assert_eq!(
+            durations("Natural:d=4,o=5,b=60,s=n:a,8p,2a"),
+            [0.9375, 0.0625, 0.5, 1.875, 0.125]
+        );
+        assert_eq!(
+            durations("Continuous:d=4,o=5,b=60,s=c:a,8p,2a"),
+            [1.0, 0.5, 2.0]
+        );
+        assert_eq!(
+            durations("Staccato:d=4,o=5,b=60,s=s:a,8p,2a"),
+            [0.5, 0.5, 0.5, 1.0, 1.0]
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use super::*;
+
+/// Runs the lenient parser on the input, returning the best effort ringtone and a warning for each
+/// problem that was worked around.
+///
+/// Rather than parsing the whole input in one go, this splits it into sections and items itself,
+/// cleans each one up, and then hands it to the strict parser. That way a bad note only loses that
+/// note.
+pub(super) fn parse_input(
+    input: &str,
+    options: &ParseOptions,
+) -> Result<(Ringtone, Vec<ParseWarning>), RtttlError> {
+    let mut lenient = Lenient {
+        input,
+        options,
+        warnings: Vec::new(),
+    };
+
+    // Work out which sections are present. A single section with `=` in it is taken to be the
+    // settings, since a ringtone name rarely has one.
+    let sections: Vec<_> = split(input, 0, ':').collect();
+    let (name, settings, notes) = match sections.as_slice() {
+        [notes] => (None, None, *notes),
+        [settings, notes] if settings.1.contains('=') => (None, Some(*settings), *notes),
+        [name, notes] => (Some(*name), None, *notes),
+        // Anything after the second `:` is notes. Any more `:` separators will turn up as bad
+        // notes.
+        [name, settings, ..] => {
+            let offset = settings.0 + settings.1.len() + 1;
+            (Some(*name), Some(*settings), (offset, &input[offset..]))
+        }
+        [] => unreachable!("split always returns at least one section"),
+    };
+
+    let name = lenient.name(name);
+    let settings = lenient.settings(settings, notes.0);
+    let notes = lenient.notes(notes, &settings)?;
+
+    let ringtone = Ringtone {
+        name,
+        settings,
+        notes,
+    };
+
+    Ok((ringtone, lenient.warnings))
+}
+
+/// Splits the input on the separator, keeping track of the byte offset of each piece in the
+/// original input.
+pub(super) fn split(
+    input: &str,
+    offset: usize,
+    separator: char,
+) -> impl Iterator<Item = (usize, &str)> {
+    input.split(separator).scan(offset, move |offset, piece| {
+        let start = *offset;
+        *offset += piece.len() + separator.len_utf8();
+        Some((start, piece))
+    })
+}
+
+struct Lenient<'a> {
+    input: &'a str,
+    options: &'a ParseOptions,
+    warnings: Vec<ParseWarning>,
+}
+
+impl Lenient<'_> {
+    fn warn(&mut self, offset: usize, kind: WarningKind) {
+        self.warnings
+            .push(ParseWarning::new(self.input, offset, kind));
+    }
+
+    fn name(&mut self, name: Option<(usize, &str)>) -> String {
+        let Some((offset, name)) = name else {
+            self.warn(0, WarningKind::MissingName);
+            return UNTITLED.to_owned();
+        };
+
+        if let Some(i) = name.trim().find(['\r', '\n']) {
+            let trimmed = name.len() - name.trim_start().len();
+            self.warn(offset + trimmed + i, WarningKind::LineBreak);
+        }
+
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if name.is_empty() {
+            self.warn(offset, WarningKind::MissingName);
+            return UNTITLED.to_owned();
+        }
+
+        name
+    }
+
+    fn settings(&mut self, section: Option<(usize, &str)>, notes_offset: usize) -> Settings {
+        let mut settings = Settings::default();
+
+        match section {
+            Some((offset, section)) if !section.trim().is_empty() => {
+                for (offset, item) in split(section, offset, ',') {
+                    let Some((offset, item)) = self.clean(offset, item) else {
+                        continue;
+                    };
+
+                    match parser::parse_setting(&item, settings, self.options.octaves.clone()) {
+                        Some(parsed) => settings = parsed,
+                        None => self.warn(offset, WarningKind::InvalidSetting),
+                    }
+                }
+            }
+            Some((offset, _)) => self.warn(offset, WarningKind::MissingSettings),
+            None => self.warn(notes_offset, WarningKind::MissingSettings),
+        }
+
+        settings
+    }
+
+    fn notes(
+        &mut self,
+        (offset, section): (usize, &str),
+        settings: &Settings,
+    ) -> Result<Vec<Note>, RtttlError> {
+        let mut notes = Vec::new();
+
+        for (offset, item) in split(section, offset, ',') {
+            let Some((offset, mut item)) = self.clean(offset, item) else {
+                continue;
+            };
+
+            // A `b` after the pitch letter is a flat. We store flats internally, but the parser
+            // only understands sharps, so respell it as the sharp of the note below.
+            let digits = item.bytes().take_while(u8::is_ascii_digit).count();
+            let flat = match item.as_bytes()[digits..] {
+                [pitch @ b'a'..=b'g', b'b', ..] => Some(pitch),
+                _ => None,
+            };
+
+            if let Some(pitch) = flat {
+                let sharp = match pitch {
+                    b'a' => "g#",
+                    b'b' => "a#",
+                    b'c' => "b",
+                    b'd' => "c#",
+                    b'e' => "d#",
+                    b'f' => "e",
+                    _ => "f#",
+                };
+                item.replace_range(digits..digits + 2, sharp);
+                self.warn(offset, WarningKind::FlatSuffix);
+            }
+
+            let note = parser::parse_note(&item, self.options.octaves.clone());
+
+            // C flat is the B in the octave below, so the octave has to follow it down.
+            let note = match (note, flat) {
+                (Some(note), Some(b'c')) => {
+                    let octave = note.octave.unwrap_or(settings.octave);
+                    octave
+                        .number()
+                        .checked_sub(1)
+                        .and_then(Octave::from_number)
+                        .filter(|octave| self.options.octaves.contains(octave))
+                        .map(|octave| note.with_octave(octave))
+                }
+                (note, _) => note,
+            };
+
+            match note {
+                Some(note) => notes.push(note),
+                None => self.warn(offset, WarningKind::InvalidNote),
+            }
+        }
+
+        if notes.is_empty() {
+            return Err(RtttlError::new(
+                self.input,
+                offset,
+                Some(Section::Notes),
+                Some("at least one note"),
+            ));
+        }
+
+        Ok(notes)
+    }
+
+    /// Cleans up a single setting or note, removing whitespace and converting to lowercase. Returns
+    /// the offset of the item with the leading whitespace skipped, or `None` if it's empty.
+    fn clean(&mut self, offset: usize, item: &str) -> Option<(usize, String)> {
+        if item.trim().is_empty() {
+            self.warn(offset, WarningKind::EmptyItem);
+            return None;
+        }
+
+        let offset = offset + item.len() - item.trim_start().len();
+        let item = item.trim();
+
+        if let Some(i) = item.find(['\r', '\n']) {
+            self.warn(offset + i, WarningKind::LineBreak);
+        }
+
+        if item.chars().any(char::is_uppercase) {
+            self.warn(offset, WarningKind::Uppercase);
+        }
+
+        let item = item
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .flat_map(char::to_lowercase)
+            .collect();
+
+        Some((offset, item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<(Ringtone, Vec<ParseWarning>), RtttlError> {
+        let options = ParseOptions {
+            lenient: true,
+            ..ParseOptions::default()
+        };
+
+        parse_input(input, &options)
+    }
+
+    fn kinds(warnings: &[ParseWarning]) -> Vec<(usize, usize, WarningKind)> {
+        warnings
+            .iter()
+            .map(|w| (w.line(), w.column(), w.kind()))
+            .collect()
+    }
+
+    #[test]
+    fn test_lenient_quirks() {
+        let input = "Forum Paste:D=8,O=5,b=100:\n16E6,eb,8c\n6,,bb.,8x,Cb,\n";
+
+        let (ringtone, warnings) = parse(input).expect("successful parse");
+
+        assert_eq!(
+            ringtone.to_rtttl(),
+            "Forum Paste:d=8,o=5,b=100:16e6,d#,8c6,a#.,b4"
+        );
+        assert_eq!(
+            kinds(&warnings),
+            [
+                (1, 13, WarningKind::Uppercase),
+                (1, 17, WarningKind::Uppercase),
+                (2, 1, WarningKind::Uppercase),
+                (2, 6, WarningKind::FlatSuffix),
+                (2, 11, WarningKind::LineBreak),
+                (3, 3, WarningKind::EmptyItem),
+                (3, 4, WarningKind::FlatSuffix),
+                (3, 8, WarningKind::InvalidNote),
+                (3, 11, WarningKind::Uppercase),
+                (3, 11, WarningKind::FlatSuffix),
+                (3, 14, WarningKind::EmptyItem),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lenient_missing_sections() {
+        let (ringtone, warnings) = parse("Tune: 8a, 8b").expect("successful parse");
+        assert_eq!(ringtone.to_rtttl(), "Tune:d=4,o=5,b=120:8a,8b");
+        assert_eq!(kinds(&warnings), [(1, 6, WarningKind::MissingSettings)]);
+
+        let (ringtone, warnings) = parse("d=8,b=90: a, b").expect("successful parse");
+        assert_eq!(ringtone.to_rtttl(), "Untitled:d=8,o=5,b=90:a,b");
+        assert_eq!(kinds(&warnings), [(1, 1, WarningKind::MissingName)]);
+
+        let (ringtone, warnings) = parse("a,b,c").expect("successful parse");
+        assert_eq!(ringtone.to_rtttl(), "Untitled:d=4,o=5,b=120:a,b,c");
+        assert_eq!(
+            kinds(&warnings),
+            [
+                (1, 1, WarningKind::MissingName),
+                (1, 1, WarningKind::MissingSettings)
+            ]
+        );
+
+        let (ringtone, warnings) = parse("Tune:d=4,x=1,o=6:a").expect("successful parse");
+        assert_eq!(ringtone.to_rtttl(), "Tune:d=4,o=6,b=120:a");
+        assert_eq!(kinds(&warnings), [(1, 10, WarningKind::InvalidSetting)]);
+    }
+
+    #[test]
+    fn test_lenient_no_notes() {
+        let err = parse("Tune:d=4:x,y,").expect_err("failed parse");
+
+        assert_eq!(err.column(), 10);
+        assert_eq!(err.section(), Some(Section::Notes));
+        assert_eq!(err.expected(), Some("at least one note"));
+    }
+}
@@ -0,0 +1,299 @@
+use std::cmp::Reverse;
+use std::fmt::Write;
+
+use super::*;
+
+/// The LilyPond note names of the natural notes, from C.
+const LETTERS: [&str; 7] = ["c", "d", "e", "f", "g", "a", "b"];
+
+/// The semitones above C of each natural note, in the same order as `LETTERS`.
+const SEMITONES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+/// The letters that are sharpened in order as a key gains sharps, as indexes into `LETTERS`.
+/// Flats are added in the reverse order.
+const SHARPS: [usize; 7] = [3, 0, 4, 1, 5, 2, 6];
+
+/// The major keys that a ringtone can be written in, by number of sharps (or flats, if negative).
+const FIFTHS: std::ops::RangeInclusive<i32> = -6..=6;
+
+/// Melody lines are wrapped at this many characters, not counting the indentation.
+const LINE_LENGTH: usize = 72;
+
+/// Writes the ringtone as a LilyPond score.
+///
+/// The pitches are `\relative`, and durations are only written when they change. The key is the
+/// major key that fits the most notes, and black keys are spelled to suit it: as they are in the
+/// key, or as sharps in sharp keys and flats in flat keys. Runs of triplets become a `\tuplet`.
+/// Notes are split and tied across the barlines by LilyPond itself.
+pub(super) fn write(ringtone: &Ringtone) -> String {
+    let settings = &ringtone.settings;
+    let fifths = key(ringtone);
+    let spelling = spelling(fifths);
+
+    let mut tokens = Vec::new();
+    let mut previous_step = 4 * 7;
+    let mut previous_duration = None;
+    let mut tuplet = false;
+
+    for note in &ringtone.notes {
+        let duration = note.duration.unwrap_or(settings.duration);
+
+        if duration.is_triplet() != tuplet {
+            tuplet = duration.is_triplet();
+            tokens.push(if tuplet { "\\tuplet 3/2 {" } else { "}" }.to_owned());
+        }
+
+        let mut token = String::new();
+
+        match note.pitch {
+            Some(pitch) => {
+                let key = midi::key(pitch, note.octave.unwrap_or(settings.octave)) as i32;
+                let (letter, accidental) = spelling[key as usize % 12];
+                token.push_str(&name(letter, accidental));
+
+                // Each note is in the octave nearest the last one, counting only the letters, so
+                // it's at most a fourth away unless it's marked up or down.
+                let octave = (key - SEMITONES[letter] - accidental as i32) / 12 - 1;
+                let step = octave * 7 + letter as i32;
+                let nearest =
+                    previous_step + ((letter as i32 - previous_step).rem_euclid(7) + 3) % 7 - 3;
+                let marks = (step - nearest) / 7;
+                token.push_str(
+                    &if marks > 0 { "'" } else { "," }.repeat(marks.unsigned_abs() as usize),
+                );
+                previous_step = step;
+            }
+            None => token.push('r'),
+        }
+
+        // Triplets are written as the plain duration, within the `\tuplet`.
+        let written = if duration.is_triplet() {
+            duration.denominator() as u32 * 2 / 3
+        } else {
+            duration.denominator() as u32
+        };
+        let written = format!("{written}{}", if note.dotted { "." } else { "" });
+        if previous_duration.as_ref() != Some(&written) {
+            token.push_str(&written);
+            previous_duration = Some(written);
+        }
+
+        if note.pitch.is_some() && settings.style == Style::Staccato {
+            token.push_str("-.");
+        }
+
+        tokens.push(token);
+    }
+
+    if tuplet {
+        tokens.push("}".to_owned());
+    }
+
+    let mut out = String::new();
+    out.push_str("\\version \"2.24.0\"\n\n");
+    out.push_str("\\header {\n");
+    writeln!(out, "  title = \"{}\"", escape(&ringtone.name)).expect("write to string");
+    out.push_str("}\n\n");
+    out.push_str("\\score {\n");
+    out.push_str("  \\new Voice \\with {\n");
+    out.push_str("    \\remove \"Note_heads_engraver\"\n");
+    out.push_str("    \\consists \"Completion_heads_engraver\"\n");
+    out.push_str("    \\remove \"Rest_engraver\"\n");
+    out.push_str("    \\consists \"Completion_rest_engraver\"\n");
+    out.push_str("  } \\relative c' {\n");
+    writeln!(out, "    \\key {} \\major", tonic(fifths)).expect("write to string");
+    writeln!(out, "    \\tempo 4 = {}", settings.tempo).expect("write to string");
+
+    // The repeat count is how many times the melody plays in total.
+    let indent = if settings.plays() > 1 {
+        writeln!(out, "    \\repeat volta {} {{", settings.plays()).expect("write to string");
+        "      "
+    } else {
+        "    "
+    };
+
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+            writeln!(out, "{indent}{line}").expect("write to string");
+            line.clear();
+        }
+
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    writeln!(out, "{indent}{line}").expect("write to string");
+
+    if settings.plays() > 1 {
+        out.push_str("    }\n");
+    }
+
+    out.push_str("  }\n");
+    out.push_str("  \\layout { }\n");
+    out.push_str("  \\midi { }\n");
+    out.push_str("}\n");
+
+    out
+}
+
+/// The major key that the most notes fit, as a number of sharps, or flats if negative. Ties go to
+/// the key with the fewest sharps or flats, and then to sharps.
+fn key(ringtone: &Ringtone) -> i32 {
+    let mut counts = [0; 12];
+    for note in &ringtone.notes {
+        if let Some(pitch) = note.pitch {
+            counts[midi::key(pitch, Octave::O0) as usize % 12] += 1;
+        }
+    }
+
+    FIFTHS
+        .max_by_key(|&fifths| {
+            let tonic = (fifths * 7).rem_euclid(12);
+            let fits: u32 = [0, 2, 4, 5, 7, 9, 11]
+                .iter()
+                .map(|&degree| counts[((tonic + degree) % 12) as usize])
+                .sum();
+
+            (fits, Reverse(fifths.abs()), fifths)
+        })
+        .expect("at least one key")
+}
+
+/// The letter and accidental to write for each pitch class, from C, in the key.
+fn spelling(fifths: i32) -> [(usize, i8); 12] {
+    // Black keys outside the key are sharps in sharp keys and flats in flat keys. In C major,
+    // they're the usual chromatic notes: C#, Eb, F#, G# and Bb.
+    let mut spelling = [(0, 0); 12];
+    for (letter, &semitone) in SEMITONES.iter().enumerate() {
+        spelling[semitone as usize] = (letter, 0);
+
+        // The black key above each natural note, apart from E and B.
+        if !matches!(letter, 2 | 6) {
+            let flat = fifths < 0 || (fifths == 0 && matches!(letter, 1 | 5));
+            spelling[semitone as usize + 1] = if flat { (letter + 1, -1) } else { (letter, 1) };
+        }
+    }
+
+    // Notes in the key are spelled as they are in its key signature, e.g. E# in F# major.
+    let mut signature = [0; 7];
+    if fifths > 0 {
+        SHARPS[..fifths as usize]
+            .iter()
+            .for_each(|&letter| signature[letter] = 1);
+    } else {
+        SHARPS[(7 + fifths) as usize..]
+            .iter()
+            .for_each(|&letter| signature[letter] = -1);
+    }
+
+    for (letter, &accidental) in signature.iter().enumerate() {
+        let pitch_class = (SEMITONES[letter] + accidental as i32).rem_euclid(12);
+        spelling[pitch_class as usize] = (letter, accidental);
+    }
+
+    spelling
+}
+
+/// The LilyPond note name, using the default Dutch names: `cis` is C#, and `ees` is written `es`.
+fn name(letter: usize, accidental: i8) -> String {
+    match (LETTERS[letter], accidental) {
+        ("e", -1) => "es".to_owned(),
+        ("a", -1) => "as".to_owned(),
+        (letter, -1) => format!("{letter}es"),
+        (letter, 1) => format!("{letter}is"),
+        (letter, _) => letter.to_owned(),
+    }
+}
+
+/// The name of the tonic of the major key.
+fn tonic(fifths: i32) -> String {
+    let pitch_class = (fifths * 7).rem_euclid(12) as usize;
+    let (letter, accidental) = spelling(fifths)[pitch_class];
+
+    name(letter, accidental)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_lilypond() {
+        let ringtone =
+            Ringtone::parse("\"Hi\":d=8,o=5,b=140,s=s,l=1:c,4a#4,16p,d#6.,12e,12f,12g,b,a#,4a")
+                .expect("successful parse");
+
+        assert_eq!(
+            write(&ringtone),
+            r#"\version "2.24.0"
+
+\header {
+  title = "\"Hi\""
+}
+
+\score {
+  \new Voice \with {
+    \remove "Note_heads_engraver"
+    \consists "Completion_heads_engraver"
+    \remove "Rest_engraver"
+    \consists "Completion_rest_engraver"
+  } \relative c' {
+    \key f \major
+    \tempo 4 = 140
+    \repeat volta 2 {
+      c'8-. bes4-. r16 es'8.-. \tuplet 3/2 { e,8-. f-. g-. } b-. bes-. a4-.
+    }
+  }
+  \layout { }
+  \midi { }
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_relative_octaves() {
+        let ringtone = Ringtone::parse("Leaps:d=4,o=5,b=100:c,g,c6,f,b,f6,p,2c4,c7")
+            .expect("successful parse");
+        let lilypond = write(&ringtone);
+
+        assert!(
+            lilypond.contains("\n    c'4 g' c f, b f' r c,,2 c'''4\n"),
+            "{lilypond}"
+        );
+    }
+
+    #[test]
+    fn test_spelling() {
+        let spell = |fifths, pitch| {
+            let (letter, accidental) = spelling(fifths)[midi::key(pitch, Octave::O0) as usize % 12];
+            name(letter, accidental)
+        };
+
+        // C major uses the usual chromatic notes.
+        assert_eq!(spell(0, Pitch::Db), "cis");
+        assert_eq!(spell(0, Pitch::Eb), "es");
+        assert_eq!(spell(0, Pitch::Bb), "bes");
+
+        // Sharp keys use sharps, and flat keys flats.
+        assert_eq!(spell(2, Pitch::Bb), "ais");
+        assert_eq!(spell(-1, Pitch::Gb), "ges");
+        assert_eq!(spell(-3, Pitch::Ab), "as");
+
+        // Notes in the key are spelled as they are in the key signature.
+        assert_eq!(spell(6, Pitch::F), "eis");
+        assert_eq!(spell(-6, Pitch::B), "ces");
+        assert_eq!(tonic(-6), "ges");
+        assert_eq!(tonic(6), "fis");
+
+        let nokia = Ringtone::parse(include_str!("../../examples/nokia.txt").trim())
+            .expect("successful parse");
+        assert_eq!(key(&nokia), 3);
+    }
+}
@@ -0,0 +1,314 @@
+use std::fmt;
+
+use super::*;
+
+/// A suspicious part of a ringtone, found by `Ringtone::lint`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lint {
+    code: LintCode,
+    target: LintTarget,
+    location: Option<(usize, usize)>,
+}
+
+/// The kinds of problem that the linter looks for. Each has a stable code, so that tools can act
+/// on specific lints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LintCode {
+    /// A note overrides the duration with the same value as the default.
+    RedundantDuration,
+    /// A note overrides the octave with the same value as the default.
+    RedundantOctave,
+    /// A note has the `.` before the octave, which some players don't accept.
+    DotBeforeOctave,
+    /// A triplet or 64th note, which are extensions that standard RTTTL players don't accept.
+    NonStandardDuration,
+    /// The name is longer than the device accepts.
+    NameTooLong,
+    /// A note, or the default octave, is outside the device's range.
+    OctaveOutOfRange,
+    /// The tempo isn't one that the device can play.
+    UnsupportedTempo,
+    /// The device can't store this many notes. This is reported on the first note past the limit.
+    TooManyNotes,
+}
+
+/// The part of the ringtone that a lint applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LintTarget {
+    Name,
+    Settings,
+    /// The note at this index in `Ringtone::notes`.
+    Note(usize),
+}
+
+impl Lint {
+    pub fn code(&self) -> LintCode {
+        self.code
+    }
+
+    pub fn target(&self) -> LintTarget {
+        self.target
+    }
+
+    /// Line and column (both 1-based) in the source text, when linted with
+    /// `Ringtone::lint_source`.
+    pub fn location(&self) -> Option<(usize, usize)> {
+        self.location
+    }
+}
+
+impl LintCode {
+    /// The stable code for the lint, e.g. `redundant-duration`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LintCode::RedundantDuration => "redundant-duration",
+            LintCode::RedundantOctave => "redundant-octave",
+            LintCode::DotBeforeOctave => "dot-before-octave",
+            LintCode::NonStandardDuration => "non-standard-duration",
+            LintCode::NameTooLong => "name-too-long",
+            LintCode::OctaveOutOfRange => "octave-out-of-range",
+            LintCode::UnsupportedTempo => "unsupported-tempo",
+            LintCode::TooManyNotes => "too-many-notes",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            LintCode::RedundantDuration => "duration is the same as the default",
+            LintCode::RedundantOctave => "octave is the same as the default",
+            LintCode::DotBeforeOctave => "dot should be placed after the octave",
+            LintCode::NonStandardDuration => "triplets and 64th notes aren't standard RTTTL",
+            LintCode::NameTooLong => "name is longer than the device accepts",
+            LintCode::OctaveOutOfRange => "octave is outside the device's range",
+            LintCode::UnsupportedTempo => "tempo isn't supported by the device",
+            LintCode::TooManyNotes => "the device can't store this many notes",
+        }
+    }
+}
+
+impl fmt::Display for LintCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for LintTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintTarget::Name => f.write_str("name"),
+            LintTarget::Settings => f.write_str("settings"),
+            LintTarget::Note(i) => write!(f, "note {}", i + 1),
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((line, column)) = self.location {
+            write!(f, "{line}:{column}: ")?;
+        }
+
+        write!(
+            f,
+            "{}: {} ({})",
+            self.code,
+            self.code.description(),
+            self.target
+        )
+    }
+}
+
+/// Checks the parsed ringtone against the device profile.
+pub(super) fn lint(ringtone: &Ringtone, profile: &DeviceProfile) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let mut push = |code, target| {
+        lints.push(Lint {
+            code,
+            target,
+            location: None,
+        })
+    };
+
+    let settings = &ringtone.settings;
+
+    if let Some(max) = profile.max_name_length {
+        if ringtone.name.chars().count() > max {
+            push(LintCode::NameTooLong, LintTarget::Name);
+        }
+    }
+
+    if settings.duration.is_triplet() || settings.duration == Duration::SixtyFourth {
+        push(LintCode::NonStandardDuration, LintTarget::Settings);
+    }
+
+    if !profile.octaves.contains(&settings.octave) {
+        push(LintCode::OctaveOutOfRange, LintTarget::Settings);
+    }
+
+    if !profile.supports_tempo(settings.tempo) {
+        push(LintCode::UnsupportedTempo, LintTarget::Settings);
+    }
+
+    for (i, note) in ringtone.notes.iter().enumerate() {
+        let target = LintTarget::Note(i);
+
+        if let Some(duration) = note.duration {
+            if duration == settings.duration {
+                push(LintCode::RedundantDuration, target);
+            }
+
+            if duration.is_triplet() || duration == Duration::SixtyFourth {
+                push(LintCode::NonStandardDuration, target);
+            }
+        }
+
+        if note.octave == Some(settings.octave) {
+            push(LintCode::RedundantOctave, target);
+        }
+
+        // The default octave is already checked, so this only needs to look at the overrides.
+        // Silences don't have an octave at all.
+        if let (Some(_), Some(octave)) = (note.pitch, note.octave) {
+            if !profile.octaves.contains(&octave) {
+                push(LintCode::OctaveOutOfRange, target);
+            }
+        }
+
+        if profile.max_notes == Some(i) {
+            push(LintCode::TooManyNotes, target);
+        }
+    }
+
+    lints
+}
+
+/// Parses and lints the source text. On top of the checks in `lint`, this finds problems that
+/// only show in the text, and gives every lint a line and column.
+pub(super) fn lint_source(input: &str, profile: &DeviceProfile) -> Result<Vec<Lint>, RtttlError> {
+    let ringtone = Ringtone::parse(input)?;
+
+    // The parse succeeded, so there are exactly two section separators, and the comma separated
+    // items in the notes section line up with the parsed notes.
+    let settings_start = input.find(':').map_or(0, |i| i + 1);
+    let notes_start = input[settings_start..]
+        .find(':')
+        .map_or(0, |i| settings_start + i + 1);
+
+    let notes: Vec<(usize, &str)> = lenient::split(&input[notes_start..], notes_start, ',')
+        .map(|(offset, item)| (offset + item.len() - item.trim_start().len(), item.trim()))
+        .collect();
+
+    let mut lints = lint(&ringtone, profile);
+
+    // A `.` followed by a digit means the dot came before the octave. Each note has at most one
+    // lint of each code, so this can be inserted next to the others for the note.
+    for (i, (_, item)) in notes.iter().enumerate() {
+        if item
+            .split_once('.')
+            .is_some_and(|(_, rest)| rest.starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let at = lints
+                .iter()
+                .position(|lint| matches!(lint.target, LintTarget::Note(j) if j > i))
+                .unwrap_or(lints.len());
+
+            lints.insert(
+                at,
+                Lint {
+                    code: LintCode::DotBeforeOctave,
+                    target: LintTarget::Note(i),
+                    location: None,
+                },
+            );
+        }
+    }
+
+    for lint in &mut lints {
+        let offset = match lint.target {
+            LintTarget::Name => 0,
+            LintTarget::Settings => {
+                settings_start + input[settings_start..].len()
+                    - input[settings_start..].trim_start().len()
+            }
+            LintTarget::Note(i) => notes[i].0,
+        };
+
+        lint.location = Some(error::line_column(input, offset));
+    }
+
+    Ok(lints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(lints: &[Lint]) -> Vec<(String, LintCode)> {
+        lints
+            .iter()
+            .map(|lint| (lint.target.to_string(), lint.code))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        let ringtone = Ringtone::parse("Much Too Long Name:d=4,o=5,b=130:4a,a5,12c,8c8,p8,32d")
+            .expect("successful parse");
+
+        let lints = lint(&ringtone, &DeviceProfile::nokia());
+
+        assert_eq!(
+            codes(&lints),
+            [
+                ("name".to_owned(), LintCode::NameTooLong),
+                ("settings".to_owned(), LintCode::UnsupportedTempo),
+                ("note 1".to_owned(), LintCode::RedundantDuration),
+                ("note 2".to_owned(), LintCode::RedundantOctave),
+                ("note 3".to_owned(), LintCode::NonStandardDuration),
+                ("note 4".to_owned(), LintCode::OctaveOutOfRange),
+            ]
+        );
+
+        let profile = DeviceProfile {
+            max_notes: Some(5),
+            ..DeviceProfile::unrestricted()
+        };
+        let lints = lint(&ringtone, &profile);
+        assert_eq!(
+            codes(&lints).last(),
+            Some(&("note 6".to_owned(), LintCode::TooManyNotes))
+        );
+
+        // The same ringtone is fine as far as an unrestricted device is concerned, apart from the
+        // lints about the text itself.
+        let lints = lint(&ringtone, &DeviceProfile::unrestricted());
+
+        assert_eq!(
+            codes(&lints),
+            [
+                ("note 1".to_owned(), LintCode::RedundantDuration),
+                ("note 2".to_owned(), LintCode::RedundantOctave),
+                ("note 3".to_owned(), LintCode::NonStandardDuration),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_source() {
+        let input = "Badinerie:d=16,o=5,b=200:\na.,8c.6,\n  8c#.6, 16b";
+
+        let lints = lint_source(input, &DeviceProfile::nokia()).expect("successful parse");
+
+        let found: Vec<_> = lints.iter().map(|lint| lint.to_string()).collect();
+        assert_eq!(
+            found,
+            [
+                "2:4: dot-before-octave: dot should be placed after the octave (note 2)",
+                "3:3: dot-before-octave: dot should be placed after the octave (note 3)",
+                "3:10: redundant-duration: duration is the same as the default (note 4)",
+            ]
+        );
+
+        assert!(lint_source("Broken:d=4:x", &DeviceProfile::nokia()).is_err());
+    }
+}
@@ -0,0 +1,864 @@
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::iterator::TickedTone;
+use super::*;
+
+/// Velocity for every note. RTTTL has no dynamics, so this is just a comfortable middle value.
+const VELOCITY: u8 = 100;
+
+/// The highest MIDI key, G9. The top bit of each data byte is kept clear.
+const HIGHEST_KEY: u8 = 127;
+
+/// Writes the ringtone as a Standard MIDI File, format 0.
+///
+/// The file's resolution is `TICKS_PER_QUARTER`, so the ringtone's timing carries over exactly,
+/// including loops and the gaps from the style. The single track is named after the ringtone, and
+/// the notes play on the first channel.
+///
+/// The highest MIDI key is G9, so the notes from G#9 to B9 can't be written.
+pub(super) fn write(ringtone: &Ringtone) -> Result<Vec<u8>, MidiError> {
+    let settings = &ringtone.settings;
+    let too_high = ringtone.notes.iter().position(|note| {
+        note.pitch
+            .is_some_and(|pitch| key(pitch, note.octave.unwrap_or(settings.octave)) > HIGHEST_KEY)
+    });
+    if let Some(i) = too_high {
+        return Err(MidiError::KeyOutOfRange(i));
+    }
+
+    let mut track = Vec::new();
+
+    // Track name.
+    let name = ringtone.name.as_bytes();
+    meta_event(&mut track, 0x03, name);
+
+    // Tempo, in microseconds per quarter note.
+    let tempo = 60_000_000 / ringtone.settings.tempo.max(1) as u32;
+    meta_event(&mut track, 0x51, &tempo.to_be_bytes()[1..]);
+
+    // Rests and gaps don't produce events, they just delay the next one.
+    let mut delay = 0;
+    for tone in iterator::ticked(ringtone) {
+        match tone {
+            TickedTone::Note {
+                pitch,
+                octave,
+                ticks,
+            } => {
+                let key = key(pitch, octave);
+                write_varint(&mut track, delay);
+                track.extend([0x90, key, VELOCITY]);
+                write_varint(&mut track, ticks);
+                track.extend([0x80, key, 0]);
+                delay = 0;
+            }
+            TickedTone::Silence { ticks } => delay += ticks,
+        }
+    }
+
+    // End of track, after any trailing silence.
+    write_varint(&mut track, delay);
+    track.extend([0xff, 0x2f, 0x00]);
+
+    let mut file = Vec::with_capacity(track.len() + 22);
+    file.extend(b"MThd");
+    file.extend(6u32.to_be_bytes());
+    file.extend(0u16.to_be_bytes()); // Format 0
+    file.extend(1u16.to_be_bytes()); // One track
+    file.extend((TICKS_PER_QUARTER as u16).to_be_bytes());
+    file.extend(b"MTrk");
+    file.extend((track.len() as u32).to_be_bytes());
+    file.extend(track);
+    Ok(file)
+}
+
+/// The MIDI key number for a note, where middle C (C4) is 60 and A4 is 69. Notes above G9 give
+/// numbers above `HIGHEST_KEY`, which aren't valid MIDI keys.
+pub(super) fn key(pitch: Pitch, octave: Octave) -> u8 {
+    let semitone = match pitch {
+        Pitch::C => 0,
+        Pitch::Db => 1,
+        Pitch::D => 2,
+        Pitch::Eb => 3,
+        Pitch::E => 4,
+        Pitch::F => 5,
+        Pitch::Gb => 6,
+        Pitch::G => 7,
+        Pitch::Ab => 8,
+        Pitch::A => 9,
+        Pitch::Bb => 10,
+        Pitch::B => 11,
+    };
+
+    (octave.number() + 1) * 12 + semitone
+}
+
+fn meta_event(track: &mut Vec<u8>, kind: u8, data: &[u8]) {
+    track.extend([0x00, 0xff, kind]);
+    write_varint(track, data.len() as u32);
+    track.extend(data);
+}
+
+/// Writes a MIDI variable length quantity: 7 bits per byte, most significant first, with the top
+/// bit set on every byte but the last.
+fn write_varint(out: &mut Vec<u8>, value: u32) {
+    let mut shift = 28;
+    while shift > 0 && value >> shift == 0 {
+        shift -= 7;
+    }
+
+    while shift > 0 {
+        out.push(0x80 | (value >> shift) as u8 & 0x7f);
+        shift -= 7;
+    }
+
+    out.push(value as u8 & 0x7f);
+}
+
+/// Options for importing a Standard MIDI File with `Ringtone::from_midi`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MidiImportOptions {
+    /// The track to import, counting from 0. By default, this is the first track with notes.
+    pub track: Option<usize>,
+
+    /// The channel to import, from 0 to 15. By default, notes from every channel are imported.
+    pub channel: Option<u8>,
+
+    /// Which note to keep when several are playing at once.
+    pub voice: Voice,
+}
+
+/// How to reduce chords and overlapping notes to the single line of a ringtone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Voice {
+    /// Keep the highest note, which is usually the melody.
+    #[default]
+    Highest,
+    /// Keep the lowest note, for bass lines.
+    Lowest,
+}
+
+/// Something from a MIDI file that didn't survive the conversion to RTTTL. Each one counts the
+/// affected notes or events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidiLoss {
+    /// Tempo changes after the first tempo, which RTTTL has no way to express.
+    TempoChanges(usize),
+    /// Notes dropped entirely because other notes were playing at the same time.
+    DroppedNotes(usize),
+    /// Notes cut short, or split in two, by another note playing over them.
+    ShortenedNotes(usize),
+    /// Notes too short for the shortest RTTTL duration.
+    TooShort(usize),
+    /// Notes moved or resized to the nearest duration that RTTTL can express.
+    Quantized(usize),
+    /// Notes below octave 0, moved up an octave.
+    Transposed(usize),
+}
+
+impl Default for MidiImportOptions {
+    fn default() -> MidiImportOptions {
+        MidiImportOptions {
+            track: None,
+            channel: None,
+            voice: Voice::Highest,
+        }
+    }
+}
+
+impl fmt::Display for MidiLoss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiLoss::TempoChanges(n) => write!(f, "ignored {n} tempo change(s)"),
+            MidiLoss::DroppedNotes(n) => write!(f, "dropped {n} overlapping note(s)"),
+            MidiLoss::ShortenedNotes(n) => write!(f, "shortened {n} overlapping note(s)"),
+            MidiLoss::TooShort(n) => write!(f, "dropped {n} note(s) too short for RTTTL"),
+            MidiLoss::Quantized(n) => write!(f, "moved or resized {n} note(s) to fit RTTTL"),
+            MidiLoss::Transposed(n) => write!(f, "moved {n} note(s) up from below octave 0"),
+        }
+    }
+}
+
+/// Imports a Standard MIDI File as a ringtone, returning what was lost along the way.
+///
+/// One track and channel are reduced to a single line, which is quantized to the durations that
+/// RTTTL can express. The first tempo becomes the ringtone's tempo, and the defaults are chosen
+/// by `Ringtone::compact`.
+pub(super) fn read(
+    data: &[u8],
+    options: &MidiImportOptions,
+) -> Result<(Ringtone, Vec<MidiLoss>), MidiError> {
+    let file = read_file(data)?;
+
+    let notes_in = |track: &Track| -> Vec<MidiNote> {
+        track
+            .notes
+            .iter()
+            .filter(|note| {
+                options
+                    .channel
+                    .is_none_or(|channel| channel == note.channel)
+            })
+            .copied()
+            .collect()
+    };
+
+    let (track, notes) = match options.track {
+        Some(i) => {
+            let track = file.tracks.get(i).ok_or(MidiError::NoSuchTrack(i))?;
+            (track, notes_in(track))
+        }
+        None => file
+            .tracks
+            .iter()
+            .map(|track| (track, notes_in(track)))
+            .find(|(_, notes)| !notes.is_empty())
+            .ok_or(MidiError::NoNotes)?,
+    };
+
+    let mut losses = Vec::new();
+    let mut report = |loss: fn(usize) -> MidiLoss, count| {
+        if count > 0 {
+            losses.push(loss(count));
+        }
+    };
+
+    // In a format 1 file, the tempo is usually in a separate track from the notes.
+    let mut tempos: Vec<_> = file
+        .tracks
+        .iter()
+        .flat_map(|t| &t.tempos)
+        .copied()
+        .collect();
+    tempos.sort_by_key(|&(time, _)| time);
+    let changes = tempos.windows(2).filter(|w| w[0].1 != w[1].1).count();
+    report(MidiLoss::TempoChanges, changes);
+
+    let micros = tempos.first().map_or(500_000, |&(_, micros)| micros.max(1));
+    let tempo = ((60_000_000 + micros / 2) / micros).clamp(1, Tempo::MAX as u32) as Tempo;
+
+    // Reduce to a single line, and convert to our own ticks.
+    let line = melody(&notes, options.voice);
+
+    let dropped = (0..notes.len())
+        .filter(|&i| !line.iter().any(|&(j, _, _)| i == j))
+        .count();
+    let shortened = (0..notes.len())
+        .filter(|&i| {
+            let mut parts = line.iter().filter(|&&(j, _, _)| i == j);
+            let length = notes[i].end - notes[i].start;
+            parts.clone().count() > 1 || parts.any(|&(_, start, end)| end - start < length)
+        })
+        .count();
+    report(MidiLoss::DroppedNotes, dropped);
+    report(MidiLoss::ShortenedNotes, shortened);
+
+    let ppq = file.ticks_per_quarter as u64;
+    let ticks = |time: u32| ((time as u64 * TICKS_PER_QUARTER as u64 + ppq / 2) / ppq) as u32;
+
+    // Quantize each note so it ends as close as possible to where it should, and fill the gaps
+    // with rests. Working from where the output has got to, rather than the original start,
+    // stops the rounding errors from adding up.
+    let mut tones = Vec::new();
+    let mut position = line.first().map_or(0, |&(_, start, _)| ticks(start));
+    let (mut too_short, mut quantized) = (0, 0);
+
+    for &(i, start, end) in &line {
+        let (start, end) = (ticks(start), ticks(end));
+
+        while let Some(length) = rest_length(start.saturating_sub(position)) {
+            tones.push((None, length));
+            position += length.0;
+        }
+
+        match nearest_length(end.saturating_sub(position)) {
+            Some(length) => {
+                if position != start || length.0 != end - start {
+                    quantized += 1;
+                }
+                tones.push((Some(notes[i].key), length));
+                position += length.0;
+            }
+            None => too_short += 1,
+        }
+    }
+    report(MidiLoss::TooShort, too_short);
+    report(MidiLoss::Quantized, quantized);
+
+    let mut transposed = 0;
+    let tones: Vec<_> = tones
+        .into_iter()
+        .map(|(key, (_, duration, dotted))| {
+            let pitch = key.map(|key| {
+                if key < 12 {
+                    transposed += 1;
+                }
+                pitch_octave(key.max(12))
+            });
+            (pitch, duration, dotted)
+        })
+        .collect();
+    report(MidiLoss::Transposed, transposed);
+
+    if !tones.iter().any(|(pitch, _, _)| pitch.is_some()) {
+        return Err(MidiError::NoNotes);
+    }
+
+    let notes = tones
+        .into_iter()
+        .map(|(pitch, duration, dotted)| Note {
+            duration: Some(duration),
+            pitch: pitch.map(|(pitch, _)| pitch),
+            octave: pitch.map(|(_, octave)| octave),
+            dotted,
+        })
+        .collect();
+
+    // A format 1 file usually names the song in the first track, rather than the one with the
+    // notes.
+    let name = track
+        .name
+        .as_deref()
+        .or_else(|| file.tracks.first().and_then(|t| t.name.as_deref()))
+        .map(|name| name.replace(':', "").trim().to_owned())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| UNTITLED.to_owned());
+
+    let mut ringtone = Ringtone {
+        name,
+        settings: Settings {
+            tempo,
+            ..Settings::default()
+        },
+        notes,
+    };
+    ringtone.compact();
+
+    Ok((ringtone, losses))
+}
+
+struct MidiFile {
+    ticks_per_quarter: u16,
+    tracks: Vec<Track>,
+}
+
+#[derive(Default)]
+struct Track {
+    name: Option<String>,
+    notes: Vec<MidiNote>,
+    /// Tempo changes, as the time in ticks and microseconds per quarter note.
+    tempos: Vec<(u32, u32)>,
+}
+
+#[derive(Clone, Copy)]
+struct MidiNote {
+    start: u32,
+    end: u32,
+    key: u8,
+    channel: u8,
+}
+
+/// Reads big endian values from a MIDI file.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], MidiError> {
+        if self.data.len() < len {
+            return Err(MidiError::Truncated);
+        }
+
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, MidiError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, MidiError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// A data byte of a channel message, which must have the top bit clear.
+    fn data_byte(&mut self) -> Result<u8, MidiError> {
+        match self.u8()? {
+            byte @ 0x00..=0x7f => Ok(byte),
+            _ => Err(MidiError::InvalidEvent),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, MidiError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// The inverse of `write_varint`. MIDI limits these to four bytes.
+    fn varint(&mut self) -> Result<u32, MidiError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = value << 7 | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(MidiError::InvalidEvent)
+    }
+}
+
+fn read_file(data: &[u8]) -> Result<MidiFile, MidiError> {
+    let mut reader = Reader { data };
+
+    if reader.bytes(4) != Ok(b"MThd") {
+        return Err(MidiError::NotMidi);
+    }
+
+    let len = reader.u32()? as usize;
+    let mut header = Reader {
+        data: reader.bytes(len)?,
+    };
+
+    // Any format can be imported: format 0 has a single track, and for formats 1 and 2 the
+    // options choose which track to import.
+    let _format = header.u16()?;
+    let _tracks = header.u16()?;
+    let ticks_per_quarter = header.u16()?;
+    if ticks_per_quarter == 0 || ticks_per_quarter & 0x8000 != 0 {
+        return Err(MidiError::UnsupportedTiming);
+    }
+
+    // Skip any chunks other than tracks, as the spec asks.
+    let mut tracks = Vec::new();
+    while !reader.data.is_empty() {
+        let id = reader.bytes(4)?;
+        let len = reader.u32()? as usize;
+        let chunk = reader.bytes(len)?;
+
+        if id == b"MTrk" {
+            tracks.push(read_track(chunk)?);
+        }
+    }
+
+    Ok(MidiFile {
+        ticks_per_quarter,
+        tracks,
+    })
+}
+
+fn read_track(data: &[u8]) -> Result<Track, MidiError> {
+    let mut reader = Reader { data };
+    let mut track = Track::default();
+    let mut time = 0u32;
+    let mut running_status = None;
+
+    // Notes which have started but not finished, as the channel, key and start time.
+    let mut sounding: Vec<(u8, u8, u32)> = Vec::new();
+
+    while !reader.data.is_empty() {
+        time = time.saturating_add(reader.varint()?);
+
+        // A data byte in place of the status byte repeats the last channel message's status.
+        let byte = reader.u8()?;
+        let (status, first) = if byte & 0x80 != 0 {
+            (byte, None)
+        } else {
+            (running_status.ok_or(MidiError::InvalidEvent)?, Some(byte))
+        };
+
+        match status {
+            0xff => {
+                let kind = reader.u8()?;
+                let len = reader.varint()? as usize;
+                let data = reader.bytes(len)?;
+
+                match (kind, data) {
+                    (0x03, _) if track.name.is_none() => {
+                        track.name = Some(String::from_utf8_lossy(data).into_owned());
+                    }
+                    (0x51, &[a, b, c]) => {
+                        track.tempos.push((time, u32::from_be_bytes([0, a, b, c])))
+                    }
+                    (0x2f, _) => break,
+                    _ => {}
+                }
+            }
+            0xf0 | 0xf7 => {
+                let len = reader.varint()? as usize;
+                reader.bytes(len)?;
+            }
+            0x80..=0xef => {
+                running_status = Some(status);
+
+                let channel = status & 0x0f;
+                let key = match first {
+                    Some(byte) => byte,
+                    None => reader.data_byte()?,
+                };
+
+                // Program change and channel pressure have a single data byte.
+                if matches!(status & 0xf0, 0xc0 | 0xd0) {
+                    continue;
+                }
+
+                let velocity = reader.data_byte()?;
+
+                match status & 0xf0 {
+                    // A note on with no velocity is a note off.
+                    0x90 if velocity > 0 => sounding.push((channel, key, time)),
+                    0x80 | 0x90 => {
+                        let found = sounding
+                            .iter()
+                            .position(|&(c, k, _)| c == channel && k == key);
+
+                        if let Some(i) = found {
+                            let (_, _, start) = sounding.remove(i);
+                            track.notes.push(MidiNote {
+                                start,
+                                end: time,
+                                key,
+                                channel,
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => return Err(MidiError::InvalidEvent),
+        }
+    }
+
+    // Notes still sounding at the end of the track finish there.
+    for (channel, key, start) in sounding {
+        track.notes.push(MidiNote {
+            start,
+            end: time,
+            key,
+            channel,
+        });
+    }
+
+    track.notes.sort_by_key(|note| note.start);
+
+    Ok(track)
+}
+
+/// Reduces the notes to a single line, picking one note whenever several overlap. Returns the
+/// parts of the line as the index of the note, and the start and end times.
+fn melody(notes: &[MidiNote], voice: Voice) -> Vec<(usize, u32, u32)> {
+    // The note to keep from those sounding is the first in this order. When the same key overlaps
+    // itself, the note that started first wins.
+    let rank = |i: usize| {
+        let key = notes[i].key as i16;
+        match voice {
+            Voice::Highest => (-key, i),
+            Voice::Lowest => (key, i),
+        }
+    };
+
+    // Sweep through the starts and ends in order, keeping track of the notes that are sounding.
+    // At the same time, ends sort before starts.
+    let mut events: Vec<(u32, bool, usize)> = notes
+        .iter()
+        .enumerate()
+        .filter(|(_, note)| note.start < note.end)
+        .flat_map(|(i, note)| [(note.start, true, i), (note.end, false, i)])
+        .collect();
+    events.sort_unstable();
+
+    let mut events = events.into_iter().peekable();
+    let mut sounding = BTreeSet::new();
+    let mut line: Vec<(usize, u32, u32)> = Vec::new();
+
+    while let Some(&(start, _, _)) = events.peek() {
+        while let Some((_, starts, i)) = events.next_if(|&(time, _, _)| time == start) {
+            if starts {
+                sounding.insert(rank(i));
+            } else {
+                sounding.remove(&rank(i));
+            }
+        }
+
+        let Some(&(end, _, _)) = events.peek() else {
+            break;
+        };
+        let Some(&(_, i)) = sounding.first() else {
+            continue;
+        };
+
+        match line.last_mut() {
+            Some(last) if last.0 == i && last.2 == start => last.2 = end,
+            _ => line.push((i, start, end)),
+        }
+    }
+
+    line
+}
+
+/// Every length a single RTTTL note can have, in ticks, with the duration and whether it's dotted.
+/// Some lengths can be written two ways, e.g. a half note is also a dotted half note triplet, so
+/// the plain durations come first to be preferred.
+fn lengths() -> impl Iterator<Item = (u32, Duration, bool)> {
+    let durations = (1..=96).filter_map(Duration::from_denominator);
+
+    durations
+        .clone()
+        .map(|d| (d.ticks(), d, false))
+        .chain(durations.map(|d| (d.ticks() * 3 / 2, d, true)))
+}
+
+/// The note length closest to the given number of ticks, preferring the shorter on a tie. `None`
+/// if it's closer to nothing at all.
+fn nearest_length(ticks: u32) -> Option<(u32, Duration, bool)> {
+    lengths()
+        .chain([(0, Duration::Whole, false)])
+        .min_by_key(|&(length, _, _)| (length.abs_diff(ticks), length))
+        .filter(|&(length, _, _)| length > 0)
+}
+
+/// The length of the next rest to fill a gap of the given ticks: the longest which fits, or the
+/// nearest if none of them do.
+fn rest_length(ticks: u32) -> Option<(u32, Duration, bool)> {
+    lengths()
+        .filter(|&(length, _, _)| length <= ticks)
+        .min_by_key(|&(length, _, _)| Reverse(length))
+        .or_else(|| nearest_length(ticks))
+}
+
+/// The inverse of `key`, for keys from 12 (C0) upwards.
+pub(super) fn pitch_octave(key: u8) -> (Pitch, Octave) {
+    let pitch = match key % 12 {
+        0 => Pitch::C,
+        1 => Pitch::Db,
+        2 => Pitch::D,
+        3 => Pitch::Eb,
+        4 => Pitch::E,
+        5 => Pitch::F,
+        6 => Pitch::Gb,
+        7 => Pitch::G,
+        8 => Pitch::Ab,
+        9 => Pitch::A,
+        10 => Pitch::Bb,
+        _ => Pitch::B,
+    };
+
+    let octave = Octave::from_number(key / 12 - 1).expect("key is a valid MIDI key");
+
+    (pitch, octave)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_varint() {
+        for (value, expected) in [
+            (0, &[0x00][..]),
+            (0x40, &[0x40]),
+            (0x7f, &[0x7f]),
+            (0x80, &[0x81, 0x00]),
+            (0x2000, &[0xc0, 0x00]),
+            (0x3fff, &[0xff, 0x7f]),
+            (0x0fff_ffff, &[0xff, 0xff, 0xff, 0x7f]),
+        ] {
+            let mut out = Vec::new();
+            write_varint(&mut out, value);
+            assert_eq!(out, expected, "{value:#x}");
+        }
+    }
+
+    #[test]
+    fn test_write_midi() {
+        let ringtone =
+            Ringtone::parse("Two:d=4,o=4,b=120,s=s:a,8p,c#5.").expect("successful parse");
+
+        let midi = write(&ringtone).expect("successful write");
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96,
+            b'M', b'T', b'r', b'k', 0, 0, 0, 34,
+            // Name and tempo (500,000 microseconds per quarter note).
+            0x00, 0xff, 0x03, 3, b'T', b'w', b'o',
+            0x00, 0xff, 0x51, 3, 0x07, 0xa1, 0x20,
+            // A4, sounding for half its length with the staccato style.
+            0x00, 0x90, 69, 100, 48, 0x80, 69, 0,
+            // C#5, dotted, after the gap of 48 and the rest of 48 ticks.
+            96, 0x90, 73, 100, 72, 0x80, 73, 0,
+            // The end of the track after the last gap.
+            72, 0xff, 0x2f, 0x00,
+        ];
+
+        assert_eq!(midi, expected);
+    }
+
+    #[test]
+    fn test_midi_round_trip() {
+        let ringtone = Ringtone::parse("Scale:d=8,o=5,b=125:c,d,e,4f,g,a6,2p,b,16c6.")
+            .expect("successful parse");
+
+        let midi = write(&ringtone).expect("successful write");
+        let (imported, losses) =
+            read(&midi, &MidiImportOptions::default()).expect("successful import");
+
+        assert_eq!(imported, ringtone);
+        assert!(losses.is_empty(), "{losses:?}");
+    }
+
+    #[test]
+    fn test_write_midi_out_of_range() {
+        let write = |rtttl| write(&Ringtone::parse(rtttl).expect("successful parse"));
+
+        assert!(write("High:d=4,o=9,b=100:g").is_ok());
+        assert_eq!(
+            write("High:d=4,o=9,b=100:g,b"),
+            Err(MidiError::KeyOutOfRange(1))
+        );
+        assert_eq!(
+            write("High:d=4,o=5,b=100:c,g#9"),
+            Err(MidiError::KeyOutOfRange(1))
+        );
+    }
+
+    #[test]
+    fn test_read_midi() {
+        #[rustfmt::skip]
+        let midi: &[u8] = &[
+            b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 2, 0x01, 0xe0,
+            // The tempo track, with the name.
+            b'M', b'T', b'r', b'k', 0, 0, 0, 27,
+            0x00, 0xff, 0x03, 4, b'S', b'o', b'n', b'g',
+            0x00, 0xff, 0x51, 3, 0x09, 0x27, 0xc0,
+            0x8f, 0x00, 0xff, 0x51, 3, 0x07, 0xa1, 0x20,
+            0x00, 0xff, 0x2f, 0x00,
+            // The notes: a slightly short chord with another note on channel 1, then an eighth
+            // note, an eighth rest and a half note.
+            b'M', b'T', b'r', b'k', 0, 0, 0, 52,
+            0x00, 0x90, 60, 100,
+            0x00, 64, 100,
+            0x00, 67, 100,
+            0x00, 0x91, 84, 100,
+            0x83, 0x5b, 0x90, 60, 0,
+            0x00, 64, 0,
+            0x00, 67, 0,
+            0x05, 0x81, 84, 0,
+            0x00, 0x90, 69, 100,
+            0x81, 0x70, 0x80, 69, 0,
+            0x81, 0x70, 0x90, 71, 100,
+            0x87, 0x40, 0x80, 71, 0,
+            0x00, 0xff, 0x2f, 0x00,
+        ];
+
+        let (ringtone, losses) = read(midi, &MidiImportOptions::default()).expect("import");
+        assert_eq!(ringtone.to_rtttl(), "Song:d=8,o=4,b=100:4c6,a,p,2b");
+        assert_eq!(
+            losses,
+            [MidiLoss::TempoChanges(1), MidiLoss::DroppedNotes(3)]
+        );
+
+        let options = MidiImportOptions {
+            channel: Some(0),
+            ..MidiImportOptions::default()
+        };
+        let (ringtone, losses) = read(midi, &options).expect("import");
+        assert_eq!(ringtone.to_rtttl(), "Song:d=8,o=4,b=100:4g,a,p,2b");
+        assert_eq!(
+            losses,
+            [
+                MidiLoss::TempoChanges(1),
+                MidiLoss::DroppedNotes(2),
+                MidiLoss::Quantized(1)
+            ]
+        );
+
+        let options = MidiImportOptions {
+            channel: Some(0),
+            voice: Voice::Lowest,
+            ..MidiImportOptions::default()
+        };
+        let (ringtone, _) = read(midi, &options).expect("import");
+        assert_eq!(ringtone.to_rtttl(), "Song:d=8,o=4,b=100:4c,a,p,2b");
+
+        let options = MidiImportOptions {
+            track: Some(0),
+            ..MidiImportOptions::default()
+        };
+        assert_eq!(read(midi, &options), Err(MidiError::NoNotes));
+
+        let options = MidiImportOptions {
+            track: Some(2),
+            ..MidiImportOptions::default()
+        };
+        assert_eq!(read(midi, &options), Err(MidiError::NoSuchTrack(2)));
+
+        let options = MidiImportOptions::default();
+        assert_eq!(read(&midi[..60], &options), Err(MidiError::Truncated));
+        assert_eq!(read(b"RIFF", &options), Err(MidiError::NotMidi));
+    }
+
+    #[test]
+    fn test_melody() {
+        let note = |start, end, key| MidiNote {
+            start,
+            end,
+            key,
+            channel: 0,
+        };
+        let notes = [
+            note(0, 100, 60),
+            note(0, 50, 64),
+            note(25, 75, 64),
+            note(50, 50, 72),
+            note(100, 200, 55),
+            note(150, 250, 67),
+        ];
+
+        // The same key overlapping itself keeps the note that started first, and a note with no
+        // length never sounds.
+        assert_eq!(
+            melody(&notes, Voice::Highest),
+            [
+                (1, 0, 50),
+                (2, 50, 75),
+                (0, 75, 100),
+                (4, 100, 150),
+                (5, 150, 250)
+            ]
+        );
+        assert_eq!(
+            melody(&notes, Voice::Lowest),
+            [(0, 0, 100), (4, 100, 200), (5, 200, 250)]
+        );
+
+        // Thousands of overlapping notes are quick to reduce.
+        let chords: Vec<_> = (0..20_000)
+            .map(|i| note(i * 10, i * 10 + 95, (i % 88) as u8 + 21))
+            .collect();
+        assert_eq!(melody(&chords, Voice::Lowest).first(), Some(&(0, 0, 95)));
+    }
+
+    #[test]
+    fn test_read_midi_invalid_key() {
+        // A note on with a status byte for its key, which isn't a valid MIDI key.
+        #[rustfmt::skip]
+        let midi: &[u8] = &[
+            b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96,
+            b'M', b'T', b'r', b'k', 0, 0, 0, 12,
+            0x00, 0x90, 0x85, 100,
+            0x60, 0x80, 0x85, 0,
+            0x00, 0xff, 0x2f, 0x00,
+        ];
+
+        assert_eq!(
+            read(midi, &MidiImportOptions::default()),
+            Err(MidiError::InvalidEvent)
+        );
+    }
+}
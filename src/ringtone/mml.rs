@@ -0,0 +1,450 @@
+use std::ops::RangeInclusive;
+
+use super::*;
+
+/// The tempos that BASIC's `T` command allows.
+const TEMPOS: RangeInclusive<Tempo> = 32..=255;
+
+/// The lengths that MML allows, as the denominator of a whole note. Triplets fit, as `L12` and so
+/// on, but the 96th triplet doesn't.
+const LENGTHS: RangeInclusive<u8> = 1..=64;
+
+/// Writes the ringtone as an MML string, in the dialect of the BASIC `PLAY` statement.
+///
+/// MML octaves are numbered as in RTTTL, so `O4 A` is 440Hz. Lengths are only written when they
+/// differ from the `L` default, apart from pauses, which always have one. MML has no loops, so the
+/// melody is written out again for each.
+pub(super) fn write(ringtone: &Ringtone) -> Result<String, MmlError> {
+    let settings = &ringtone.settings;
+
+    if !TEMPOS.contains(&settings.tempo) {
+        return Err(MmlError::UnsupportedTempo(settings.tempo));
+    }
+
+    // A default that MML doesn't have is never used, since the notes that inherit it can't be
+    // written either.
+    let default = if LENGTHS.contains(&settings.duration.denominator()) {
+        settings.duration
+    } else {
+        Duration::Quarter
+    };
+
+    let style = match settings.style {
+        Style::Natural => "MN",
+        Style::Continuous => "ML",
+        Style::Staccato => "MS",
+    };
+
+    let mut melody = Vec::new();
+    let mut octave = settings.octave;
+
+    for (i, note) in ringtone.notes.iter().enumerate() {
+        let duration = note.duration.unwrap_or(settings.duration);
+        if !LENGTHS.contains(&duration.denominator()) {
+            return Err(MmlError::UnsupportedDuration(i));
+        }
+
+        let mut token = match note.pitch {
+            Some(pitch) => {
+                let note_octave = note.octave.unwrap_or(settings.octave);
+                if note_octave != octave {
+                    melody.push(octave_change(octave, note_octave));
+                    octave = note_octave;
+                }
+
+                let mut token = name(pitch).to_owned();
+                if duration != default {
+                    token.push_str(&duration.denominator().to_string());
+                }
+
+                token
+            }
+            None => format!("P{}", duration.denominator()),
+        };
+
+        if note.dotted {
+            token.push('.');
+        }
+
+        melody.push(token);
+    }
+
+    // Each time round starts back in the default octave.
+    if octave != settings.octave {
+        melody.push(octave_change(octave, settings.octave));
+    }
+
+    let mut tokens = vec![
+        format!("T{}", settings.tempo),
+        format!("O{}", settings.octave.number()),
+        format!("L{}", default.denominator()),
+        style.to_owned(),
+    ];
+    for _ in 0..settings.plays() {
+        tokens.extend(melody.iter().cloned());
+    }
+
+    // The octave change at the end of the last time round isn't needed.
+    if octave != settings.octave {
+        tokens.pop();
+    }
+
+    Ok(tokens.join(" "))
+}
+
+/// Reads an MML string, optionally wrapped in a BASIC `PLAY` statement.
+///
+/// The defaults are those of BASIC: `T120 O4 L4 MN`. The tempo and style apply to the whole
+/// ringtone, so they can't change once the melody has started. Volume (`V`) and the foreground
+/// and background modes (`MF` and `MB`) don't affect the melody, and are skipped.
+pub(super) fn read(input: &str) -> Result<Ringtone, MmlError> {
+    let (base, body) = strip_play(input);
+    let bytes = body.as_bytes();
+
+    let mut settings = Settings {
+        duration: Duration::Quarter,
+        octave: Octave::O4,
+        tempo: 120,
+        loops: 0,
+        style: Style::Natural,
+    };
+    let mut notes = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let offset = base + i;
+        let command = bytes[i].to_ascii_uppercase();
+        i += 1;
+
+        let invalid = MmlError::InvalidValue(offset);
+        let started = !notes.is_empty();
+
+        match command {
+            b' ' | b'\t' | b'\r' | b'\n' | b';' => {}
+            b'T' => {
+                let tempo = number(bytes, &mut i)
+                    .and_then(|tempo| Tempo::try_from(tempo).ok())
+                    .filter(|tempo| TEMPOS.contains(tempo))
+                    .ok_or(invalid)?;
+
+                if started && tempo != settings.tempo {
+                    return Err(MmlError::Unrepresentable(offset));
+                }
+                settings.tempo = tempo;
+            }
+            b'O' => {
+                settings.octave = number(bytes, &mut i)
+                    .and_then(|number| Octave::from_number(u8::try_from(number).ok()?))
+                    .ok_or(invalid)?;
+            }
+            b'<' | b'>' => {
+                let number = settings.octave.number();
+                let number = if command == b'<' {
+                    number.checked_sub(1)
+                } else {
+                    Some(number + 1)
+                };
+
+                settings.octave = number.and_then(Octave::from_number).ok_or(invalid)?;
+            }
+            b'L' => {
+                let (duration, dotted) = length(bytes, &mut i, offset, None)?;
+                if dotted {
+                    return Err(MmlError::Unrepresentable(offset));
+                }
+                settings.duration = duration;
+            }
+            b'M' => {
+                let style = match bytes.get(i).map(u8::to_ascii_uppercase) {
+                    Some(b'N') => Some(Style::Natural),
+                    Some(b'L') => Some(Style::Continuous),
+                    Some(b'S') => Some(Style::Staccato),
+                    Some(b'F' | b'B') => None,
+                    _ => return Err(MmlError::InvalidCommand(offset)),
+                };
+                i += 1;
+
+                if let Some(style) = style {
+                    if started && style != settings.style {
+                        return Err(MmlError::Unrepresentable(offset));
+                    }
+                    settings.style = style;
+                }
+            }
+            b'V' => {
+                number(bytes, &mut i).ok_or(invalid)?;
+            }
+            b'P' | b'R' => {
+                let (duration, dotted) = length(bytes, &mut i, offset, Some(settings.duration))?;
+                notes.push(Note {
+                    duration: Some(duration),
+                    pitch: None,
+                    octave: None,
+                    dotted,
+                });
+            }
+            // A note by number, from 1 for C in octave 0, or 0 for a rest.
+            b'N' => {
+                let key = number(bytes, &mut i)
+                    .filter(|&number| number <= 120)
+                    .ok_or(invalid)?;
+                let (pitch, octave) = match key {
+                    0 => (None, None),
+                    key => {
+                        let (pitch, octave) = midi::pitch_octave(key as u8 + 11);
+                        (Some(pitch), Some(octave))
+                    }
+                };
+                let (duration, dotted) = length(bytes, &mut i, offset, Some(settings.duration))?;
+
+                notes.push(Note {
+                    duration: Some(duration),
+                    pitch,
+                    octave,
+                    dotted,
+                });
+            }
+            b'A'..=b'G' => {
+                let semitone = match command {
+                    b'C' => 0,
+                    b'D' => 2,
+                    b'E' => 4,
+                    b'F' => 5,
+                    b'G' => 7,
+                    b'A' => 9,
+                    _ => 11,
+                };
+                let accidental = match bytes.get(i) {
+                    Some(b'+' | b'#') => 1,
+                    Some(b'-') => -1,
+                    _ => 0,
+                };
+                if accidental != 0 {
+                    i += 1;
+                }
+
+                // Sharps and flats can cross into the next octave, as with `B+` and `C-`.
+                let key = (settings.octave.number() as i32 + 1) * 12 + semitone + accidental;
+                let key = u8::try_from(key)
+                    .ok()
+                    .filter(|key| (12..132).contains(key))
+                    .ok_or(invalid)?;
+                let (pitch, octave) = midi::pitch_octave(key);
+                let (duration, dotted) = length(bytes, &mut i, offset, Some(settings.duration))?;
+
+                notes.push(Note {
+                    duration: Some(duration),
+                    pitch: Some(pitch),
+                    octave: Some(octave),
+                    dotted,
+                });
+            }
+            _ => return Err(MmlError::InvalidCommand(offset)),
+        }
+    }
+
+    if notes.is_empty() {
+        return Err(MmlError::NoNotes);
+    }
+
+    let mut ringtone = Ringtone {
+        name: UNTITLED.to_owned(),
+        settings,
+        notes,
+    };
+    ringtone.compact();
+
+    Ok(ringtone)
+}
+
+/// Strips a BASIC `PLAY "..."` statement around the MML, if there is one, returning the offset of
+/// the MML in the input.
+fn strip_play(input: &str) -> (usize, &str) {
+    let trimmed = input.trim_start();
+    let play = trimmed
+        .get(..4)
+        .filter(|play| play.eq_ignore_ascii_case("PLAY"));
+
+    match play {
+        Some(_) => {
+            let body = trimmed[4..].trim();
+            let body = body.strip_prefix('"').unwrap_or(body);
+            let body = body.strip_suffix('"').unwrap_or(body);
+
+            (body.as_ptr() as usize - input.as_ptr() as usize, body)
+        }
+        None => (0, input),
+    }
+}
+
+/// Reads a number, if there is one.
+fn number(bytes: &[u8], i: &mut usize) -> Option<u32> {
+    let len = bytes[*i..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit())
+        .count();
+    let number = std::str::from_utf8(&bytes[*i..*i + len])
+        .ok()?
+        .parse()
+        .ok()?;
+    *i += len;
+
+    Some(number)
+}
+
+/// Reads a length and any dot, falling back to the default if there's no number. MML allows any
+/// length in `LENGTHS`, but only the RTTTL durations can be imported.
+fn length(
+    bytes: &[u8],
+    i: &mut usize,
+    offset: usize,
+    default: Option<Duration>,
+) -> Result<(Duration, bool), MmlError> {
+    let duration = match number(bytes, i) {
+        Some(number) => {
+            let number = u8::try_from(number)
+                .ok()
+                .filter(|number| LENGTHS.contains(number))
+                .ok_or(MmlError::InvalidValue(offset))?;
+
+            Duration::from_denominator(number).ok_or(MmlError::Unrepresentable(offset))?
+        }
+        None => default.ok_or(MmlError::InvalidValue(offset))?,
+    };
+
+    let dots = bytes[*i..].iter().take_while(|&&b| b == b'.').count();
+    *i += dots;
+    if dots > 1 {
+        return Err(MmlError::Unrepresentable(offset));
+    }
+
+    Ok((duration, dots == 1))
+}
+
+fn octave_change(from: Octave, to: Octave) -> String {
+    match to.number() as i32 - from.number() as i32 {
+        1 => ">".to_owned(),
+        -1 => "<".to_owned(),
+        _ => format!("O{}", to.number()),
+    }
+}
+
+fn name(pitch: Pitch) -> &'static str {
+    match pitch {
+        Pitch::A => "A",
+        Pitch::Bb => "A#",
+        Pitch::B => "B",
+        Pitch::C => "C",
+        Pitch::Db => "C#",
+        Pitch::D => "D",
+        Pitch::Eb => "D#",
+        Pitch::E => "E",
+        Pitch::F => "F",
+        Pitch::Gb => "F#",
+        Pitch::G => "G",
+        Pitch::Ab => "G#",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_mml() {
+        let ringtone = Ringtone::parse(include_str!("../../examples/nokia.txt").trim())
+            .expect("successful parse");
+
+        assert_eq!(
+            write(&ringtone),
+            Ok(
+                "T120 O5 L4 ML > E16 D16 < F#8 G#8 > C#16 < B16 D8 E8 B16 A16 C#8 E8 A2 P2"
+                    .to_owned()
+            )
+        );
+
+        let ringtone =
+            Ringtone::parse("Loop:d=12,o=4,b=90,s=s,l=1:c.,8p,c7,b6").expect("successful parse");
+
+        assert_eq!(
+            write(&ringtone),
+            Ok("T90 O4 L12 MS C. P8 O7 C < B O4 C. P8 O7 C < B".to_owned())
+        );
+
+        // A default length that MML doesn't have isn't written.
+        let ringtone = Ringtone::parse("Short:d=96,o=4,b=90:4c,8p").expect("successful parse");
+        assert_eq!(write(&ringtone), Ok("T90 O4 L4 ML C P8".to_owned()));
+    }
+
+    #[test]
+    fn test_write_mml_errors() {
+        let unsupported = |rtttl| {
+            write(&Ringtone::parse(rtttl).expect("successful parse")).expect_err("failed write")
+        };
+
+        assert_eq!(
+            unsupported("Fast:d=4,o=5,b=900:c"),
+            MmlError::UnsupportedTempo(900)
+        );
+        assert_eq!(
+            unsupported("Slow:d=4,o=5,b=31:c"),
+            MmlError::UnsupportedTempo(31)
+        );
+        assert_eq!(
+            unsupported("Short:d=4,o=5,b=120:c,96c"),
+            MmlError::UnsupportedDuration(1)
+        );
+        assert_eq!(
+            unsupported("Short:d=96,o=5,b=120:4c,c"),
+            MmlError::UnsupportedDuration(1)
+        );
+    }
+
+    #[test]
+    fn test_read_mml() {
+        let ringtone =
+            read("PLAY \"t150o4l8 ms cd-e+ p4 <b>>c4. r n0 n46;mf v10\"").expect("successful read");
+
+        assert_eq!(
+            ringtone.to_rtttl(),
+            "Untitled:d=8,o=4,b=150,s=s:c,c#,f,4p,b3,4c5.,p,p,a3"
+        );
+
+        // The defaults are T120 O4 L4 MN.
+        let ringtone = read("C").expect("successful read");
+        assert_eq!(ringtone.to_rtttl(), "Untitled:d=4,o=4,b=120,s=n:c");
+    }
+
+    #[test]
+    fn test_mml_round_trip() {
+        for input in [
+            "Untitled:d=16,o=5,b=180,s=c:e6,d6,8f#,c#6,b,a,2a",
+            "Untitled:d=12,o=4,b=90,s=s:c.,8p,c7,b6,c",
+            "Untitled:d=48,o=9,b=255,s=n:c,c0,p.,1b,b",
+            "Untitled:d=3,o=5,b=32,s=n:c,6d,64e.",
+        ] {
+            let ringtone = Ringtone::parse(input).expect("successful parse");
+            let mml = write(&ringtone).expect("successful write");
+
+            assert_eq!(read(&mml), Ok(ringtone), "{mml}");
+        }
+    }
+
+    #[test]
+    fn test_read_mml_errors() {
+        assert_eq!(read("C X"), Err(MmlError::InvalidCommand(2)));
+        assert_eq!(read("PLAY \"C Q\""), Err(MmlError::InvalidCommand(8)));
+        assert_eq!(read("O10 C"), Err(MmlError::InvalidValue(0)));
+        assert_eq!(read("O0 C-"), Err(MmlError::InvalidValue(3)));
+        assert_eq!(read("T0 C"), Err(MmlError::InvalidValue(0)));
+        assert_eq!(read("T256 C"), Err(MmlError::InvalidValue(0)));
+        assert_eq!(read("L C"), Err(MmlError::InvalidValue(0)));
+        assert_eq!(read("C5"), Err(MmlError::Unrepresentable(0)));
+        assert_eq!(read("C65"), Err(MmlError::InvalidValue(0)));
+        assert_eq!(read("C96"), Err(MmlError::InvalidValue(0)));
+        assert_eq!(read("C4.."), Err(MmlError::Unrepresentable(0)));
+        assert_eq!(read("C T100"), Err(MmlError::Unrepresentable(2)));
+        assert_eq!(read("MN C ML D"), Err(MmlError::Unrepresentable(5)));
+        assert_eq!(read("T100 MS"), Err(MmlError::NoNotes));
+    }
+}
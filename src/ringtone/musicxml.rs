@@ -0,0 +1,476 @@
+use std::cmp::Reverse;
+use std::fmt::Write;
+
+use super::*;
+
+/// Every measure is in 4/4 time, since RTTTL has no time signature.
+const MEASURE: u32 = TICKS_PER_QUARTER * 4;
+
+const DURATIONS: [Duration; 13] = [
+    Duration::Whole,
+    Duration::Half,
+    Duration::HalfTriplet,
+    Duration::Quarter,
+    Duration::QuarterTriplet,
+    Duration::Eighth,
+    Duration::EighthTriplet,
+    Duration::Sixteenth,
+    Duration::SixteenthTriplet,
+    Duration::ThirtySecond,
+    Duration::ThirtySecondTriplet,
+    Duration::SixtyFourth,
+    Duration::SixtyFourthTriplet,
+];
+
+/// A note value as a duration and whether it's dotted.
+type Value = (Duration, bool);
+
+/// Writes the ringtone as a MusicXML score, with a single part in 4/4 time.
+///
+/// One division is one tick, so every note value has a whole number of divisions. Notes that
+/// cross a barline are split and tied, and the last measure is filled out with rests. The loop
+/// count becomes repeat barlines around the whole melody.
+pub(super) fn write(ringtone: &Ringtone) -> String {
+    let settings = &ringtone.settings;
+    let values = note_values();
+
+    let mut measures = vec![String::new()];
+    let mut position = 0;
+
+    for note in &ringtone.notes {
+        let pitch = note
+            .pitch
+            .map(|pitch| (pitch, note.octave.unwrap_or(settings.octave)));
+
+        // Split the note at the barlines, and into note values that fit.
+        let mut pieces = Vec::new();
+        let mut remaining = note.ticks(settings);
+        while remaining > 0 {
+            let part = remaining.min(MEASURE - position);
+            let measure = measures.len() - 1;
+            pieces.extend(
+                split(part, &values)
+                    .into_iter()
+                    .map(|(ticks, value)| (measure, ticks, value)),
+            );
+
+            remaining -= part;
+            position += part;
+            if position == MEASURE {
+                measures.push(String::new());
+                position = 0;
+            }
+        }
+
+        let count = pieces.len();
+        for (i, (measure, ticks, value)) in pieces.into_iter().enumerate() {
+            let tie = (i > 0, i + 1 < count);
+            let staccato = i == 0 && settings.style == Style::Staccato;
+            write_note(&mut measures[measure], pitch, ticks, value, tie, staccato);
+        }
+    }
+
+    // Fill out the last measure, or drop it if the melody ended on a barline.
+    if position > 0 {
+        let measure = measures.last_mut().expect("at least one measure");
+        for (ticks, value) in split(MEASURE - position, &values) {
+            write_note(measure, None, ticks, value, (false, false), false);
+        }
+    } else {
+        measures.pop();
+    }
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n");
+    out.push_str(
+        "<!DOCTYPE score-partwise PUBLIC \"-//Recordare//DTD MusicXML 4.0 Partwise//EN\" \
+         \"http://www.musicxml.org/dtds/partwise.dtd\">\n",
+    );
+    out.push_str("<score-partwise version=\"4.0\">\n");
+    writeln!(
+        out,
+        "  <work>\n    <work-title>{}</work-title>\n  </work>",
+        escape(&ringtone.name)
+    )
+    .expect("write to string");
+    out.push_str("  <part-list>\n");
+    out.push_str("    <score-part id=\"P1\">\n");
+    out.push_str("      <part-name>Ringtone</part-name>\n");
+    out.push_str("    </score-part>\n");
+    out.push_str("  </part-list>\n");
+    out.push_str("  <part id=\"P1\">\n");
+
+    let last = measures.len() - 1;
+    for (i, measure) in measures.iter().enumerate() {
+        writeln!(out, "    <measure number=\"{}\">", i + 1).expect("write to string");
+
+        if i == 0 {
+            if settings.plays() > 1 {
+                out.push_str("      <barline location=\"left\">\n");
+                out.push_str("        <repeat direction=\"forward\"/>\n");
+                out.push_str("      </barline>\n");
+            }
+
+            out.push_str("      <attributes>\n");
+            writeln!(out, "        <divisions>{TICKS_PER_QUARTER}</divisions>")
+                .expect("write to string");
+            out.push_str("        <key>\n");
+            out.push_str("          <fifths>0</fifths>\n");
+            out.push_str("        </key>\n");
+            out.push_str("        <time>\n");
+            out.push_str("          <beats>4</beats>\n");
+            out.push_str("          <beat-type>4</beat-type>\n");
+            out.push_str("        </time>\n");
+            out.push_str("        <clef>\n");
+            out.push_str("          <sign>G</sign>\n");
+            out.push_str("          <line>2</line>\n");
+            out.push_str("        </clef>\n");
+            out.push_str("      </attributes>\n");
+
+            // The tempo as a metronome mark, and for playback.
+            out.push_str("      <direction placement=\"above\">\n");
+            out.push_str("        <direction-type>\n");
+            out.push_str("          <metronome>\n");
+            out.push_str("            <beat-unit>quarter</beat-unit>\n");
+            writeln!(
+                out,
+                "            <per-minute>{}</per-minute>",
+                settings.tempo
+            )
+            .expect("write to string");
+            out.push_str("          </metronome>\n");
+            out.push_str("        </direction-type>\n");
+            writeln!(out, "        <sound tempo=\"{}\"/>", settings.tempo)
+                .expect("write to string");
+            out.push_str("      </direction>\n");
+        }
+
+        out.push_str(measure);
+
+        // The repeat count is how many times the melody plays in total.
+        if i == last && settings.plays() > 1 {
+            out.push_str("      <barline location=\"right\">\n");
+            writeln!(
+                out,
+                "        <repeat direction=\"backward\" times=\"{}\"/>",
+                settings.plays()
+            )
+            .expect("write to string");
+            out.push_str("      </barline>\n");
+        }
+
+        out.push_str("    </measure>\n");
+    }
+
+    out.push_str("  </part>\n");
+    out.push_str("</score-partwise>\n");
+
+    out
+}
+
+/// Every note value with its length in ticks, longest first, preferring plain values to dotted
+/// and triplet ones of the same length.
+pub(super) fn note_values() -> Vec<(u32, Value)> {
+    let mut values: Vec<_> = DURATIONS
+        .iter()
+        .flat_map(|&duration| {
+            let ticks = duration.ticks();
+            [
+                (ticks, (duration, false)),
+                (ticks * 3 / 2, (duration, true)),
+            ]
+        })
+        .collect();
+
+    values.sort_by_key(|&(ticks, (duration, dotted))| {
+        (Reverse(ticks), duration.is_triplet(), dotted)
+    });
+
+    values
+}
+
+/// Splits a length into note values, longest first. Any leftover too short for a note value is
+/// written as just a length, which only happens after unusual mixes of dotted and triplet notes.
+pub(super) fn split(mut ticks: u32, values: &[(u32, Value)]) -> Vec<(u32, Option<Value>)> {
+    let mut pieces = Vec::new();
+
+    while ticks > 0 {
+        match values.iter().find(|&&(length, _)| length <= ticks) {
+            Some(&(length, value)) => {
+                pieces.push((length, Some(value)));
+                ticks -= length;
+            }
+            None => {
+                pieces.push((ticks, None));
+                ticks = 0;
+            }
+        }
+    }
+
+    pieces
+}
+
+/// Writes a `<note>` element. Rests are never tied.
+fn write_note(
+    out: &mut String,
+    pitch: Option<(Pitch, Octave)>,
+    ticks: u32,
+    value: Option<Value>,
+    (tie_stop, tie_start): (bool, bool),
+    staccato: bool,
+) {
+    let (tie_stop, tie_start) = (tie_stop && pitch.is_some(), tie_start && pitch.is_some());
+    let staccato = staccato && pitch.is_some();
+
+    out.push_str("      <note>\n");
+
+    match pitch {
+        Some((pitch, octave)) => {
+            let (step, alter) = step(pitch);
+            out.push_str("        <pitch>\n");
+            writeln!(out, "          <step>{step}</step>").expect("write to string");
+            if alter != 0 {
+                writeln!(out, "          <alter>{alter}</alter>").expect("write to string");
+            }
+            writeln!(out, "          <octave>{}</octave>", octave.number())
+                .expect("write to string");
+            out.push_str("        </pitch>\n");
+        }
+        None => out.push_str("        <rest/>\n"),
+    }
+
+    writeln!(out, "        <duration>{ticks}</duration>").expect("write to string");
+
+    if tie_stop {
+        out.push_str("        <tie type=\"stop\"/>\n");
+    }
+    if tie_start {
+        out.push_str("        <tie type=\"start\"/>\n");
+    }
+
+    if let Some((duration, dotted)) = value {
+        writeln!(out, "        <type>{}</type>", type_name(duration)).expect("write to string");
+
+        if dotted {
+            out.push_str("        <dot/>\n");
+        }
+
+        if duration.is_triplet() {
+            out.push_str("        <time-modification>\n");
+            out.push_str("          <actual-notes>3</actual-notes>\n");
+            out.push_str("          <normal-notes>2</normal-notes>\n");
+            out.push_str("        </time-modification>\n");
+        }
+    }
+
+    if tie_stop || tie_start || staccato {
+        out.push_str("        <notations>\n");
+        if tie_stop {
+            out.push_str("          <tied type=\"stop\"/>\n");
+        }
+        if tie_start {
+            out.push_str("          <tied type=\"start\"/>\n");
+        }
+        if staccato {
+            out.push_str("          <articulations>\n");
+            out.push_str("            <staccato/>\n");
+            out.push_str("          </articulations>\n");
+        }
+        out.push_str("        </notations>\n");
+    }
+
+    out.push_str("      </note>\n");
+}
+
+/// The MusicXML note type. Triplets are written as the next longest plain type, with a time
+/// modification.
+fn type_name(duration: Duration) -> &'static str {
+    match duration {
+        Duration::Whole => "whole",
+        Duration::Half | Duration::HalfTriplet => "half",
+        Duration::Quarter | Duration::QuarterTriplet => "quarter",
+        Duration::Eighth | Duration::EighthTriplet => "eighth",
+        Duration::Sixteenth | Duration::SixteenthTriplet => "16th",
+        Duration::ThirtySecond | Duration::ThirtySecondTriplet => "32nd",
+        Duration::SixtyFourth | Duration::SixtyFourthTriplet => "64th",
+    }
+}
+
+/// The step and alteration of the pitch. The black keys are written as flats, as `Pitch` names
+/// them.
+fn step(pitch: Pitch) -> (char, i8) {
+    match pitch {
+        Pitch::A => ('A', 0),
+        Pitch::Bb => ('B', -1),
+        Pitch::B => ('B', 0),
+        Pitch::C => ('C', 0),
+        Pitch::Db => ('D', -1),
+        Pitch::D => ('D', 0),
+        Pitch::Eb => ('E', -1),
+        Pitch::E => ('E', 0),
+        Pitch::F => ('F', 0),
+        Pitch::Gb => ('G', -1),
+        Pitch::G => ('G', 0),
+        Pitch::Ab => ('A', -1),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_musicxml() {
+        let ringtone = Ringtone::parse("A&B:d=2,o=5,b=100,l=1:c.,b4,8p").expect("successful parse");
+
+        assert_eq!(
+            write(&ringtone),
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 4.0 Partwise//EN" "http://www.musicxml.org/dtds/partwise.dtd">
+<score-partwise version="4.0">
+  <work>
+    <work-title>A&amp;B</work-title>
+  </work>
+  <part-list>
+    <score-part id="P1">
+      <part-name>Ringtone</part-name>
+    </score-part>
+  </part-list>
+  <part id="P1">
+    <measure number="1">
+      <barline location="left">
+        <repeat direction="forward"/>
+      </barline>
+      <attributes>
+        <divisions>96</divisions>
+        <key>
+          <fifths>0</fifths>
+        </key>
+        <time>
+          <beats>4</beats>
+          <beat-type>4</beat-type>
+        </time>
+        <clef>
+          <sign>G</sign>
+          <line>2</line>
+        </clef>
+      </attributes>
+      <direction placement="above">
+        <direction-type>
+          <metronome>
+            <beat-unit>quarter</beat-unit>
+            <per-minute>100</per-minute>
+          </metronome>
+        </direction-type>
+        <sound tempo="100"/>
+      </direction>
+      <note>
+        <pitch>
+          <step>C</step>
+          <octave>5</octave>
+        </pitch>
+        <duration>288</duration>
+        <type>half</type>
+        <dot/>
+      </note>
+      <note>
+        <pitch>
+          <step>B</step>
+          <octave>4</octave>
+        </pitch>
+        <duration>96</duration>
+        <tie type="start"/>
+        <type>quarter</type>
+        <notations>
+          <tied type="start"/>
+        </notations>
+      </note>
+    </measure>
+    <measure number="2">
+      <note>
+        <pitch>
+          <step>B</step>
+          <octave>4</octave>
+        </pitch>
+        <duration>96</duration>
+        <tie type="stop"/>
+        <type>quarter</type>
+        <notations>
+          <tied type="stop"/>
+        </notations>
+      </note>
+      <note>
+        <rest/>
+        <duration>48</duration>
+        <type>eighth</type>
+      </note>
+      <note>
+        <rest/>
+        <duration>192</duration>
+        <type>half</type>
+      </note>
+      <note>
+        <rest/>
+        <duration>48</duration>
+        <type>eighth</type>
+      </note>
+      <barline location="right">
+        <repeat direction="backward" times="2"/>
+      </barline>
+    </measure>
+  </part>
+</score-partwise>
+"#
+        );
+    }
+
+    #[test]
+    fn test_write_musicxml_values() {
+        // A flat triplet, staccato notes tied over the barlines, and a melody that ends on a
+        // barline.
+        let ringtone =
+            Ringtone::parse("Ties:d=4,o=5,b=120,s=s:6a#,6p,6p,1c,1d,2e").expect("successful parse");
+        let xml = write(&ringtone);
+
+        assert!(xml
+            .contains("<step>B</step>\n          <alter>-1</alter>\n          <octave>5</octave>"));
+        assert_eq!(xml.matches("<time-modification>").count(), 3);
+        assert_eq!(xml.matches("<measure ").count(), 3);
+        assert_eq!(xml.matches("<staccato/>").count(), 4);
+        assert_eq!(xml.matches("<tie type=\"start\"/>").count(), 2);
+        assert_eq!(xml.matches("<tie type=\"stop\"/>").count(), 2);
+        assert!(!xml.contains("<repeat"));
+
+        // Every measure is full.
+        for measure in xml.split("<measure ").skip(1) {
+            let ticks: u32 = measure
+                .match_indices("<duration>")
+                .map(|(i, _)| {
+                    let rest = &measure[i + "<duration>".len()..];
+                    rest[..rest.find('<').expect("closing tag")]
+                        .parse::<u32>()
+                        .expect("number")
+                })
+                .sum();
+
+            assert_eq!(ticks, MEASURE);
+        }
+    }
+}
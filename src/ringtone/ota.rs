@@ -0,0 +1,552 @@
+use super::*;
+
+/// The number of commands in a ringtone payload: ringing tone programming, then sound.
+const COMMANDS: u32 = 2;
+const RINGING_TONE_PROGRAMMING: u32 = 0b010_0101;
+const SOUND: u32 = 0b001_1101;
+const BASIC_SONG: u32 = 0b001;
+
+const PATTERN_HEADER: u32 = 0b000;
+const NOTE: u32 = 0b001;
+const SCALE: u32 = 0b010;
+const STYLE: u32 = 0b011;
+const TEMPO: u32 = 0b100;
+const VOLUME: u32 = 0b101;
+
+/// The longest name that fits in the 4 bit title length.
+const MAX_NAME_LENGTH: usize = 15;
+
+/// Encodes the ringtone as a Nokia Smart Messaging ringing tone (the `.ott` format).
+///
+/// The melody goes in a single pattern, which starts with the style and tempo. A scale instruction
+/// is added wherever the octave changes. This is the same bit-packed format that phones received
+/// by SMS, so it's much stricter than RTTTL: `Ringtone::fit` with `DeviceProfile::nokia` will
+/// take care of the tempo and octaves, but not the durations.
+pub(super) fn write(ringtone: &Ringtone) -> Result<Vec<u8>, OtaError> {
+    let settings = &ringtone.settings;
+
+    let name: Vec<u8> = ringtone
+        .name
+        .chars()
+        .map(|c| u8::try_from(c).map_err(|_| OtaError::InvalidName))
+        .collect::<Result<_, _>>()?;
+    if name.len() > MAX_NAME_LENGTH {
+        return Err(OtaError::NameTooLong);
+    }
+
+    let tempo = NOKIA_TEMPOS
+        .iter()
+        .position(|&tempo| tempo == settings.tempo)
+        .ok_or(OtaError::UnsupportedTempo(settings.tempo))?;
+
+    if settings.loops > LOOP_FOREVER {
+        return Err(OtaError::TooManyLoops);
+    }
+
+    // Build the instructions first, since the pattern header needs to know how many there are.
+    let mut instructions = BitWriter::default();
+    let mut count = 0;
+
+    instructions.push(STYLE, 3);
+    instructions.push(style_code(settings.style), 2);
+    instructions.push(TEMPO, 3);
+    instructions.push(tempo as u32, 5);
+    count += 2;
+
+    let mut scale = None;
+    for (i, note) in ringtone.notes.iter().enumerate() {
+        let duration = note.duration.unwrap_or(settings.duration);
+        let (code, specifier) = match (duration_code(duration), note.dotted) {
+            (Some((_, TRIPLET)), true) => return Err(OtaError::UnsupportedDuration(i)),
+            (Some((code, specifier)), false) => (code, specifier),
+            (Some((code, _)), true) => (code, DOTTED),
+            (None, _) => return Err(OtaError::UnsupportedDuration(i)),
+        };
+
+        // Rests don't need the scale.
+        if let Some(pitch) = note.pitch {
+            let octave = note.octave.unwrap_or(settings.octave);
+            if scale != Some(octave) {
+                instructions.push(SCALE, 3);
+                instructions.push(scale_code(octave)?, 2);
+                count += 1;
+                scale = Some(octave);
+            }
+
+            instructions.push(NOTE, 3);
+            instructions.push(pitch_code(pitch), 4);
+        } else {
+            instructions.push(NOTE, 3);
+            instructions.push(0, 4);
+        }
+
+        instructions.push(code, 3);
+        instructions.push(specifier, 2);
+        count += 1;
+    }
+
+    let count = u8::try_from(count).map_err(|_| OtaError::TooManyNotes)?;
+
+    let mut out = BitWriter::default();
+    out.push(COMMANDS, 8);
+    out.push(RINGING_TONE_PROGRAMMING, 7);
+    out.align();
+    out.push(SOUND, 7);
+    out.push(BASIC_SONG, 3);
+
+    out.push(name.len() as u32, 4);
+    for c in name {
+        out.push(c as u32, 8);
+    }
+
+    // A single pattern, the A part, with the loops from the ringtone.
+    out.push(1, 8);
+    out.push(PATTERN_HEADER, 3);
+    out.push(0, 2);
+    out.push(settings.loops as u32, 4);
+    out.push(count as u32, 8);
+    out.append(&instructions);
+
+    // Command end.
+    out.align();
+    out.push(0, 8);
+
+    Ok(out.bytes)
+}
+
+/// Decodes a Nokia Smart Messaging ringing tone. Anything that isn't exactly as the format
+/// describes is an error, as is anything RTTTL can't express.
+///
+/// The default duration and octave are chosen by `Ringtone::compact`. Without a tempo or style
+/// instruction, the RTTTL defaults are used.
+pub(super) fn read(data: &[u8]) -> Result<Ringtone, OtaError> {
+    let mut reader = BitReader { data, position: 0 };
+
+    if reader.read(8)? != COMMANDS
+        || reader.read(7)? != RINGING_TONE_PROGRAMMING
+        || reader.read(1)? != 0
+        || reader.read(7)? != SOUND
+    {
+        return Err(OtaError::InvalidHeader);
+    }
+
+    if reader.read(3)? != BASIC_SONG {
+        return Err(OtaError::UnsupportedSongType);
+    }
+
+    let name_length = reader.read(4)?;
+    let mut name = String::new();
+    for _ in 0..name_length {
+        // Latin-1 is the default character set, and maps directly to chars.
+        name.push(char::from(reader.read(8)? as u8));
+    }
+
+    if name.contains(':') {
+        return Err(OtaError::InvalidName);
+    }
+
+    let mut settings = Settings::default();
+    let mut notes = Vec::new();
+    // The patterns seen so far, by ID, for patterns that repeat them.
+    let mut patterns: Vec<(u32, Vec<Note>)> = Vec::new();
+    // Notes before the first scale instruction use the RTTTL default octave.
+    let mut scale = Octave::O5;
+
+    let pattern_count = reader.read(8)?;
+    for _ in 0..pattern_count {
+        if reader.read(3)? != PATTERN_HEADER {
+            return Err(OtaError::InvalidInstruction);
+        }
+
+        let id = reader.read(2)?;
+        let loops = reader.read(4)? as u8;
+        let count = reader.read(8)?;
+
+        let mut pattern = Vec::new();
+
+        // A pattern with no instructions repeats the last pattern with the same ID.
+        if count == 0 {
+            let (_, repeated) = patterns
+                .iter()
+                .rev()
+                .find(|(i, _)| *i == id)
+                .ok_or(OtaError::UndefinedPattern)?;
+            pattern.clone_from(repeated);
+        }
+
+        for _ in 0..count {
+            // Tempo and style apply to the whole ringtone, so they can only change before the
+            // first note.
+            let started = !(notes.is_empty() && pattern.is_empty());
+
+            match reader.read(3)? {
+                NOTE => {
+                    let pitch = match reader.read(4)? {
+                        0 => None,
+                        code => Some(pitch_from_code(code)?),
+                    };
+                    let duration = reader.read(3)?;
+                    let specifier = reader.read(2)?;
+                    let (duration, dotted) = duration_from_code(duration, specifier)?;
+
+                    pattern.push(Note {
+                        duration: Some(duration),
+                        pitch,
+                        octave: pitch.map(|_| scale),
+                        dotted,
+                    });
+                }
+                SCALE => {
+                    scale = match reader.read(2)? {
+                        0 => Octave::O4,
+                        1 => Octave::O5,
+                        2 => Octave::O6,
+                        _ => Octave::O7,
+                    };
+                }
+                STYLE => {
+                    let style = match reader.read(2)? {
+                        0 => Style::Natural,
+                        1 => Style::Continuous,
+                        2 => Style::Staccato,
+                        _ => return Err(OtaError::InvalidInstruction),
+                    };
+                    if started && style != settings.style {
+                        return Err(OtaError::Unrepresentable);
+                    }
+                    settings.style = style;
+                }
+                TEMPO => {
+                    let tempo = NOKIA_TEMPOS[reader.read(5)? as usize];
+                    if started && tempo != settings.tempo {
+                        return Err(OtaError::Unrepresentable);
+                    }
+                    settings.tempo = tempo;
+                }
+                VOLUME => {
+                    // RTTTL has no volume, and it doesn't change the melody.
+                    reader.read(4)?;
+                }
+                _ => return Err(OtaError::InvalidInstruction),
+            }
+        }
+
+        patterns.push((id, pattern.clone()));
+
+        // A single pattern's loops carry over to the ringtone. With more patterns, each one is
+        // repeated in place instead.
+        if pattern_count == 1 {
+            settings.loops = loops;
+        } else if loops == LOOP_FOREVER {
+            return Err(OtaError::Unrepresentable);
+        } else {
+            for _ in 0..=loops {
+                notes.extend_from_slice(&pattern);
+            }
+            continue;
+        }
+
+        notes.extend(pattern);
+    }
+
+    // The filler up to the byte boundary must be zero, followed by the command end.
+    let filler = (8 - reader.position % 8) % 8;
+    if reader.read(filler)? != 0 || reader.read(8)? != 0 {
+        return Err(OtaError::InvalidHeader);
+    }
+
+    if reader.position < data.len() * 8 {
+        return Err(OtaError::TrailingData);
+    }
+
+    if notes.is_empty() {
+        return Err(OtaError::NoNotes);
+    }
+
+    let mut ringtone = Ringtone {
+        name: if name.is_empty() {
+            UNTITLED.to_owned()
+        } else {
+            name
+        },
+        settings,
+        notes,
+    };
+    ringtone.compact();
+
+    Ok(ringtone)
+}
+
+const DOTTED: u32 = 0b01;
+const DOUBLE_DOTTED: u32 = 0b10;
+const TRIPLET: u32 = 0b11;
+
+/// The duration code and specifier for a note, or `None` if the format can't express it.
+fn duration_code(duration: Duration) -> Option<(u32, u32)> {
+    let code = match duration {
+        Duration::Whole => (0, 0),
+        Duration::Half => (1, 0),
+        Duration::HalfTriplet => (1, TRIPLET),
+        Duration::Quarter => (2, 0),
+        Duration::QuarterTriplet => (2, TRIPLET),
+        Duration::Eighth => (3, 0),
+        Duration::EighthTriplet => (3, TRIPLET),
+        Duration::Sixteenth => (4, 0),
+        Duration::SixteenthTriplet => (4, TRIPLET),
+        Duration::ThirtySecond => (5, 0),
+        Duration::ThirtySecondTriplet => (5, TRIPLET),
+        Duration::SixtyFourth | Duration::SixtyFourthTriplet => return None,
+    };
+
+    Some(code)
+}
+
+fn duration_from_code(code: u32, specifier: u32) -> Result<(Duration, bool), OtaError> {
+    // A whole note triplet is two thirds of a whole note, which RTTTL has no way to write.
+    let (plain, triplet) = match code {
+        0 => (Duration::Whole, None),
+        1 => (Duration::Half, Some(Duration::HalfTriplet)),
+        2 => (Duration::Quarter, Some(Duration::QuarterTriplet)),
+        3 => (Duration::Eighth, Some(Duration::EighthTriplet)),
+        4 => (Duration::Sixteenth, Some(Duration::SixteenthTriplet)),
+        5 => (Duration::ThirtySecond, Some(Duration::ThirtySecondTriplet)),
+        _ => return Err(OtaError::InvalidInstruction),
+    };
+
+    match specifier {
+        0 => Ok((plain, false)),
+        DOTTED => Ok((plain, true)),
+        DOUBLE_DOTTED => Err(OtaError::Unrepresentable),
+        _ => triplet
+            .map(|triplet| (triplet, false))
+            .ok_or(OtaError::Unrepresentable),
+    }
+}
+
+/// Scale 1 has A at 440Hz, which is A4.
+fn scale_code(octave: Octave) -> Result<u32, OtaError> {
+    match octave {
+        Octave::O4 => Ok(0),
+        Octave::O5 => Ok(1),
+        Octave::O6 => Ok(2),
+        Octave::O7 => Ok(3),
+        _ => Err(OtaError::UnsupportedOctave(octave)),
+    }
+}
+
+fn style_code(style: Style) -> u32 {
+    match style {
+        Style::Natural => 0,
+        Style::Continuous => 1,
+        Style::Staccato => 2,
+    }
+}
+
+/// Notes count up in semitones from C, with 0 for a pause.
+fn pitch_code(pitch: Pitch) -> u32 {
+    match pitch {
+        Pitch::C => 1,
+        Pitch::Db => 2,
+        Pitch::D => 3,
+        Pitch::Eb => 4,
+        Pitch::E => 5,
+        Pitch::F => 6,
+        Pitch::Gb => 7,
+        Pitch::G => 8,
+        Pitch::Ab => 9,
+        Pitch::A => 10,
+        Pitch::Bb => 11,
+        Pitch::B => 12,
+    }
+}
+
+fn pitch_from_code(code: u32) -> Result<Pitch, OtaError> {
+    let pitch = match code {
+        1 => Pitch::C,
+        2 => Pitch::Db,
+        3 => Pitch::D,
+        4 => Pitch::Eb,
+        5 => Pitch::E,
+        6 => Pitch::F,
+        7 => Pitch::Gb,
+        8 => Pitch::G,
+        9 => Pitch::Ab,
+        10 => Pitch::A,
+        11 => Pitch::Bb,
+        12 => Pitch::B,
+        _ => return Err(OtaError::InvalidInstruction),
+    };
+
+    Ok(pitch)
+}
+
+/// Packs values into bytes, most significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// The number of bits written.
+    len: usize,
+}
+
+impl BitWriter {
+    fn push(&mut self, value: u32, width: usize) {
+        for bit in (0..width).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+
+            if value >> bit & 1 == 1 {
+                let last = self.bytes.len() - 1;
+                self.bytes[last] |= 0x80 >> (self.len % 8);
+            }
+
+            self.len += 1;
+        }
+    }
+
+    fn append(&mut self, other: &BitWriter) {
+        for i in 0..other.len {
+            let bit = other.bytes[i / 8] >> (7 - i % 8) & 1;
+            self.push(bit as u32, 1);
+        }
+    }
+
+    /// Pads with zeros up to the next byte boundary.
+    fn align(&mut self) {
+        self.len = self.bytes.len() * 8;
+    }
+}
+
+/// The inverse of `BitWriter`.
+struct BitReader<'a> {
+    data: &'a [u8],
+    /// The number of bits read.
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, width: usize) -> Result<u32, OtaError> {
+        if self.position + width > self.data.len() * 8 {
+            return Err(OtaError::Truncated);
+        }
+
+        let mut value = 0;
+        for _ in 0..width {
+            let bit = self.data[self.position / 8] >> (7 - self.position % 8) & 1;
+            value = value << 1 | bit as u32;
+            self.position += 1;
+        }
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_ota() {
+        let ringtone =
+            Ringtone::parse("Hi:d=4,o=5,b=125,s=s:8c6,a.,12p").expect("successful parse");
+
+        let ota = write(&ringtone).expect("successful encode");
+
+        // Each instruction is on a line, with the bits from the end of the previous byte.
+        let bits = [
+            "00000010", // Two commands
+            "0100101", "0", // Ringing tone programming, filler
+            "0011101", "001", // Sound, basic song
+            "0010", "01001000", "01101001", // "Hi"
+            "00000001", // One pattern
+            "000", "00", "0000", "00000111", // A part, no loops, 7 instructions
+            "011", "10", // Staccato
+            "100", "01110", // Tempo 125
+            "010", "10", // Scale 3
+            "001", "0001", "011", "00", // 8c
+            "010", "01", // Scale 2
+            "001", "1010", "010", "01", // 4a.
+            "001", "0000", "011", "11",       // 12p
+            "000000",   // Filler
+            "00000000", // Command end
+        ]
+        .concat();
+
+        let expected: Vec<u8> = bits
+            .as_bytes()
+            .chunks(8)
+            .map(|byte| u8::from_str_radix(std::str::from_utf8(byte).unwrap(), 2).unwrap())
+            .collect();
+
+        assert_eq!(ota, expected);
+        assert_eq!(&ota[..3], [0x02, 0x4a, 0x3a]);
+    }
+
+    #[test]
+    fn test_ota_round_trip() {
+        for input in [
+            "Hi:d=4,o=5,b=125,s=s:8c6,a.,12p,b",
+            "Nokia:d=8,o=5,b=180,l=2:e6,d6,4f#,4g#,c#6,b,4d,4e,b,a,c#,4e,2a",
+            "Blank:d=16,o=7,b=25,s=n:p,c,32c4,2p.,1b",
+        ] {
+            let ringtone = Ringtone::parse(input).expect("successful parse");
+            let ota = write(&ringtone).expect("successful encode");
+
+            assert_eq!(read(&ota), Ok(ringtone), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_write_ota_errors() {
+        let write = |input| write(&Ringtone::parse(input).expect("successful parse"));
+
+        assert_eq!(
+            write("Sixteen chars!!!:d=4,o=5,b=125:c"),
+            Err(OtaError::NameTooLong)
+        );
+        assert_eq!(
+            write("Tempo:d=4,o=5,b=120:c"),
+            Err(OtaError::UnsupportedTempo(120))
+        );
+        assert_eq!(
+            write("Low:d=4,o=5,b=125:c,c3"),
+            Err(OtaError::UnsupportedOctave(Octave::O3))
+        );
+        assert_eq!(
+            write("Short:d=4,o=5,b=125:c,64c"),
+            Err(OtaError::UnsupportedDuration(1))
+        );
+        assert_eq!(
+            write("Dotted:d=4,o=5,b=125:c,12c."),
+            Err(OtaError::UnsupportedDuration(1))
+        );
+
+        let mut ringtone = Ringtone::parse("Loops:d=4,o=5,b=125:c").expect("successful parse");
+        ringtone.settings.loops = 16;
+        assert_eq!(super::write(&ringtone), Err(OtaError::TooManyLoops));
+    }
+
+    #[test]
+    fn test_read_ota_errors() {
+        let ringtone = Ringtone::parse("Hi:d=4,o=5,b=125:c,d").expect("successful parse");
+        let ota = write(&ringtone).expect("successful encode");
+
+        assert_eq!(read(&ota[..ota.len() - 1]), Err(OtaError::Truncated));
+        assert_eq!(
+            read(&[ota.as_slice(), &[0]].concat()),
+            Err(OtaError::TrailingData)
+        );
+        assert_eq!(
+            read(&[0x02, 0x4a, 0x3a, 0x80, 0]),
+            Err(OtaError::UnsupportedSongType)
+        );
+        assert_eq!(read(b"Hi:d=4:c"), Err(OtaError::InvalidHeader));
+
+        // Change the first note's duration from 010 to the unused code 110. It comes after 71
+        // bits of header, 13 bits of style and tempo, 5 bits of scale and 7 bits of note.
+        let mut bad = ota.clone();
+        let duration = 71 + 13 + 5 + 7;
+        bad[duration / 8] |= 0x80 >> (duration % 8);
+        assert_eq!(read(&bad), Err(OtaError::InvalidInstruction));
+    }
+}
@@ -1,19 +1,56 @@
-use std::{iter::Sum, str::FromStr};
+use std::{iter::Sum, ops::RangeInclusive, str::FromStr};
 
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_till1},
     character::complete::{digit1, multispace0, one_of},
-    combinator::{eof, map, map_parser, map_res, opt, recognize, value},
-    multi::separated_list1,
-    sequence::{delimited, preceded, terminated, tuple},
+    combinator::{all_consuming, cut, eof, map, map_res, opt, peek, recognize, value, verify},
+    error::{context, VerboseError},
+    multi::many0,
+    sequence::{delimited, pair, preceded, terminated, tuple},
     Finish, IResult, Parser,
 };
 
 use super::*;
 
+/// Options controlling what the parser accepts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// The octaves that may be used, in the settings or on individual notes. Anything outside this
+    /// range is rejected, even though it's valid RTTTL.
+    pub octaves: RangeInclusive<Octave>,
+
+    /// Work around the quirks of ringtones pasted from forums, rather than rejecting them. Each
+    /// problem is reported as a `ParseWarning`, and notes that can't be understood are skipped.
+    pub lenient: bool,
+}
+
+impl ParseOptions {
+    /// Only the octaves that the original Nokia phones could play.
+    pub fn nokia() -> ParseOptions {
+        ParseOptions {
+            octaves: Octave::O4..=Octave::O7,
+            ..ParseOptions::default()
+        }
+    }
+}
+
+/// By default, accept every octave that any player supports.
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            octaves: Octave::O0..=Octave::O9,
+            lenient: false,
+        }
+    }
+}
+
+/// All of our parsers keep a trace of the contexts they were in, so a failure can be reported with
+/// what was expected and where.
+type ParseResult<'a, T> = IResult<&'a str, T, VerboseError<&'a str>>;
+
 /// Generic base 10 numeric parser, used in a few places.
-fn base10_numeric<N>(input: &str) -> IResult<&str, N>
+fn base10_numeric<N>(input: &str) -> ParseResult<'_, N>
 where
     N: Sum<N> + FromStr,
 {
@@ -23,54 +60,89 @@ where
     .parse(input)
 }
 
+/// Parses a comma separated list where the first item may fail softly, but any item after a comma
+/// must be present. This makes an error inside the list point at the bad item, rather than at the
+/// comma before it.
+fn comma_list<'a, O, F>(item: F) -> impl FnMut(&'a str) -> ParseResult<'a, Vec<O>>
+where
+    F: Parser<&'a str, O, VerboseError<&'a str>> + Clone,
+{
+    map(
+        pair(item.clone(), many0(preceded(item_separator, cut(item)))),
+        |(first, mut rest)| {
+            rest.insert(0, first);
+            rest
+        },
+    )
+}
+
 /// The `:` character separating the name from the settings, and the settings from the notes.
-fn section_separator(input: &str) -> IResult<&str, ()> {
-    value((), delimited(multispace0, tag(":"), multispace0)).parse(input)
+fn section_separator(input: &str) -> ParseResult<'_, ()> {
+    context(
+        "':' separator",
+        value((), delimited(multispace0, tag(":"), multispace0)),
+    )
+    .parse(input)
 }
 
 /// The `,` character separating notes in the ringtone.
-fn item_separator(input: &str) -> IResult<&str, ()> {
+fn item_separator(input: &str) -> ParseResult<'_, ()> {
     value((), delimited(multispace0, tag(","), multispace0)).parse(input)
 }
 
 /// Freeform name before the first `:` separator.
-fn name(input: &str) -> IResult<&str, &str> {
-    take_till1(|c| c == ':').parse(input)
+fn name(input: &str) -> ParseResult<'_, &str> {
+    context("ringtone name", take_till1(|c| c == ':')).parse(input)
 }
 
-/// The numeric notation for note duration, mapped into the `Duration` enum.
-fn duration(input: &str) -> IResult<&str, Duration> {
-    map_parser(
-        digit1,
-        alt((
-            value(Duration::ThirtySecond, tag("32")),
-            value(Duration::Sixteenth, tag("16")),
-            value(Duration::Eighth, tag("8")),
-            value(Duration::Quarter, tag("4")),
-            value(Duration::Half, tag("2")),
-            value(Duration::Whole, tag("1")),
-        )),
+/// The numeric notation for note duration, mapped into the `Duration` enum. This includes the
+/// triplet durations, which are an extension to RTTTL.
+fn duration(input: &str) -> ParseResult<'_, Duration> {
+    context(
+        "duration 1/2/4/8/16/32/64 or triplet 3/6/12/24/48/96",
+        map_res(digit1, |s: &str| {
+            s.parse()
+                .ok()
+                .and_then(Duration::from_denominator)
+                .ok_or("No such duration")
+        }),
     )
     .parse(input)
 }
 
 /// The numeric notation for octave, mapped into the `Octave` enum.
-fn octave(input: &str) -> IResult<&str, Octave> {
-    map_parser(
-        digit1,
-        alt((
-            value(Octave::O4, tag("4")),
-            value(Octave::O5, tag("5")),
-            value(Octave::O6, tag("6")),
-            value(Octave::O7, tag("7")),
-        )),
+fn octave(input: &str) -> ParseResult<'_, Octave> {
+    context(
+        "octave 0-9",
+        map_res(digit1, |s: &str| {
+            s.parse()
+                .ok()
+                .and_then(Octave::from_number)
+                .ok_or("No such octave")
+        }),
     )
     .parse(input)
 }
 
+/// An octave which must also be within the range allowed by the `ParseOptions`.
+///
+/// Parsers which depend on the options are built fresh on each call, from a closure that only
+/// captures the options. This keeps them `Clone`, which `comma_list` needs.
+fn allowed_octave<'a>(
+    allowed: RangeInclusive<Octave>,
+) -> impl FnMut(&'a str) -> ParseResult<'a, Octave> + Clone {
+    move |input| {
+        context(
+            "octave within the allowed range",
+            verify(octave, |octave| allowed.contains(octave)),
+        )
+        .parse(input)
+    }
+}
+
 /// Tempo value in beats per minute.
-fn tempo(input: &str) -> IResult<&str, u16> {
-    base10_numeric.parse(input)
+fn tempo(input: &str) -> ParseResult<'_, u16> {
+    context("tempo in beats per minute", base10_numeric).parse(input)
 }
 
 /// Intermediate enum for parsing the settings section. This allows type safety while we build up
@@ -80,6 +152,8 @@ enum Setting {
     Duration(Duration),
     Octave(Octave),
     Tempo(Tempo),
+    Loops(u8),
+    Style(Style),
     Ignored,
 }
 
@@ -93,93 +167,167 @@ impl Setting {
             },
             Setting::Octave(octave) => Settings { octave, ..settings },
             Setting::Tempo(tempo) => Settings { tempo, ..settings },
+            Setting::Loops(loops) => Settings { loops, ..settings },
+            Setting::Style(style) => Settings { style, ..settings },
             Setting::Ignored => settings,
         }
     }
 }
 
-/// A single component from the settings section of the ringtone. We ignore `l=` and `s=` values
-/// because they aren't part of the standard (at least on Wikipedia), but it's convenient when
-/// pasting ringtones from other sources.
-fn setting(input: &str) -> IResult<&str, Setting> {
-    alt((
-        map(preceded(tag("d="), duration), Setting::Duration),
-        map(preceded(tag("o="), octave), Setting::Octave),
-        map(preceded(tag("b="), tempo), Setting::Tempo),
-        value(Setting::Ignored, preceded(tag("l="), base10_numeric::<u32>)),
-        value(Setting::Ignored, preceded(tag("s="), base10_numeric::<u32>)),
-    ))
+/// The RTX style, either as a letter or as the number used by the Nokia Smart Messaging format.
+/// Other numbers turn up in ringtones pasted from elsewhere, but players don't agree on what they
+/// mean, so they're accepted and ignored.
+fn style(input: &str) -> ParseResult<'_, Setting> {
+    context(
+        "style n/c/s or 0/1/2",
+        alt((
+            value(Setting::Style(Style::Natural), tag("n")),
+            value(Setting::Style(Style::Continuous), tag("c")),
+            value(Setting::Style(Style::Staccato), tag("s")),
+            map(base10_numeric::<u32>, |style| match style {
+                0 => Setting::Style(Style::Natural),
+                1 => Setting::Style(Style::Continuous),
+                2 => Setting::Style(Style::Staccato),
+                _ => Setting::Ignored,
+            }),
+        )),
+    )
     .parse(input)
 }
 
+/// A single component from the settings section of the ringtone. The `l=` and `s=` values aren't
+/// part of the standard (at least on Wikipedia), but come from RTX ringtones, and turn up when
+/// pasting ringtones from other sources.
+///
+/// Once the key is recognised, the value must be valid, so we `cut` to report the bad value rather
+/// than backtracking.
+fn setting<'a>(
+    octaves: RangeInclusive<Octave>,
+) -> impl FnMut(&'a str) -> ParseResult<'a, Setting> + Clone {
+    move |input| {
+        context(
+            "setting d=, o=, b=, l= or s=",
+            alt((
+                map(preceded(tag("d="), cut(duration)), Setting::Duration),
+                map(
+                    preceded(tag("o="), cut(allowed_octave(octaves.clone()))),
+                    Setting::Octave,
+                ),
+                map(preceded(tag("b="), cut(tempo)), Setting::Tempo),
+                map(
+                    preceded(
+                        tag("l="),
+                        cut(context(
+                            "loop count up to 15",
+                            verify(base10_numeric, |&loops| loops <= LOOP_FOREVER),
+                        )),
+                    ),
+                    Setting::Loops,
+                ),
+                preceded(tag("s="), cut(style)),
+            )),
+        )
+        .parse(input)
+    }
+}
+
 /// The settings section of the ringtone, which can be specified in any order.
-fn settings(input: &str) -> IResult<&str, Settings> {
-    map(separated_list1(item_separator, setting), |list| {
+fn settings<'a>(
+    octaves: RangeInclusive<Octave>,
+) -> impl FnMut(&'a str) -> ParseResult<'a, Settings> {
+    map(comma_list(setting(octaves)), |list| {
         list.into_iter().fold(Settings::default(), Setting::put)
     })
-    .parse(input)
 }
 
 /// Note pitch value, mapped into the `Pitch` enum. The `p` value is used for a rest. The weird
 /// mismatch here is that RTTTL specifies sharp notes with a `#` suffix, but to make our internal
 /// representation easier, we use the flat equivalent instead.
-fn pitch(input: &str) -> IResult<&str, Option<Pitch>> {
-    map_res(
-        tuple((one_of("abcdefgp"), opt(tag("#")))),
-        |pitch| match pitch {
-            ('a', None) => Ok(Some(Pitch::A)),
-            ('a', Some(_)) => Ok(Some(Pitch::Bb)),
-            ('b', None) => Ok(Some(Pitch::B)),
-            ('c', None) => Ok(Some(Pitch::C)),
-            ('c', Some(_)) => Ok(Some(Pitch::Db)),
-            ('d', None) => Ok(Some(Pitch::D)),
-            ('d', Some(_)) => Ok(Some(Pitch::Eb)),
-            ('e', None) => Ok(Some(Pitch::E)),
-            ('f', None) => Ok(Some(Pitch::F)),
-            ('f', Some(_)) => Ok(Some(Pitch::Gb)),
-            ('g', None) => Ok(Some(Pitch::G)),
-            ('g', Some(_)) => Ok(Some(Pitch::Ab)),
-            ('p', None) => Ok(None),
-            _ => Err("No such pitch"),
-        },
+fn pitch(input: &str) -> ParseResult<'_, Option<Pitch>> {
+    context(
+        "pitch a-g or p",
+        map_res(
+            tuple((one_of("abcdefgp"), opt(tag("#")))),
+            |pitch| match pitch {
+                ('a', None) => Ok(Some(Pitch::A)),
+                ('a', Some(_)) => Ok(Some(Pitch::Bb)),
+                ('b', None) => Ok(Some(Pitch::B)),
+                ('c', None) => Ok(Some(Pitch::C)),
+                ('c', Some(_)) => Ok(Some(Pitch::Db)),
+                ('d', None) => Ok(Some(Pitch::D)),
+                ('d', Some(_)) => Ok(Some(Pitch::Eb)),
+                ('e', None) => Ok(Some(Pitch::E)),
+                ('f', None) => Ok(Some(Pitch::F)),
+                ('f', Some(_)) => Ok(Some(Pitch::Gb)),
+                ('g', None) => Ok(Some(Pitch::G)),
+                ('g', Some(_)) => Ok(Some(Pitch::Ab)),
+                ('p', None) => Ok(None),
+                _ => Err("No such pitch"),
+            },
+        ),
     )
     .parse(input)
 }
 
+/// A numeric field which is optional, but must be valid if any digits are present. Without this,
+/// a bad duration like `3a` would be reported as a bad pitch.
+fn opt_numeric<'a, O, F>(parser: F) -> impl FnMut(&'a str) -> ParseResult<'a, Option<O>>
+where
+    F: Parser<&'a str, O, VerboseError<&'a str>>,
+{
+    opt(preceded(peek(digit1), cut(parser)))
+}
+
 /// A single note in the ringtone.
-fn note(input: &str) -> IResult<&str, Note> {
-    // Some ringtones place the `.` before the octave, so we allow either placement of it. The spec
-    // says it should be after the octave.
-    map(
-        tuple((
-            opt(duration),
-            pitch,
-            map(opt(tag(".")), |o| o.is_some()),
-            opt(octave),
-            map(opt(tag(".")), |o| o.is_some()),
-        )),
-        |(duration, pitch, dotted0, octave, dotted1)| Note {
-            duration,
-            pitch,
-            octave,
-            dotted: dotted0 || dotted1,
-        },
-    )
-    .parse(input)
+fn note<'a>(
+    octaves: RangeInclusive<Octave>,
+) -> impl FnMut(&'a str) -> ParseResult<'a, Note> + Clone {
+    move |input| {
+        // Some ringtones place the `.` before the octave, so we allow either placement of it. The
+        // spec says it should be after the octave.
+        map(
+            tuple((
+                opt_numeric(duration),
+                pitch,
+                map(opt(tag(".")), |o| o.is_some()),
+                opt_numeric(allowed_octave(octaves.clone())),
+                map(opt(tag(".")), |o| o.is_some()),
+            )),
+            |(duration, pitch, dotted0, octave, dotted1)| Note {
+                duration,
+                pitch,
+                octave,
+                dotted: dotted0 || dotted1,
+            },
+        )
+        .parse(input)
+    }
 }
 
 /// A list of notes in the ringtone.
-fn notes(input: &str) -> IResult<&str, Vec<Note>> {
-    separated_list1(item_separator, note).parse(input)
+fn notes<'a>(octaves: RangeInclusive<Octave>) -> impl FnMut(&'a str) -> ParseResult<'a, Vec<Note>> {
+    comma_list(note(octaves))
 }
 
 /// The entire ringtone, which consists of a name, settings, and a list of notes.
-fn ringtone(input: &str) -> IResult<&str, Ringtone> {
+fn ringtone<'a>(options: &ParseOptions) -> impl FnMut(&'a str) -> ParseResult<'a, Ringtone> {
     map(
         tuple((
-            map(terminated(name, section_separator), |s| s.to_owned()),
-            terminated(settings, section_separator),
-            terminated(notes, eof),
+            context(
+                Section::NAME,
+                map(terminated(name, section_separator), |s| s.to_owned()),
+            ),
+            context(
+                Section::SETTINGS,
+                terminated(settings(options.octaves.clone()), section_separator),
+            ),
+            context(
+                Section::NOTES,
+                terminated(
+                    notes(options.octaves.clone()),
+                    context("',' or end of ringtone", eof),
+                ),
+            ),
         )),
         |(name, settings, notes)| Ringtone {
             name,
@@ -187,12 +335,31 @@ fn ringtone(input: &str) -> IResult<&str, Ringtone> {
             notes,
         },
     )
-    .parse(input)
+}
+
+/// Parses a single item from the settings section on its own, and applies it to the settings.
+/// Used by the lenient parser, which splits up the sections itself.
+pub(super) fn parse_setting(
+    input: &str,
+    settings: Settings,
+    octaves: RangeInclusive<Octave>,
+) -> Option<Settings> {
+    let (_rest, setting) = all_consuming(setting(octaves)).parse(input).ok()?;
+    Some(Setting::put(settings, setting))
+}
+
+/// Parses a single note on its own. Used by the lenient parser, which splits up the notes itself.
+pub(super) fn parse_note(input: &str, octaves: RangeInclusive<Octave>) -> Option<Note> {
+    let (_rest, note) = all_consuming(note(octaves)).parse(input).ok()?;
+    Some(note)
 }
 
 ///  Runs the parser on the input and returns the parsed ringtone.
-pub(super) fn parse_input(input: &str) -> Result<Ringtone, Box<dyn std::error::Error>> {
-    let (_rest, ringtone) = ringtone.parse(input).map_err(|e| e.to_owned()).finish()?;
+pub(super) fn parse_input(input: &str, options: &ParseOptions) -> Result<Ringtone, RtttlError> {
+    let (_rest, ringtone) = ringtone(options)
+        .parse(input)
+        .finish()
+        .map_err(|e| RtttlError::from_verbose(input, e))?;
     Ok(ringtone)
 }
 
@@ -200,6 +367,14 @@ pub(super) fn parse_input(input: &str) -> Result<Ringtone, Box<dyn std::error::E
 mod tests {
     use super::*;
 
+    fn all() -> RangeInclusive<Octave> {
+        ParseOptions::default().octaves
+    }
+
+    fn parse_input(input: &str) -> Result<Ringtone, RtttlError> {
+        super::parse_input(input, &ParseOptions::default())
+    }
+
     #[test]
     fn test_parse_name() {
         let input = "Ringtone! Hi!:";
@@ -212,13 +387,14 @@ mod tests {
         let input = "d=4,o=7,b=108";
 
         assert_eq!(
-            settings(input),
+            settings(all())(input),
             Ok((
                 "",
                 Settings {
                     duration: Duration::Quarter,
                     octave: Octave::O7,
                     tempo: 108,
+                    ..Settings::default()
                 }
             ))
         );
@@ -226,13 +402,14 @@ mod tests {
         let input = "d=16";
 
         assert_eq!(
-            settings(input),
+            settings(all())(input),
             Ok((
                 "",
                 Settings {
                     duration: Duration::Sixteenth,
                     octave: Octave::O5,
                     tempo: 120,
+                    ..Settings::default()
                 }
             ))
         );
@@ -240,13 +417,14 @@ mod tests {
         let input = "o=4";
 
         assert_eq!(
-            settings(input),
+            settings(all())(input),
             Ok((
                 "",
                 Settings {
                     duration: Duration::Quarter,
                     octave: Octave::O4,
                     tempo: 120,
+                    ..Settings::default()
                 }
             ))
         );
@@ -255,7 +433,7 @@ mod tests {
     #[test]
     fn test_parse_note() {
         assert_eq!(
-            note("2a4"),
+            note(all())("2a4"),
             Ok((
                 "",
                 Note {
@@ -268,7 +446,7 @@ mod tests {
         );
 
         assert_eq!(
-            note("2e"),
+            note(all())("2e"),
             Ok((
                 "",
                 Note {
@@ -281,7 +459,7 @@ mod tests {
         );
 
         assert_eq!(
-            note("2d#"),
+            note(all())("2d#"),
             Ok((
                 "",
                 Note {
@@ -294,7 +472,7 @@ mod tests {
         );
 
         assert_eq!(
-            note("32a#4"),
+            note(all())("32a#4"),
             Ok((
                 "",
                 Note {
@@ -307,7 +485,7 @@ mod tests {
         );
 
         assert_eq!(
-            note("8c#7."),
+            note(all())("8c#7."),
             Ok((
                 "",
                 Note {
@@ -320,7 +498,7 @@ mod tests {
         );
 
         assert_eq!(
-            note("8c#.7"),
+            note(all())("8c#.7"),
             Ok((
                 "",
                 Note {
@@ -333,7 +511,7 @@ mod tests {
         );
 
         assert_eq!(
-            note("g"),
+            note(all())("g"),
             Ok((
                 "",
                 Note {
@@ -346,7 +524,7 @@ mod tests {
         );
 
         assert_eq!(
-            note("4p."),
+            note(all())("4p."),
             Ok((
                 "",
                 Note {
@@ -371,10 +549,109 @@ mod tests {
             Settings {
                 duration: Duration::Quarter,
                 octave: Octave::O5,
-                tempo: 120
+                tempo: 120,
+                loops: 5,
+                style: Style::Continuous,
             }
         );
 
         assert_eq!(ringtone.notes.len(), 14);
     }
+
+    #[test]
+    fn test_parse_error() {
+        let input = "Nokia: d=4,o=5,b=120: 16e6, 16d6,\n8f#, 8x, 16c#6";
+
+        let err = parse_input(input).expect_err("failed parse");
+
+        assert_eq!(err.offset(), 40);
+        assert_eq!(err.line(), 2);
+        assert_eq!(err.column(), 7);
+        assert_eq!(err.section(), Some(Section::Notes));
+        assert_eq!(err.expected(), Some("pitch a-g or p"));
+        assert_eq!(err.snippet(), "8f#, 8x, 16c#6\n      ^");
+
+        let err = parse_input("Nokia: d=4,o=5,b=120: 16e6, 5d6").expect_err("failed parse");
+        assert_eq!(err.column(), 29);
+        assert_eq!(
+            err.expected(),
+            Some("duration 1/2/4/8/16/32/64 or triplet 3/6/12/24/48/96")
+        );
+
+        let err = parse_input("Nokia: d=4,o=10,b=120: 16e6").expect_err("failed parse");
+        assert_eq!(err.column(), 14);
+        assert_eq!(err.section(), Some(Section::Settings));
+        assert_eq!(err.expected(), Some("octave 0-9"));
+
+        let err = parse_input("Nokia: d=4,o=5,b=120: 16e6, 16d6,").expect_err("failed parse");
+        assert_eq!(err.column(), 34);
+        assert_eq!(err.expected(), Some("pitch a-g or p"));
+
+        let err = parse_input("Nokia: d=4,o=5,b=120: 16e6 16d6").expect_err("failed parse");
+        assert_eq!(err.column(), 27);
+        assert_eq!(err.expected(), Some("',' or end of ringtone"));
+
+        let err = parse_input("Nokia d=4,o=5,b=120").expect_err("failed parse");
+        assert_eq!(err.section(), Some(Section::Name));
+        assert_eq!(err.expected(), Some("':' separator"));
+    }
+
+    #[test]
+    fn test_parse_examples() {
+        for input in [
+            include_str!("../../examples/nokia.txt"),
+            include_str!("../../examples/badinerie.txt"),
+        ] {
+            parse_input(input.trim()).expect("successful parse");
+        }
+    }
+
+    #[test]
+    fn test_parse_octave_range() {
+        let input = "Wide: d=4,o=3,b=120: 8c8, 8p, 2a0, 4g9";
+
+        let ringtone = parse_input(input).expect("successful parse");
+        assert_eq!(ringtone.settings.octave, Octave::O3);
+        assert_eq!(ringtone.notes[0].octave, Some(Octave::O8));
+        assert_eq!(ringtone.notes[2].octave, Some(Octave::O0));
+        assert_eq!(ringtone.notes[3].octave, Some(Octave::O9));
+
+        let nokia = ParseOptions::nokia();
+
+        let err = super::parse_input(input, &nokia).expect_err("failed parse");
+        assert_eq!(err.column(), 13);
+        assert_eq!(err.section(), Some(Section::Settings));
+        assert_eq!(err.expected(), Some("octave within the allowed range"));
+
+        let err =
+            super::parse_input("Nokia: d=4,o=5,b=120: 8c8", &nokia).expect_err("failed parse");
+        assert_eq!(err.column(), 25);
+        assert_eq!(err.section(), Some(Section::Notes));
+        assert_eq!(err.expected(), Some("octave within the allowed range"));
+
+        super::parse_input("Nokia: d=4,o=7,b=120: 8c4, 8c7", &nokia).expect("successful parse");
+    }
+
+    #[test]
+    fn test_parse_rtx_settings() {
+        let parse_settings = |input| settings(all())(input).map(|(_, settings)| settings);
+
+        assert_eq!(parse_settings("l=15").map(|s| s.loops), Ok(15));
+        assert!(parse_settings("l=16").is_err());
+
+        for (input, style) in [
+            ("s=n", Style::Natural),
+            ("s=0", Style::Natural),
+            ("s=c", Style::Continuous),
+            ("s=1", Style::Continuous),
+            ("s=s", Style::Staccato),
+            ("s=2", Style::Staccato),
+            ("s=s,s=4", Style::Staccato),
+        ] {
+            assert_eq!(parse_settings(input).map(|s| s.style), Ok(style), "{input}");
+        }
+
+        assert!(parse_settings("l=256").is_err());
+        assert!(parse_settings("s=x").is_err());
+    }
 }
@@ -0,0 +1,197 @@
+use std::fmt;
+
+use super::*;
+
+/// Writes the ringtone in canonical RTTTL form, e.g. `Nokia:d=4,o=5,b=120:16e6,16d6,8f#`.
+///
+/// The standard settings are written out explicitly, and the RTX `l=` and `s=` settings only when
+/// they aren't the default. Notes only include the fields that they override, so the output parses
+/// back to the same ringtone.
+impl fmt::Display for Ringtone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:", self.name, self.settings)?;
+
+        for (i, note) in self.notes.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{note}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "d={},o={},b={}", self.duration, self.octave, self.tempo)?;
+
+        let defaults = Settings::default();
+
+        if self.loops != defaults.loops {
+            write!(f, ",l={}", self.loops)?;
+        }
+
+        if self.style != defaults.style {
+            write!(f, ",s={}", self.style)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Style::Natural => f.write_str("n"),
+            Style::Continuous => f.write_str("c"),
+            Style::Staccato => f.write_str("s"),
+        }
+    }
+}
+
+/// A single note, with the `.` after the octave as the spec places it.
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(duration) = self.duration {
+            write!(f, "{duration}")?;
+        }
+
+        match self.pitch {
+            Some(pitch) => write!(f, "{pitch}")?,
+            None => f.write_str("p")?,
+        }
+
+        if let Some(octave) = self.octave {
+            write!(f, "{octave}")?;
+        }
+
+        if self.dotted {
+            f.write_str(".")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", *self as u8)
+    }
+}
+
+impl fmt::Display for Octave {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.number())
+    }
+}
+
+/// The inverse of the parser's mapping: we store flats, but RTTTL only has sharps.
+impl fmt::Display for Pitch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pitch = match self {
+            Pitch::A => "a",
+            Pitch::Bb => "a#",
+            Pitch::B => "b",
+            Pitch::C => "c",
+            Pitch::Db => "c#",
+            Pitch::D => "d",
+            Pitch::Eb => "d#",
+            Pitch::E => "e",
+            Pitch::F => "f",
+            Pitch::Gb => "f#",
+            Pitch::G => "g",
+            Pitch::Ab => "g#",
+        };
+
+        f.write_str(pitch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize() {
+        let input =
+            "Nokia: d=4,o=5,b=120,l=5,s=4: 16e6, 16d6, 8f#, 8g#, 16c#6, 16b, 8d, 8e, 8c#.6, 4p.";
+
+        let ringtone = Ringtone::parse(input).expect("successful parse");
+
+        assert_eq!(
+            ringtone.to_rtttl(),
+            "Nokia:d=4,o=5,b=120,l=5:16e6,16d6,8f#,8g#,16c#6,16b,8d,8e,8c#6.,4p."
+        );
+
+        let ringtone = Ringtone::parse("Style:d=4,o=5,b=120,s=2:a").expect("successful parse");
+        assert_eq!(ringtone.to_rtttl(), "Style:d=4,o=5,b=120,s=s:a");
+    }
+
+    #[test]
+    fn test_round_trip_examples() {
+        for input in [
+            include_str!("../../examples/nokia.txt"),
+            include_str!("../../examples/badinerie.txt"),
+        ] {
+            let ringtone = Ringtone::parse(input.trim()).expect("successful parse");
+            let reparsed = Ringtone::parse(&ringtone.to_rtttl()).expect("successful reparse");
+
+            assert_eq!(ringtone, reparsed);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_notes() {
+        let durations =
+            std::iter::once(None).chain((1..=96).filter_map(Duration::from_denominator).map(Some));
+        let pitches = [
+            None,
+            Some(Pitch::A),
+            Some(Pitch::Bb),
+            Some(Pitch::B),
+            Some(Pitch::C),
+            Some(Pitch::Db),
+            Some(Pitch::D),
+            Some(Pitch::Eb),
+            Some(Pitch::E),
+            Some(Pitch::F),
+            Some(Pitch::Gb),
+            Some(Pitch::G),
+            Some(Pitch::Ab),
+        ];
+        let octaves = std::iter::once(None).chain((0..=9).map(Octave::from_number));
+
+        let mut notes = Vec::new();
+        for duration in durations {
+            for pitch in pitches {
+                for octave in octaves.clone() {
+                    for dotted in [false, true] {
+                        notes.push(Note {
+                            duration,
+                            pitch,
+                            octave,
+                            dotted,
+                        });
+                    }
+                }
+            }
+        }
+
+        let ringtone = Ringtone {
+            name: "Everything".to_owned(),
+            settings: Settings {
+                duration: Duration::Eighth,
+                octave: Octave::O6,
+                tempo: 63,
+                loops: 2,
+                style: Style::Natural,
+            },
+            notes,
+        };
+
+        let reparsed = Ringtone::parse(&ringtone.to_rtttl()).expect("successful parse");
+
+        assert_eq!(ringtone, reparsed);
+    }
+}
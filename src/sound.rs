@@ -1,9 +1,17 @@
-use rodio::{source::Zero, OutputStream, Sink, Source};
+use rodio::cpal::traits::HostTrait;
+use rodio::{cpal, source::Zero, DeviceTrait, OutputStream, Sink, Source};
 
-use crate::synth::{ADSROptions, BandPassFilter, SawWave};
+use crate::synth::Patch;
+
+/// The sample rate to use when the output device doesn't say what it prefers.
+const DEFAULT_SAMPLE_RATE: u32 = 48_000;
 
 pub struct OutputContext {
     sink: Sink,
+    patch: Patch,
+
+    // Notes are generated at the device's own sample rate, so they don't need resampling.
+    sample_rate: u32,
 
     // Maintained to stop it being swept away. The `rodio` library uses `Arc` and `Weak` internally
     // to manage the lifetime of the stream, and we need to stop the stream from being dropped
@@ -14,19 +22,16 @@ pub struct OutputContext {
 
 impl OutputContext {
     pub fn play(&self, freq: f32, secs: f32) {
+        let patch = &self.patch;
+
         // Fundamental wave.
-        let saw = SawWave::new(freq);
+        let wave = patch.waveform.oscillator(freq, self.sample_rate);
 
-        // Through trial and error, three filters were found to be the sweet spot for reproducing
-        // the sound of the early Nokia phones.
-        let filtered = BandPassFilter::new(saw, 1.5, 3500.0);
-        let filtered = BandPassFilter::new(filtered, 1.5, 3200.0);
-        let filtered = BandPassFilter::new(filtered, 1.5, 2800.0);
+        // Shape the sound, e.g. like the phone's speaker and case.
+        let filtered = patch.filters.apply(wave);
 
-        // Declick the result with a short ADSR envelope.
-        let source = ADSROptions::new(0.0, 0.0, 1.0, 0.005)
-            .envelope(filtered, secs)
-            .amplify(0.20);
+        // Shape the volume of the note with its envelope.
+        let source = patch.envelope.envelope(filtered, secs).amplify(patch.gain);
 
         // Buffer the note to be played.
         self.sink.append(source);
@@ -34,8 +39,8 @@ impl OutputContext {
 
     pub fn silence(&self, secs: f32) {
         // All zero samples is a silent note.
-        let source =
-            Zero::<f32>::new(1, 48_000).take_duration(std::time::Duration::from_secs_f32(secs));
+        let source = Zero::<f32>::new(1, self.sample_rate)
+            .take_duration(std::time::Duration::from_secs_f32(secs));
 
         self.sink.append(source);
     }
@@ -46,9 +51,28 @@ impl OutputContext {
     }
 }
 
-pub fn output() -> OutputContext {
-    let (stream, stream_handle) = OutputStream::try_default().expect("opening output device");
+/// Opens the default output device, to play notes with the patch at the device's sample rate.
+pub fn output(patch: Patch) -> OutputContext {
+    let device = cpal::default_host().default_output_device();
+    let config = device
+        .as_ref()
+        .and_then(|device| device.default_output_config().ok());
+
+    // If the default device won't tell us its config, let `rodio` find one that works.
+    let (stream, stream_handle) = match (&device, &config) {
+        (Some(device), Some(config)) => {
+            OutputStream::try_from_device_config(device, config.clone())
+        }
+        _ => OutputStream::try_default(),
+    }
+    .expect("opening output device");
     let sink = Sink::try_new(&stream_handle).unwrap();
+    let sample_rate = config.map_or(DEFAULT_SAMPLE_RATE, |config| config.sample_rate().0);
 
-    OutputContext { sink, stream }
+    OutputContext {
+        sink,
+        patch,
+        sample_rate,
+        stream,
+    }
 }
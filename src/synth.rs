@@ -1,8 +1,9 @@
 mod envelope;
 mod filter;
 mod oscillator;
+mod patch;
 
 pub use envelope::ADSROptions;
-pub use envelope::ADSR;
-pub use filter::BandPassFilter;
-pub use oscillator::SawWave;
+pub use filter::{Biquad, BiquadType, Coefficients, FilterChain};
+pub use oscillator::Waveform;
+pub use patch::{Patch, PatchError};
@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 use rodio::Source;
+use serde::{Deserialize, Serialize};
 
 /// Options for an ADSR envelope.
 ///
@@ -8,6 +9,8 @@ use rodio::Source;
 //
 // Confession time, I only added this to remove the tiny click when a note turns off. Full linear
 // ADSR implementation to make a 5ms fade-out on each note. Total overkill.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ADSROptions {
     attack: f32,
     decay: f32,
@@ -25,6 +28,26 @@ impl ADSROptions {
         }
     }
 
+    /// The attack time, in seconds.
+    pub fn attack(&self) -> f32 {
+        self.attack
+    }
+
+    /// The decay time, in seconds.
+    pub fn decay(&self) -> f32 {
+        self.decay
+    }
+
+    /// The sustain level, from 0.0 to 1.0.
+    pub fn sustain(&self) -> f32 {
+        self.sustain
+    }
+
+    /// The release time, in seconds.
+    pub fn release(&self) -> f32 {
+        self.release
+    }
+
     pub fn envelope<S: Source + Iterator<Item = f32>>(
         &self,
         source: S,
@@ -39,9 +62,10 @@ impl ADSROptions {
         // Contrary to most ADSR envelope implementations, the release is considered part of the
         // note duration. This makes it easier to handle because ordinarily the envelope is a
         // function based on the input, but in our case our input is the total duration of the note.
+        let decay_end = attack.saturating_add(decay);
         let sustain_duration = ((note_duration * sample_rate) as u32)
-            .checked_sub(attack + decay + release)
-            .unwrap_or(0);
+            .saturating_sub(decay_end.saturating_add(release));
+        let sustain_end = decay_end.saturating_add(sustain_duration);
 
         let sustain = self.sustain;
 
@@ -49,15 +73,16 @@ impl ADSROptions {
             source,
             index: 0,
             attack_end: attack,
-            decay_end: attack + decay,
+            decay_end,
             sustain,
-            sustain_end: attack + decay + sustain_duration,
-            release_end: attack + decay + sustain_duration + release,
+            sustain_end,
+            release_end: sustain_end.saturating_add(release),
         }
     }
 }
 
 /// An ADSR envelope, bound to a source note.
+#[allow(clippy::upper_case_acronyms)]
 pub struct ADSR<S: Source + Iterator<Item = f32>> {
     source: S,
     index: u32,
@@ -1,26 +1,176 @@
+use std::f32::consts::PI;
+
 use rodio::Source;
+use serde::{Deserialize, Serialize};
 
-/// Band pass filter implementation based on the Audio EQ Cookbook at:
+/// The filter types from the Audio EQ Cookbook at:
 ///
 /// https://www.w3.org/TR/audio-eq-cookbook/
-/// See: "BPF (constant skirt gain, peak gain = Q)"
 ///
-/// The choice of filter algorithm is based on the sound of the early Nokia devices. Due to the
-/// hardware limitations and the construction of the handset, there was a natural resonance and
-/// filtering inherent in the physical device. This filter attempts to replicate that sound.
+/// Each filter has a frequency in Hz and a Q, which sets how wide the band is, how sharp the
+/// corner, or how steep the shelf. The peaking and shelf filters also have a gain in dB.
+///
+/// In patch files, the type is given in kebab case, e.g. `type = "band-pass-skirt"`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum BiquadType {
+    /// Passes the frequencies below `freq`. "LPF"
+    LowPass { freq: f32, q: f32 },
+    /// Passes the frequencies above `freq`. "HPF"
+    HighPass { freq: f32, q: f32 },
+    /// Passes a band around `freq`, peaking at a gain of Q. "BPF (constant skirt gain, peak gain
+    /// = Q)"
+    BandPassSkirt { freq: f32, q: f32 },
+    /// Passes a band around `freq`, peaking at 0 dB. "BPF (constant 0 dB peak gain)"
+    BandPassPeak { freq: f32, q: f32 },
+    /// Cuts a band around `freq`. "notch"
+    Notch { freq: f32, q: f32 },
+    /// Passes all frequencies, shifting the phase around `freq`. "APF"
+    AllPass { freq: f32, q: f32 },
+    /// Boosts or cuts a band around `freq` by the gain. "peakingEQ"
+    Peaking { freq: f32, q: f32, gain_db: f32 },
+    /// Boosts or cuts the frequencies below `freq` by the gain. "lowShelf"
+    LowShelf { freq: f32, q: f32, gain_db: f32 },
+    /// Boosts or cuts the frequencies above `freq` by the gain. "highShelf"
+    HighShelf { freq: f32, q: f32, gain_db: f32 },
+}
+
+/// The highest filter frequency, as a fraction of the sample rate. The filters only work below the
+/// Nyquist frequency, half the sample rate, and become unstable above it.
+const MAX_FREQ: f32 = 0.49;
+
+/// The coefficients of a biquad filter, normalised so that `a0` is 1.0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Coefficients {
+    pub b0: f32,
+    pub b1: f32,
+    pub b2: f32,
+    pub a1: f32,
+    pub a2: f32,
+}
+
+impl BiquadType {
+    /// The frequency, Q and gain in dB, which is 0.0 for the filters that don't have one.
+    pub fn parameters(&self) -> (f32, f32, f32) {
+        match *self {
+            BiquadType::LowPass { freq, q }
+            | BiquadType::HighPass { freq, q }
+            | BiquadType::BandPassSkirt { freq, q }
+            | BiquadType::BandPassPeak { freq, q }
+            | BiquadType::Notch { freq, q }
+            | BiquadType::AllPass { freq, q } => (freq, q, 0.0),
+            BiquadType::Peaking { freq, q, gain_db }
+            | BiquadType::LowShelf { freq, q, gain_db }
+            | BiquadType::HighShelf { freq, q, gain_db } => (freq, q, gain_db),
+        }
+    }
+
+    /// The filter coefficients at the sample rate. Filter math lies herein.
+    ///
+    /// Frequencies at or above the Nyquist frequency are lowered to just below it, so that a patch
+    /// made for a higher sample rate still plays, if a little duller.
+    pub fn coefficients(&self, sample_rate: u32) -> Coefficients {
+        let (freq, q, gain_db) = self.parameters();
+        let freq = freq.min(MAX_FREQ * sample_rate as f32);
+
+        let w0 = 2.0 * PI * freq / sample_rate as f32;
+        let cosw = w0.cos();
+        let sinw = w0.sin();
+        let alpha = sinw / (2.0 * q);
+        let a = 10f32.powf(gain_db / 40.0);
+
+        // The cookbook's coefficients, in the order b0, b1, b2, a0, a1, a2.
+        let [b0, b1, b2, a0, a1, a2] = match self {
+            BiquadType::LowPass { .. } => [
+                (1.0 - cosw) / 2.0,
+                1.0 - cosw,
+                (1.0 - cosw) / 2.0,
+                1.0 + alpha,
+                -2.0 * cosw,
+                1.0 - alpha,
+            ],
+            BiquadType::HighPass { .. } => [
+                (1.0 + cosw) / 2.0,
+                -(1.0 + cosw),
+                (1.0 + cosw) / 2.0,
+                1.0 + alpha,
+                -2.0 * cosw,
+                1.0 - alpha,
+            ],
+            BiquadType::BandPassSkirt { .. } => [
+                sinw / 2.0,
+                0.0,
+                -sinw / 2.0,
+                1.0 + alpha,
+                -2.0 * cosw,
+                1.0 - alpha,
+            ],
+            BiquadType::BandPassPeak { .. } => {
+                [alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cosw, 1.0 - alpha]
+            }
+            BiquadType::Notch { .. } => {
+                [1.0, -2.0 * cosw, 1.0, 1.0 + alpha, -2.0 * cosw, 1.0 - alpha]
+            }
+            BiquadType::AllPass { .. } => [
+                1.0 - alpha,
+                -2.0 * cosw,
+                1.0 + alpha,
+                1.0 + alpha,
+                -2.0 * cosw,
+                1.0 - alpha,
+            ],
+            BiquadType::Peaking { .. } => [
+                1.0 + alpha * a,
+                -2.0 * cosw,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cosw,
+                1.0 - alpha / a,
+            ],
+            BiquadType::LowShelf { .. } => {
+                let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+                [
+                    a * ((a + 1.0) - (a - 1.0) * cosw + sqrt_a_alpha),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cosw),
+                    a * ((a + 1.0) - (a - 1.0) * cosw - sqrt_a_alpha),
+                    (a + 1.0) + (a - 1.0) * cosw + sqrt_a_alpha,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cosw),
+                    (a + 1.0) + (a - 1.0) * cosw - sqrt_a_alpha,
+                ]
+            }
+            BiquadType::HighShelf { .. } => {
+                let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+                [
+                    a * ((a + 1.0) + (a - 1.0) * cosw + sqrt_a_alpha),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cosw),
+                    a * ((a + 1.0) + (a - 1.0) * cosw - sqrt_a_alpha),
+                    (a + 1.0) - (a - 1.0) * cosw + sqrt_a_alpha,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cosw),
+                    (a + 1.0) - (a - 1.0) * cosw - sqrt_a_alpha,
+                ]
+            }
+        };
+
+        Coefficients {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+}
+
+/// Biquad filter, of any of the types in the Audio EQ Cookbook. See `BiquadType`.
 ///
 /// For more information on the filter design and coefficients, please refer to the Audio EQ
-/// Cookbook linked above.
-pub struct BandPassFilter<S>
+/// Cookbook linked there.
+pub struct Biquad<S>
 where
     S: Source<Item = f32>,
 {
     source: S,
-    b0: f32,
-    b1: f32,
-    b2: f32,
-    a1: f32,
-    a2: f32,
+    coefficients: Coefficients,
 
     x_n1: f32,
     x_n2: f32,
@@ -28,61 +178,33 @@ where
     y_n2: f32,
 }
 
-impl<S> BandPassFilter<S>
+impl<S> Biquad<S>
 where
     S: Source<Item = f32>,
 {
-    pub fn new(s: S, q: f32, fc: f32) -> BandPassFilter<S> {
-        // Filter math lies herein.
-        let fs = s.sample_rate() as f32;
-        let wc = std::f32::consts::PI * 2.0 * fc / fs;
+    pub fn new(s: S, filter: BiquadType) -> Biquad<S> {
+        let coefficients = filter.coefficients(s.sample_rate());
 
-        let cosw = wc.cos();
-        let sinw = wc.sin();
-
-        let alpha = sinw / (2.0 * q);
-
-        let a0 = 1.0 + alpha;
-
-        let b0 = sinw / (2.0 * a0);
-        let b1 = 0.0;
-        let b2 = sinw / (-2.0 * a0);
-        let a1 = -2.0 * cosw / a0;
-        let a2 = (1.0 - alpha) / a0;
-
-        let x_n1 = 0.0;
-        let x_n2 = 0.0;
-        let y_n1 = 0.0;
-        let y_n2 = 0.0;
-
-        BandPassFilter {
+        Biquad {
             source: s,
-            b0,
-            b1,
-            b2,
-            a1,
-            a2,
-            x_n1,
-            x_n2,
-            y_n1,
-            y_n2,
+            coefficients,
+            x_n1: 0.0,
+            x_n2: 0.0,
+            y_n1: 0.0,
+            y_n2: 0.0,
         }
     }
 }
 
-impl<S> Iterator for BandPassFilter<S>
+impl<S> Iterator for Biquad<S>
 where
     S: Source<Item = f32>,
 {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let &mut BandPassFilter {
-            b0,
-            b1,
-            b2,
-            a1,
-            a2,
+        let &mut Biquad {
+            coefficients: Coefficients { b0, b1, b2, a1, a2 },
             ref mut x_n1,
             ref mut x_n2,
             ref mut y_n1,
@@ -103,7 +225,7 @@ where
     }
 }
 
-impl<S> Source for BandPassFilter<S>
+impl<S> Source for Biquad<S>
 where
     S: Source<Item = f32>,
 {
@@ -123,3 +245,269 @@ where
         self.source.total_duration()
     }
 }
+
+/// A series of biquad filters, applied in order.
+///
+/// ```
+/// use rtttlsynth::{BiquadType, FilterChain};
+///
+/// let chain = FilterChain::new()
+///     .filter(BiquadType::HighPass { freq: 200.0, q: 0.7 })
+///     .filter(BiquadType::LowPass { freq: 4000.0, q: 0.7 });
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FilterChain {
+    filters: Vec<BiquadType>,
+}
+
+impl FilterChain {
+    /// An empty chain, which passes the sound through unchanged.
+    pub fn new() -> Self {
+        FilterChain::default()
+    }
+
+    /// Adds a filter to the end of the chain.
+    pub fn filter(mut self, filter: BiquadType) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// The filters, in the order they're applied.
+    pub fn filters(&self) -> &[BiquadType] {
+        &self.filters
+    }
+
+    /// Runs the source through each filter in the chain.
+    pub fn apply<S>(&self, source: S) -> Box<dyn Source<Item = f32> + Send>
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        self.filters
+            .iter()
+            .fold(Box::new(source), |source, &filter| {
+                Box::new(Biquad::new(source, filter))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// A complex number, as (real, imaginary).
+    type Complex = (f64, f64);
+
+    fn mul((a, b): Complex, (c, d): Complex) -> Complex {
+        (a * c - b * d, a * d + b * c)
+    }
+
+    fn abs((a, b): Complex) -> f64 {
+        a.hypot(b)
+    }
+
+    /// The magnitude of the digital filter's response at the frequency, from its coefficients.
+    fn digital_magnitude(coefficients: &Coefficients, freq: f64) -> f64 {
+        let w = 2.0 * std::f64::consts::PI * freq / SAMPLE_RATE as f64;
+        // z^-1 and z^-2.
+        let z1 = ((-w).cos(), (-w).sin());
+        let z2 = mul(z1, z1);
+
+        let polynomial = |c0: f32, c1: f32, c2: f32| {
+            let (c0, c1, c2) = (c0 as f64, c1 as f64, c2 as f64);
+            (c0 + c1 * z1.0 + c2 * z2.0, c1 * z1.1 + c2 * z2.1)
+        };
+        let Coefficients { b0, b1, b2, a1, a2 } = *coefficients;
+
+        abs(polynomial(b0, b1, b2)) / abs(polynomial(1.0, a1, a2))
+    }
+
+    /// The magnitude of the analog prototype's response from the cookbook, at the frequency. The
+    /// bilinear transform maps the frequency to `s = j tan(w/2) / tan(w0/2)`, so the digital
+    /// filter should match it exactly.
+    fn analog_magnitude(filter: BiquadType, freq: f64) -> f64 {
+        let (f0, q, gain_db) = match filter {
+            BiquadType::LowPass { freq, q }
+            | BiquadType::HighPass { freq, q }
+            | BiquadType::BandPassSkirt { freq, q }
+            | BiquadType::BandPassPeak { freq, q }
+            | BiquadType::Notch { freq, q }
+            | BiquadType::AllPass { freq, q } => (freq, q, 0.0),
+            BiquadType::Peaking { freq, q, gain_db }
+            | BiquadType::LowShelf { freq, q, gain_db }
+            | BiquadType::HighShelf { freq, q, gain_db } => (freq, q, gain_db),
+        };
+        let (f0, q) = (f0 as f64, q as f64);
+        let a = 10f64.powf(gain_db as f64 / 40.0);
+        let nyquist = SAMPLE_RATE as f64 / 2.0;
+        let warp = |f: f64| (std::f64::consts::FRAC_PI_2 * f / nyquist).tan();
+
+        // s = jw, so s^2 = -w^2.
+        let w = warp(freq) / warp(f0);
+        let s = (0.0, w);
+        let s2 = (-w * w, 0.0);
+        let quadratic = |c2: f64, c1: f64, c0: f64| (c2 * s2.0 + c0, c1 * s.1);
+        let ratio = |numerator, denominator| abs(numerator) / abs(denominator);
+
+        match filter {
+            BiquadType::LowPass { .. } => {
+                ratio(quadratic(0.0, 0.0, 1.0), quadratic(1.0, 1.0 / q, 1.0))
+            }
+            BiquadType::HighPass { .. } => {
+                ratio(quadratic(1.0, 0.0, 0.0), quadratic(1.0, 1.0 / q, 1.0))
+            }
+            BiquadType::BandPassSkirt { .. } => {
+                ratio(quadratic(0.0, 1.0, 0.0), quadratic(1.0, 1.0 / q, 1.0))
+            }
+            BiquadType::BandPassPeak { .. } => {
+                ratio(quadratic(0.0, 1.0 / q, 0.0), quadratic(1.0, 1.0 / q, 1.0))
+            }
+            BiquadType::Notch { .. } => {
+                ratio(quadratic(1.0, 0.0, 1.0), quadratic(1.0, 1.0 / q, 1.0))
+            }
+            BiquadType::AllPass { .. } => {
+                ratio(quadratic(1.0, -1.0 / q, 1.0), quadratic(1.0, 1.0 / q, 1.0))
+            }
+            BiquadType::Peaking { .. } => ratio(
+                quadratic(1.0, a / q, 1.0),
+                quadratic(1.0, 1.0 / (a * q), 1.0),
+            ),
+            BiquadType::LowShelf { .. } => {
+                let numerator = quadratic(1.0, a.sqrt() / q, a);
+                let denominator = quadratic(a, a.sqrt() / q, 1.0);
+                a * ratio(numerator, denominator)
+            }
+            BiquadType::HighShelf { .. } => {
+                let numerator = quadratic(a, a.sqrt() / q, 1.0);
+                let denominator = quadratic(1.0, a.sqrt() / q, a);
+                a * ratio(numerator, denominator)
+            }
+        }
+    }
+
+    #[test]
+    fn test_magnitude_response() {
+        let filters = [
+            BiquadType::LowPass {
+                freq: 1000.0,
+                q: 0.707,
+            },
+            BiquadType::HighPass {
+                freq: 1000.0,
+                q: 0.707,
+            },
+            BiquadType::BandPassSkirt {
+                freq: 3500.0,
+                q: 1.5,
+            },
+            BiquadType::BandPassPeak {
+                freq: 3500.0,
+                q: 1.5,
+            },
+            BiquadType::Notch {
+                freq: 2000.0,
+                q: 4.0,
+            },
+            BiquadType::AllPass {
+                freq: 2000.0,
+                q: 1.0,
+            },
+            BiquadType::Peaking {
+                freq: 1500.0,
+                q: 2.0,
+                gain_db: 6.0,
+            },
+            BiquadType::Peaking {
+                freq: 1500.0,
+                q: 2.0,
+                gain_db: -12.0,
+            },
+            BiquadType::LowShelf {
+                freq: 300.0,
+                q: 0.707,
+                gain_db: 9.0,
+            },
+            BiquadType::HighShelf {
+                freq: 5000.0,
+                q: 0.707,
+                gain_db: -6.0,
+            },
+        ];
+
+        for filter in filters {
+            let coefficients = filter.coefficients(SAMPLE_RATE);
+
+            for freq in [
+                50.0, 300.0, 1000.0, 2000.0, 3500.0, 8000.0, 15000.0, 22000.0,
+            ] {
+                let digital = digital_magnitude(&coefficients, freq);
+                let analog = analog_magnitude(filter, freq);
+
+                // Within 0.01 dB, or close to silent at the bottom of the notch.
+                let close = if analog < 1e-3 {
+                    digital < 1e-3
+                } else {
+                    (20.0 * (digital / analog).log10()).abs() < 0.01
+                };
+                assert!(close, "{filter:?} at {freq} Hz: {digital} vs {analog}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_above_nyquist() {
+        // 15 kHz is fine at 48 kHz, but above the Nyquist frequency at 22.05 kHz.
+        for filter in [
+            BiquadType::LowPass {
+                freq: 15000.0,
+                q: 1.5,
+            },
+            BiquadType::BandPassSkirt {
+                freq: 15000.0,
+                q: 1.5,
+            },
+            BiquadType::Notch {
+                freq: 15000.0,
+                q: 1.5,
+            },
+            BiquadType::HighShelf {
+                freq: 15000.0,
+                q: 1.5,
+                gain_db: 6.0,
+            },
+        ] {
+            // The poles are inside the unit circle, so the filter is stable.
+            let Coefficients { a1, a2, .. } = filter.coefficients(22050);
+            assert!(a2.abs() < 1.0 && a1.abs() < 1.0 + a2, "{filter:?}");
+
+            let sine = rodio::source::SineWave::new(1000.0);
+            let filtered = Biquad::new(sine, filter);
+            assert!(filtered.take(22050).all(f32::is_finite), "{filter:?}");
+        }
+    }
+
+    #[test]
+    fn test_filter_chain() {
+        // A 1 kHz sine through two low-pass filters at 1 kHz, each -3 dB there with this Q.
+        let sine = rodio::source::SineWave::new(1000.0);
+        let filter = BiquadType::LowPass {
+            freq: 1000.0,
+            q: std::f32::consts::FRAC_1_SQRT_2,
+        };
+        let chain = FilterChain::new().filter(filter).filter(filter);
+        assert_eq!(chain.filters(), [filter, filter]);
+
+        let filtered = chain.apply(sine);
+        let sample_rate = filtered.sample_rate();
+
+        // Skip a while for the filters to settle, then measure the peak.
+        let peak = filtered
+            .skip(sample_rate as usize / 10)
+            .take(sample_rate as usize / 10)
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+
+        assert!((peak - 0.5).abs() < 0.01, "peak {peak}");
+    }
+}
@@ -1,7 +1,112 @@
+use std::fmt;
+use std::str::FromStr;
+
 use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+/// The primitive waveform that notes are played with.
+///
+/// https://en.wikipedia.org/wiki/Waveform
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Waveform {
+    /// Bright and buzzy. Through the phone filters, this sounds most like the early Nokias.
+    #[default]
+    Saw,
+    /// Hollow, like most buzzers and the simplest phone tone generators.
+    Square,
+    /// A square wave that's high for this fraction of each cycle. Narrow pulses sound thinner, as
+    /// on the NES and other sound chips.
+    Pulse { duty: f32 },
+    /// Soft, with only odd harmonics that fall away quickly.
+    Triangle,
+    /// A pure tone, with no harmonics.
+    Sine,
+    /// Noise from a linear feedback shift register, which gets brighter as the note gets higher.
+    Noise,
+}
+
+impl Waveform {
+    /// An oscillator for this waveform, playing the frequency at the sample rate.
+    pub fn oscillator(self, freq: f32, sample_rate: u32) -> Box<dyn Source<Item = f32> + Send> {
+        match self {
+            Waveform::Saw => Box::new(SawWave::new(freq, sample_rate)),
+            Waveform::Square => Box::new(SquareWave::new(freq, sample_rate)),
+            Waveform::Pulse { duty } => Box::new(PulseWave::new(freq, duty, sample_rate)),
+            Waveform::Triangle => Box::new(TriangleWave::new(freq, sample_rate)),
+            Waveform::Sine => Box::new(SineWave::new(freq, sample_rate)),
+            Waveform::Noise => Box::new(NoiseWave::new(freq, sample_rate)),
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Waveform::Saw => f.write_str("saw"),
+            Waveform::Square => f.write_str("square"),
+            Waveform::Pulse { duty } => write!(f, "pulse:{duty}"),
+            Waveform::Triangle => f.write_str("triangle"),
+            Waveform::Sine => f.write_str("sine"),
+            Waveform::Noise => f.write_str("noise"),
+        }
+    }
+}
+
+// Waveforms are saved by name, as in `pulse:0.125`.
+impl From<Waveform> for String {
+    fn from(waveform: Waveform) -> Self {
+        waveform.to_string()
+    }
+}
+
+impl TryFrom<String> for Waveform {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
+/// Parses the waveform's name, e.g. `square`. A pulse wave can give its duty cycle, as in
+/// `pulse:0.125`, and is otherwise a quarter.
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let waveform = match s {
+            "saw" => Waveform::Saw,
+            "square" => Waveform::Square,
+            "pulse" => Waveform::Pulse { duty: 0.25 },
+            "triangle" => Waveform::Triangle,
+            "sine" => Waveform::Sine,
+            "noise" => Waveform::Noise,
+            _ => {
+                let duty = s
+                    .strip_prefix("pulse:")
+                    .and_then(|duty| duty.parse().ok())
+                    .filter(|duty| (0.0..=1.0).contains(duty));
+
+                match duty {
+                    Some(duty) => Waveform::Pulse { duty },
+                    None => {
+                        return Err(format!(
+                            "unknown waveform {s:?}, expected one of: saw, square, pulse, \
+                             pulse:<duty>, triangle, sine, noise"
+                        ))
+                    }
+                }
+            }
+        };
+
+        Ok(waveform)
+    }
+}
 
 /// Simple saw wave oscillator. One of the primitive waveforms.
 ///
+/// The jump at the end of each cycle is smoothed with PolyBLEP, so that high notes don't alias.
+///
 /// https://en.wikipedia.org/wiki/Waveform
 #[derive(Clone, Debug)]
 pub struct SawWave {
@@ -9,18 +114,19 @@ pub struct SawWave {
     freq: f32,
     mult: f32,
     num_sample: usize,
+    sample_rate: u32,
 }
 
 impl SawWave {
-    pub fn new(freq: f32) -> Self {
-        // At our fixed sample rate, we need to go through the range of [-1.0, 1.0] for each cycle
-        // of the wave. So, we double it in the multiplier to go from [0.0, 1.0] to [0.0, 2.0], and
-        // then subtract 1.0 below to get the range we want.
-        let mult = 2.0 * freq / 48000.0;
+    pub fn new(freq: f32, sample_rate: u32) -> Self {
+        // The multiplier takes us through [0.0, 1.0] once per cycle at the sample rate. We double
+        // that below to go to [0.0, 2.0], and then subtract 1.0 to get the range we want.
+        let mult = freq / sample_rate as f32;
         SawWave {
             freq,
             mult,
             num_sample: 0,
+            sample_rate,
         }
     }
 }
@@ -32,28 +138,397 @@ impl Iterator for SawWave {
     fn next(&mut self) -> Option<Self::Item> {
         self.num_sample = self.num_sample.wrapping_add(1);
         // Linear increase at each step, and then using the remainder to wrap around.
-        Some((self.mult * self.num_sample as f32).rem_euclid(2.0) - 1.0)
+        let phase = (self.mult * self.num_sample as f32).rem_euclid(1.0);
+        Some(2.0 * phase - 1.0 - poly_blep(phase, self.mult))
+    }
+}
+
+/// Square wave oscillator, high for the first half of each cycle and low for the second.
+#[derive(Clone, Debug)]
+pub struct SquareWave {
+    pulse: PulseWave,
+}
+
+impl SquareWave {
+    pub fn new(freq: f32, sample_rate: u32) -> Self {
+        SquareWave {
+            pulse: PulseWave::new(freq, 0.5, sample_rate),
+        }
     }
 }
 
-impl Source for SawWave {
+impl Iterator for SquareWave {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pulse.next()
+    }
+}
+
+// The square wave is a pulse wave underneath.
+impl Source for SquareWave {
     #[inline]
     fn current_frame_len(&self) -> Option<usize> {
-        None
+        self.pulse.current_frame_len()
     }
 
     #[inline]
     fn channels(&self) -> u16 {
-        1
+        self.pulse.channels()
     }
 
     #[inline]
     fn sample_rate(&self) -> u32 {
-        48000
+        self.pulse.sample_rate()
     }
 
     #[inline]
     fn total_duration(&self) -> Option<std::time::Duration> {
-        None
+        self.pulse.total_duration()
+    }
+}
+
+/// Pulse wave oscillator, high for the duty cycle fraction of each cycle and low for the rest.
+///
+/// Both edges are smoothed with PolyBLEP, so that high notes don't alias.
+#[derive(Clone, Debug)]
+pub struct PulseWave {
+    duty: f32,
+    mult: f32,
+    num_sample: usize,
+    sample_rate: u32,
+}
+
+impl PulseWave {
+    pub fn new(freq: f32, duty: f32, sample_rate: u32) -> Self {
+        // The multiplier takes us through [0.0, 1.0] once per cycle, so the duty cycle is where
+        // the wave drops.
+        PulseWave {
+            duty: duty.clamp(0.0, 1.0),
+            mult: freq / sample_rate as f32,
+            num_sample: 0,
+            sample_rate,
+        }
+    }
+}
+
+impl Iterator for PulseWave {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.num_sample = self.num_sample.wrapping_add(1);
+        let phase = (self.mult * self.num_sample as f32).rem_euclid(1.0);
+        let naive = if phase < self.duty { 1.0 } else { -1.0 };
+
+        // The rising edge is at the start of the cycle, and the falling edge is at the duty
+        // cycle, so its phase is measured from there.
+        let falling = (phase - self.duty).rem_euclid(1.0);
+        Some(naive + poly_blep(phase, self.mult) - poly_blep(falling, self.mult))
+    }
+}
+
+/// Triangle wave oscillator, rising from -1.0 to 1.0 over the first half of each cycle and falling
+/// back over the second.
+#[derive(Clone, Debug)]
+pub struct TriangleWave {
+    mult: f32,
+    num_sample: usize,
+    sample_rate: u32,
+}
+
+impl TriangleWave {
+    pub fn new(freq: f32, sample_rate: u32) -> Self {
+        TriangleWave {
+            mult: freq / sample_rate as f32,
+            num_sample: 0,
+            sample_rate,
+        }
+    }
+}
+
+impl Iterator for TriangleWave {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.num_sample = self.num_sample.wrapping_add(1);
+        // Fold the phase in half, so it goes up to the peak and back down.
+        let phase = (self.mult * self.num_sample as f32).rem_euclid(1.0);
+        Some(1.0 - 4.0 * (phase - 0.5).abs())
+    }
+}
+
+/// Sine wave oscillator.
+#[derive(Clone, Debug)]
+pub struct SineWave {
+    mult: f32,
+    num_sample: usize,
+    sample_rate: u32,
+}
+
+impl SineWave {
+    pub fn new(freq: f32, sample_rate: u32) -> Self {
+        SineWave {
+            mult: freq / sample_rate as f32,
+            num_sample: 0,
+            sample_rate,
+        }
+    }
+}
+
+impl Iterator for SineWave {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.num_sample = self.num_sample.wrapping_add(1);
+        // Wrap the phase before scaling it, so the sine doesn't lose precision as it grows.
+        let phase = (self.mult * self.num_sample as f32).rem_euclid(1.0);
+        Some((std::f32::consts::TAU * phase).sin())
+    }
+}
+
+/// Noise oscillator, using a 15-bit linear feedback shift register like the noise channels of
+/// 8-bit sound chips.
+///
+/// The register is clocked at 16 times the note's frequency, so higher notes give brighter noise.
+/// Clocked at the sample rate or above, it's white noise.
+///
+/// https://en.wikipedia.org/wiki/Linear-feedback_shift_register
+#[derive(Clone, Debug)]
+pub struct NoiseWave {
+    mult: f32,
+    phase: f32,
+    register: u16,
+    sample_rate: u32,
+}
+
+impl NoiseWave {
+    pub fn new(freq: f32, sample_rate: u32) -> Self {
+        NoiseWave {
+            mult: 16.0 * freq / sample_rate as f32,
+            phase: 0.0,
+            // Any non-zero seed works. All zeros would never change.
+            register: 1,
+            sample_rate,
+        }
+    }
+}
+
+impl Iterator for NoiseWave {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        // Clock the register as many times as it ticked over since the last sample.
+        self.phase += self.mult;
+        while self.phase >= 1.0 {
+            self.phase -= 1.0;
+
+            // The new top bit is the XOR of the bottom two, which gives the longest sequence, of
+            // 32767 steps before it repeats.
+            let feedback = (self.register ^ (self.register >> 1)) & 1;
+            self.register = (self.register >> 1) | (feedback << 14);
+        }
+
+        Some(if self.register & 1 == 1 { 1.0 } else { -1.0 })
+    }
+}
+
+/// The PolyBLEP correction for a wave that jumps down by 2.0 at phase 0.0, where the phase goes
+/// up by `step` each sample.
+///
+/// Jumps in a wave have harmonics far above the Nyquist frequency, which fold back down as
+/// aliasing. Subtracting this polynomial from the samples either side of the jump smooths it into
+/// an approximation of a band-limited step, which removes most of the aliasing.
+///
+/// https://www.martin-finke.de/articles/audio-plugins-018-polyblep-oscillator/
+fn poly_blep(phase: f32, step: f32) -> f32 {
+    if phase < step {
+        // Just after the jump.
+        let t = phase / step;
+        2.0 * t - t * t - 1.0
+    } else if phase > 1.0 - step {
+        // Just before the jump.
+        let t = (phase - 1.0) / step;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Implements `Source` for an endless mono oscillator at its sample rate.
+macro_rules! oscillator_source {
+    ($($oscillator:ty),*) => {
+        $(
+            impl Source for $oscillator {
+                #[inline]
+                fn current_frame_len(&self) -> Option<usize> {
+                    None
+                }
+
+                #[inline]
+                fn channels(&self) -> u16 {
+                    1
+                }
+
+                #[inline]
+                fn sample_rate(&self) -> u32 {
+                    self.sample_rate
+                }
+
+                #[inline]
+                fn total_duration(&self) -> Option<std::time::Duration> {
+                    None
+                }
+            }
+        )*
+    };
+}
+
+oscillator_source!(SawWave, PulseWave, TriangleWave, SineWave, NoiseWave);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_waveforms() {
+        // At 480 Hz, each cycle is exactly 100 samples.
+        let cycle = |waveform: Waveform| -> Vec<f32> {
+            waveform.oscillator(480.0, 48000).take(100).collect()
+        };
+
+        for waveform in [
+            Waveform::Saw,
+            Waveform::Square,
+            Waveform::Pulse { duty: 0.125 },
+            Waveform::Triangle,
+            Waveform::Sine,
+            Waveform::Noise,
+        ] {
+            let samples = cycle(waveform);
+            assert!(
+                samples.iter().all(|s| (-1.0..=1.0).contains(s)),
+                "{waveform} out of range"
+            );
+        }
+
+        let high = |samples: Vec<f32>| samples.iter().filter(|&&s| s > 0.0).count();
+        // The last sample of the cycle is the start of the next one, which is on the rising edge
+        // and so smoothed to 0.0.
+        assert_eq!(high(cycle(Waveform::Square)), 49);
+        assert_eq!(high(cycle(Waveform::Pulse { duty: 0.125 })), 12);
+
+        let sine = cycle(Waveform::Sine);
+        assert!((sine[24] - 1.0).abs() < 1e-3);
+        assert!((sine[74] + 1.0).abs() < 1e-3);
+
+        let triangle = cycle(Waveform::Triangle);
+        assert!((triangle[49] - 1.0).abs() < 1e-3);
+        assert!((triangle[99] + 1.0).abs() < 1e-3);
+
+        // At other sample rates, the cycle is still the right length.
+        for waveform in [Waveform::Saw, Waveform::Square, Waveform::Sine] {
+            for sample_rate in [44100, 22050] {
+                let oscillator = waveform.oscillator(441.0, sample_rate);
+                assert_eq!(oscillator.sample_rate(), sample_rate);
+
+                let samples: Vec<f32> = oscillator.take(sample_rate as usize).collect();
+                let cycle = sample_rate as usize / 441;
+                let rising = samples.windows(2).filter(|w| w[0] <= 0.0 && w[1] > 0.0);
+                // The square wave starts high, so its first rising edge is before the first sample.
+                let rising = rising.count();
+                assert!((440..=441).contains(&rising), "{waveform} at {sample_rate}");
+                assert!((samples[cycle / 4 - 1] - samples[cycle + cycle / 4 - 1]).abs() < 1e-3);
+            }
+        }
+
+        // The noise is roughly balanced between high and low.
+        let noise: Vec<f32> = Waveform::Noise
+            .oscillator(3000.0, 48000)
+            .take(48000)
+            .collect();
+        assert!((20000..28000).contains(&high(noise)));
+    }
+
+    /// The fraction of the energy in the signal that isn't at a multiple of the frequency, which
+    /// at these frequencies is harmonics above the Nyquist frequency that have folded back down.
+    fn alias_energy(samples: &[f32], freq: f32) -> f32 {
+        // With 4800 samples, the bins of the DFT are 10 Hz apart, so the harmonics each land in
+        // a single bin.
+        assert_eq!(samples.len(), 4800);
+        let harmonic_bin = (freq / 10.0) as usize;
+        let len = samples.len();
+        let (cos, sin): (Vec<f64>, Vec<f64>) = (0..len)
+            .map(|n| (std::f64::consts::TAU * n as f64 / len as f64).sin_cos())
+            .map(|(sin, cos)| (cos, sin))
+            .unzip();
+
+        let mut total = 0.0;
+        let mut alias = 0.0;
+
+        for bin in 1..len / 2 {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (n, &sample) in samples.iter().enumerate() {
+                re += sample as f64 * cos[bin * n % len];
+                im -= sample as f64 * sin[bin * n % len];
+            }
+
+            let energy = re * re + im * im;
+            total += energy;
+            if bin % harmonic_bin != 0 {
+                alias += energy;
+            }
+        }
+
+        (alias / total) as f32
+    }
+
+    #[test]
+    fn test_aliasing() {
+        // Around the top of o=7, where the harmonics of a naive wave fold back into the audible
+        // range.
+        let freq = 3520.0;
+        let mult = freq / 48000.0;
+        let naive_phase = |n: usize| (mult * (n + 1) as f32).rem_euclid(1.0);
+
+        let naive_saw: Vec<f32> = (0..4800).map(|n| 2.0 * naive_phase(n) - 1.0).collect();
+        let saw: Vec<f32> = SawWave::new(freq, 48000).take(4800).collect();
+
+        let naive_square: Vec<f32> = (0..4800)
+            .map(|n| if naive_phase(n) < 0.5 { 1.0 } else { -1.0 })
+            .collect();
+        let square: Vec<f32> = SquareWave::new(freq, 48000).take(4800).collect();
+
+        let naive_pulse: Vec<f32> = (0..4800)
+            .map(|n| if naive_phase(n) < 0.25 { 1.0 } else { -1.0 })
+            .collect();
+        let pulse: Vec<f32> = PulseWave::new(freq, 0.25, 48000).take(4800).collect();
+
+        for (name, naive, band_limited) in [
+            ("saw", naive_saw, saw),
+            ("square", naive_square, square),
+            ("pulse", naive_pulse, pulse),
+        ] {
+            let naive = alias_energy(&naive, freq);
+            let band_limited = alias_energy(&band_limited, freq);
+            assert!(band_limited < 0.01, "{name} aliases: {band_limited}");
+            assert!(
+                band_limited < naive / 10.0,
+                "{name} aliases: {band_limited} vs {naive}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_waveform() {
+        assert_eq!("square".parse(), Ok(Waveform::Square));
+        assert_eq!("pulse".parse(), Ok(Waveform::Pulse { duty: 0.25 }));
+        assert_eq!("pulse:0.125".parse(), Ok(Waveform::Pulse { duty: 0.125 }));
+        assert!("pulse:2".parse::<Waveform>().is_err());
+        assert!("organ".parse::<Waveform>().is_err());
     }
 }
@@ -0,0 +1,311 @@
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ADSROptions, BiquadType, FilterChain, Waveform};
+
+/// The sample rate that filter frequencies are checked against. Filters only work below half of it,
+/// the Nyquist frequency.
+const MIN_SAMPLE_RATE: f32 = 44100.0;
+
+/// The longest attack, decay or release, in seconds.
+const MAX_ENVELOPE_TIME: f32 = 60.0;
+
+/// The most that peaking and shelf filters can boost or cut, in dB.
+const MAX_GAIN_DB: f32 = 60.0;
+
+/// An instrument: everything about how a note sounds, from the oscillator through the filters and
+/// envelope to the volume.
+///
+/// Patches can be saved to and loaded from TOML files, so voices can be tuned without
+/// recompiling. The default patch is the sound of the early Nokia phones, and looks like this:
+///
+/// ```toml
+/// waveform = "saw"
+/// gain = 0.2
+///
+/// [envelope]
+/// attack = 0.0
+/// decay = 0.0
+/// sustain = 1.0
+/// release = 0.005
+///
+/// [[filters]]
+/// type = "band-pass-skirt"
+/// freq = 3500.0
+/// q = 1.5
+/// ```
+///
+/// with two more band-pass filters at 3200 Hz and 2800 Hz. See `BiquadType` for the other filter
+/// types, which are named in the same way.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Patch {
+    /// The oscillator's waveform, named as for `--waveform`, e.g. `"pulse:0.125"`.
+    pub waveform: Waveform,
+    /// How much to scale the samples by at the end, to set the volume.
+    pub gain: f32,
+    /// The envelope of each note, in seconds apart from the sustain level.
+    pub envelope: ADSROptions,
+    /// The filters that the oscillator is run through, in order.
+    pub filters: FilterChain,
+}
+
+impl Default for Patch {
+    fn default() -> Self {
+        // Due to the hardware limitations and the construction of the handset, there was a
+        // natural resonance and filtering inherent in the physical device. Through trial and
+        // error, three band-pass filters were found to be the sweet spot for reproducing it.
+        let filters = [3500.0, 3200.0, 2800.0]
+            .into_iter()
+            .fold(FilterChain::new(), |chain, freq| {
+                chain.filter(BiquadType::BandPassSkirt { freq, q: 1.5 })
+            });
+
+        Patch {
+            waveform: Waveform::Saw,
+            gain: 0.20,
+            // Only there to declick the end of each note.
+            envelope: ADSROptions::new(0.0, 0.0, 1.0, 0.005),
+            filters,
+        }
+    }
+}
+
+impl Patch {
+    /// Reads a patch from TOML, and checks it with `validate`.
+    pub fn from_toml(input: &str) -> Result<Patch, PatchError> {
+        let patch: Patch = toml::from_str(input).map_err(|e| PatchError::Toml(e.to_string()))?;
+        patch.validate()?;
+
+        Ok(patch)
+    }
+
+    /// Checks that the patch makes a usable sound, rather than silence, noise or NaNs, naming the
+    /// first field that doesn't.
+    ///
+    /// The filter frequencies must be below the Nyquist frequency at 44.1 kHz. On a device with a
+    /// lower sample rate, such as 22.05 kHz, any filters above its Nyquist frequency are lowered to
+    /// just below it when they play, so the patch may sound duller there.
+    pub fn validate(&self) -> Result<(), PatchError> {
+        let check = |valid: bool, field: &str, expected: &str, value: f32| {
+            if valid {
+                Ok(())
+            } else {
+                Err(PatchError::Invalid(format!(
+                    "{field} must be {expected}, not {value}"
+                )))
+            }
+        };
+
+        let gain = self.gain;
+        check(gain.is_finite() && gain >= 0.0, "gain", "at least 0", gain)?;
+
+        let envelope = &self.envelope;
+        for (field, time) in [
+            ("envelope.attack", envelope.attack()),
+            ("envelope.decay", envelope.decay()),
+            ("envelope.release", envelope.release()),
+        ] {
+            let valid = (0.0..=MAX_ENVELOPE_TIME).contains(&time);
+            check(valid, field, "from 0 to 60 seconds", time)?;
+        }
+        let sustain = envelope.sustain();
+        check(
+            (0.0..=1.0).contains(&sustain),
+            "envelope.sustain",
+            "from 0 to 1",
+            sustain,
+        )?;
+
+        let nyquist = MIN_SAMPLE_RATE / 2.0;
+        for (i, filter) in self.filters.filters().iter().enumerate() {
+            let (freq, q, gain_db) = filter.parameters();
+
+            let valid = freq > 0.0 && freq < nyquist;
+            check(
+                valid,
+                &format!("filters[{i}].freq"),
+                "above 0 and below 22050 Hz",
+                freq,
+            )?;
+            check(
+                q.is_finite() && q > 0.0,
+                &format!("filters[{i}].q"),
+                "above 0",
+                q,
+            )?;
+
+            let valid = (-MAX_GAIN_DB..=MAX_GAIN_DB).contains(&gain_db);
+            check(
+                valid,
+                &format!("filters[{i}].gain_db"),
+                "from -60 to 60 dB",
+                gain_db,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the patch as TOML.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("patches can always be written as TOML")
+    }
+
+    /// Loads a patch from a TOML file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Patch, PatchError> {
+        let input = std::fs::read_to_string(path).map_err(PatchError::Io)?;
+
+        Patch::from_toml(&input)
+    }
+
+    /// Saves the patch to a TOML file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), PatchError> {
+        std::fs::write(path, self.to_toml()).map_err(PatchError::Io)
+    }
+}
+
+/// A patch that couldn't be loaded or saved.
+#[derive(Debug)]
+pub enum PatchError {
+    /// The file couldn't be read or written.
+    Io(std::io::Error),
+    /// The file isn't a valid patch, with a message saying where and why.
+    Toml(String),
+    /// The patch wouldn't make a usable sound, with a message naming the bad field.
+    Invalid(String),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::Io(e) => write!(f, "can't access patch file: {e}"),
+            PatchError::Toml(message) | PatchError::Invalid(message) => {
+                write!(f, "invalid patch: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_toml() {
+        let patch = Patch::default();
+        assert_eq!(
+            Patch::from_toml(&patch.to_toml()).expect("valid patch"),
+            patch
+        );
+
+        // The example stays in step with the default.
+        let example = Patch::from_toml(include_str!("../../examples/nokia.toml"));
+        assert_eq!(example.expect("valid patch"), patch);
+
+        let patch = Patch::from_toml(
+            r#"
+            waveform = "pulse:0.125"
+            gain = 0.5
+
+            [envelope]
+            attack = 0.01
+            decay = 0.1
+            sustain = 0.6
+            release = 0.05
+
+            [[filters]]
+            type = "high-pass"
+            freq = 200.0
+            q = 0.7
+
+            [[filters]]
+            type = "high-shelf"
+            freq = 4000.0
+            q = 0.7
+            gain_db = -6.0
+            "#,
+        )
+        .expect("valid patch");
+
+        assert_eq!(
+            patch,
+            Patch {
+                waveform: Waveform::Pulse { duty: 0.125 },
+                gain: 0.5,
+                envelope: ADSROptions::new(0.01, 0.1, 0.6, 0.05),
+                filters: FilterChain::new()
+                    .filter(BiquadType::HighPass {
+                        freq: 200.0,
+                        q: 0.7
+                    })
+                    .filter(BiquadType::HighShelf {
+                        freq: 4000.0,
+                        q: 0.7,
+                        gain_db: -6.0,
+                    }),
+            }
+        );
+    }
+
+    #[test]
+    fn test_patch_errors() {
+        let error = |input: &str| match Patch::from_toml(input) {
+            Err(PatchError::Toml(message)) => message,
+            result => panic!("expected an error, got {result:?}"),
+        };
+
+        let valid = Patch::default().to_toml();
+        assert!(error(&valid.replace("\"saw\"", "\"organ\"")).contains("unknown waveform"));
+        assert!(error(&valid.replace("band-pass-skirt", "comb")).contains("unknown variant"));
+        assert!(error(&format!("volume = 1.0\n{valid}")).contains("unknown field"));
+        assert!(error("waveform = \"saw\"").contains("missing field"));
+
+        // Values that would make silence, noise or NaNs.
+        let invalid = |from: &str, to: &str| match Patch::from_toml(&valid.replace(from, to)) {
+            Err(PatchError::Invalid(message)) => message,
+            result => panic!("expected an error, got {result:?}"),
+        };
+
+        assert_eq!(
+            invalid("gain = 0.2", "gain = nan"),
+            "gain must be at least 0, not NaN"
+        );
+        assert_eq!(
+            invalid("gain = 0.2", "gain = -1.0"),
+            "gain must be at least 0, not -1"
+        );
+        assert_eq!(
+            invalid("attack = 0.0", "attack = 1e9"),
+            "envelope.attack must be from 0 to 60 seconds, not 1000000000"
+        );
+        assert_eq!(
+            invalid("sustain = 1.0", "sustain = 1.5"),
+            "envelope.sustain must be from 0 to 1, not 1.5"
+        );
+        assert_eq!(
+            invalid("freq = 2800.0", "freq = 24000.0"),
+            "filters[2].freq must be above 0 and below 22050 Hz, not 24000"
+        );
+        assert_eq!(
+            invalid("q = 1.5", "q = 0.0"),
+            "filters[0].q must be above 0, not 0"
+        );
+        let peaking = Patch {
+            filters: FilterChain::new().filter(BiquadType::Peaking {
+                freq: 1000.0,
+                q: 1.0,
+                gain_db: 100.0,
+            }),
+            ..Patch::default()
+        };
+        assert_eq!(
+            peaking.validate().map_err(|e| e.to_string()),
+            Err("invalid patch: filters[0].gain_db must be from -60 to 60 dB, not 100".to_owned())
+        );
+    }
+}